@@ -0,0 +1,92 @@
+//! Conversion throughput, reported as records per second.
+//!
+//! `process_packet` runs over fixed in-memory packets, so its numbers do not depend on
+//! tshark; the end-to-end case converts the golden-test fixture and is skipped when
+//! `tshark` is not on `PATH`. Run with `cargo bench`.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use pcap_to_parquet::{convert, process_packet, ConverterConfig};
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Command;
+
+type Packet = HashMap<String, HashMap<String, String>>;
+
+fn layer(fields: &[(&str, &str)]) -> HashMap<String, String> {
+    fields.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+fn packet(frame_number: u32, endpoint: &str, direction: &str, capdata: &str) -> Packet {
+    let frame_number = frame_number.to_string();
+    let timestamp = format!("{:.6}", frame_number.parse::<f64>().unwrap() * 0.001);
+    HashMap::from([
+        (
+            "frame".to_string(),
+            layer(&[("frame.number", &frame_number), ("frame.time_relative", &timestamp), ("frame.len", "128")]),
+        ),
+        (
+            "usb".to_string(),
+            layer(&[
+                ("usb.device_address", "16"),
+                ("usb.bus_id", "1"),
+                ("usb.endpoint_address", endpoint),
+                ("usb.endpoint_address.direction", direction),
+                ("usb.transfer_type", "0x03"),
+                ("usb.urb_type", "'C'"),
+                ("usb.urb_status", "0"),
+                ("usb.urb_id", "0xffff888101234500"),
+                ("usb.capdata", capdata),
+            ]),
+        ),
+    ])
+}
+
+/// A GetData/PutData ADC exchange, a PD status poll and an empty IN completion, repeated
+fn sample_packets() -> Vec<Packet> {
+    const ADC_PUT_DATA: &str = "41:0a:82:02:01:00:00:0b:5c:0f:00:00:fa:ff:ff:ff:a0:0f:00:00:f2:ff:ff:ff:04:10:00:00:50:00:00:00:7e:0d:7b:7e:d4:04:71:01:42:01:83:7e:00:80:78:00:25:00:21:00";
+    let templates = [
+        ("0x01", "0", "0c:0a:02:00"),
+        ("0x81", "1", ADC_PUT_DATA),
+        ("0x01", "0", "0c:0b:20:00"),
+        ("0x81", "1", ""),
+    ];
+    (0..1000u32)
+        .map(|i| {
+            let (endpoint, direction, capdata) = templates[i as usize % templates.len()];
+            packet(i + 1, endpoint, direction, capdata)
+        })
+        .collect()
+}
+
+fn bench_process_packet(c: &mut Criterion) {
+    let packets = sample_packets();
+    let mut group = c.benchmark_group("process_packet");
+    group.throughput(Throughput::Elements(packets.len() as u64));
+    group.bench_function("mixed_km003c", |b| {
+        b.iter(|| {
+            for packet in &packets {
+                std::hint::black_box(process_packet(packet, "bench", false).unwrap());
+            }
+        })
+    });
+    group.finish();
+}
+
+fn bench_end_to_end(c: &mut Criterion) {
+    if !Command::new("tshark").arg("--version").output().is_ok_and(|o| o.status.success()) {
+        eprintln!("tshark not found; skipping end-to-end conversion benchmark");
+        return;
+    }
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/km003c_adc.pcapng");
+    let dir = tempfile::tempdir().unwrap();
+    // Every iteration replaces the previous iteration's output
+    let config = ConverterConfig::new(fixture).output(dir.path().join("bench.parquet")).device_address(5).overwrite(true);
+
+    let mut group = c.benchmark_group("convert");
+    group.sample_size(10);
+    group.bench_function("km003c_adc_fixture", |b| b.iter(|| convert(&config).unwrap()));
+    group.finish();
+}
+
+criterion_group!(benches, bench_process_packet, bench_end_to_end);
+criterion_main!(benches);
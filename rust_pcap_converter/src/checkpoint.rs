@@ -0,0 +1,153 @@
+use polars::prelude::*;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use crate::record::{create_dataframe, UsbPacketRecord};
+use crate::Result;
+
+/// On-disk progress of an interrupted conversion (`--batch-size` / `--resume`).
+///
+/// Each flushed batch is written as its own Parquet file under `<output>.partial/`, and
+/// `<output>.checkpoint` holds the last frame number and the number of batches covering
+/// it. Batch files and the sidecar are both written to a temporary file and renamed into
+/// place, and only batches the sidecar counts are loaded, so a crash mid-flush neither
+/// records frames that were not saved nor restores a batch twice.
+pub(crate) struct Checkpoint {
+    dir: PathBuf,
+    sidecar: PathBuf,
+    /// Batches committed to the sidecar
+    batches: usize,
+}
+
+impl Checkpoint {
+    pub(crate) fn for_output(output: &Path) -> Self {
+        let with_suffix = |suffix: &str| {
+            let mut path = output.as_os_str().to_owned();
+            path.push(suffix);
+            PathBuf::from(path)
+        };
+        let mut checkpoint = Self { dir: with_suffix(".partial"), sidecar: with_suffix(".checkpoint"), batches: 0 };
+        // A corrupt sidecar is reported by `last_frame` when resuming
+        checkpoint.batches = checkpoint.committed().ok().flatten().map_or(0, |(_, batches)| batches);
+        checkpoint
+    }
+
+    pub(crate) fn exists(&self) -> bool {
+        self.sidecar.exists()
+    }
+
+    /// `(last frame, batch count)` recorded in the sidecar, `<frame> <batches>`
+    fn committed(&self) -> Result<Option<(u32, usize)>> {
+        if !self.exists() {
+            return Ok(None);
+        }
+        let contents = fs::read_to_string(&self.sidecar)?;
+        let corrupt = || format!("Corrupt checkpoint file {:?}: {:?}", self.sidecar, contents.trim());
+        let (frame, batches) = contents.trim().split_once(' ').ok_or_else(corrupt)?;
+        Ok(Some((frame.parse().map_err(|_| corrupt())?, batches.parse().map_err(|_| corrupt())?)))
+    }
+
+    /// Last frame number already saved, if a checkpoint was written
+    pub(crate) fn last_frame(&self) -> Result<Option<u32>> {
+        Ok(self.committed()?.map(|(frame, _)| frame))
+    }
+
+    /// Rows of the batches committed by earlier runs, in batch order
+    pub(crate) fn load(&self) -> Result<Option<DataFrame>> {
+        let batches = self.committed()?.map_or(0, |(_, batches)| batches);
+        let mut frames = Vec::with_capacity(batches);
+        for index in 0..batches {
+            frames.push(ParquetReader::new(File::open(self.batch_path(index))?).finish()?);
+        }
+        if frames.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(polars::functions::concat_df_diagonal(&frames)?))
+    }
+
+    /// Persist one batch of records and advance the sidecar to its last frame
+    pub(crate) fn save(&mut self, records: &[UsbPacketRecord]) -> Result<()> {
+        let Some(last) = records.last() else {
+            return Ok(());
+        };
+        fs::create_dir_all(&self.dir)?;
+        let mut df = create_dataframe(records.to_vec())?;
+        let batch = self.batch_path(self.batches);
+        let temp_batch = batch.with_extension("parquet.tmp");
+        ParquetWriter::new(File::create(&temp_batch)?).finish(&mut df)?;
+        fs::rename(&temp_batch, &batch)?;
+
+        let temp_sidecar = self.sidecar.with_extension("checkpoint.tmp");
+        fs::write(&temp_sidecar, format!("{} {}", last.frame_number, self.batches + 1))?;
+        fs::rename(&temp_sidecar, &self.sidecar)?;
+        self.batches += 1;
+        Ok(())
+    }
+
+    /// Remove all checkpoint state once the output is final (or before a fresh run)
+    pub(crate) fn clear(&mut self) -> Result<()> {
+        if self.dir.exists() {
+            fs::remove_dir_all(&self.dir)?;
+        }
+        if self.sidecar.exists() {
+            fs::remove_file(&self.sidecar)?;
+        }
+        self.batches = 0;
+        Ok(())
+    }
+
+    fn batch_path(&self, index: usize) -> PathBuf {
+        self.dir.join(format!("batch-{:06}.parquet", index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(frame: &str) -> UsbPacketRecord {
+        crate::test_support::record(&[("frame.number", frame)], &[])
+    }
+
+    #[test]
+    fn saves_batches_and_resumes_from_last_frame() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out.parquet");
+
+        let mut checkpoint = Checkpoint::for_output(&output);
+        assert_eq!(checkpoint.last_frame().unwrap(), None);
+        checkpoint.save(&[record("1"), record("2")]).unwrap();
+        checkpoint.save(&[record("5")]).unwrap();
+
+        let mut resumed = Checkpoint::for_output(&output);
+        assert_eq!(resumed.last_frame().unwrap(), Some(5));
+        let df = resumed.load().unwrap().unwrap();
+        let frames: Vec<u32> = df.column("frame_number").unwrap().u32().unwrap().into_no_null_iter().collect();
+        assert_eq!(frames, [1, 2, 5]);
+
+        resumed.clear().unwrap();
+        assert!(!resumed.exists());
+        assert!(!dir.path().join("out.parquet.partial").exists());
+    }
+
+    #[test]
+    fn ignores_batches_the_sidecar_never_committed() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out.parquet");
+        let mut checkpoint = Checkpoint::for_output(&output);
+        checkpoint.save(&[record("1"), record("2")]).unwrap();
+
+        // Crash after the next batch landed but before the sidecar moved on, and another mid-write
+        let partial = dir.path().join("out.parquet.partial");
+        fs::copy(partial.join("batch-000000.parquet"), partial.join("batch-000001.parquet")).unwrap();
+        fs::write(partial.join("batch-000002.parquet.tmp"), b"PAR1").unwrap();
+
+        let mut resumed = Checkpoint::for_output(&output);
+        assert_eq!(resumed.last_frame().unwrap(), Some(2));
+        assert_eq!(resumed.load().unwrap().unwrap().height(), 2);
+        resumed.save(&[record("3")]).unwrap();
+        let frames: Vec<u32> =
+            resumed.load().unwrap().unwrap().column("frame_number").unwrap().u32().unwrap().into_no_null_iter().collect();
+        assert_eq!(frames, [1, 2, 3]);
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+use crate::config::ConverterConfig;
+use crate::converter::Converter;
+use crate::km003c::{self, CMD_PUT_DATA};
+use crate::{ConverterError, Result};
+
+/// Byte offset of the rolling transaction id in the KM003C main header
+const TRANSACTION_ID_OFFSET: usize = 1;
+
+/// Differing byte offsets listed before the report truncates
+const MAX_LISTED_OFFSETS: usize = 16;
+
+/// One KM003C application message from a bulk transfer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub frame_number: u32,
+    pub direction: String,
+    pub packet_type: u8,
+    pub attribute: u16,
+    pub payload: Vec<u8>,
+}
+
+impl Message {
+    /// Whether two messages are the same protocol step.
+    ///
+    /// Transaction ids always differ between captures and PutData bodies carry live
+    /// measurements, so commands compare byte-for-byte apart from the id while PutData
+    /// compares only the attributes and sizes of its logical packets.
+    fn matches(&self, other: &Message) -> bool {
+        if (&self.direction, self.packet_type, self.attribute) != (&other.direction, other.packet_type, other.attribute) {
+            return false;
+        }
+        if self.packet_type == CMD_PUT_DATA {
+            let layout = |payload: &[u8]| -> Vec<(u16, usize)> {
+                km003c::logical_packets(payload).into_iter().map(|(attribute, body)| (attribute, body.len())).collect()
+            };
+            return layout(&self.payload) == layout(&other.payload);
+        }
+        differing_bytes(&self.payload, &other.payload).is_empty()
+    }
+}
+
+/// Byte offsets where two payloads differ, ignoring the transaction id
+fn differing_bytes(left: &[u8], right: &[u8]) -> Vec<usize> {
+    (0..left.len().max(right.len()))
+        .filter(|&i| i != TRANSACTION_ID_OFFSET && left.get(i) != right.get(i))
+        .collect()
+}
+
+/// First position where the two message sequences stop agreeing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Position in the message sequence (transaction order, not frame number)
+    pub index: usize,
+    /// `None` when that side ran out of messages
+    pub left: Option<Message>,
+    pub right: Option<Message>,
+    pub differing_bytes: Vec<usize>,
+}
+
+/// Result of [`compare_captures`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comparison {
+    pub left_counts: BTreeMap<u8, usize>,
+    pub right_counts: BTreeMap<u8, usize>,
+    pub divergence: Option<Divergence>,
+}
+
+/// Decode two captures to KM003C message sequences and diff them (`compare` subcommand).
+///
+/// Device address, tshark settings and other options come from `config`; its input is
+/// replaced by each capture in turn. Messages are aligned by their order in the capture.
+pub fn compare_captures(config: &ConverterConfig, left: &Path, right: &Path) -> Result<Comparison> {
+    let left = messages(config, left)?;
+    let right = messages(config, right)?;
+    Ok(compare_messages(&left, &right))
+}
+
+fn messages(config: &ConverterConfig, input: &Path) -> Result<Vec<Message>> {
+    let mut config = config.clone();
+    config.input = input.to_path_buf();
+    let mut converter = Converter::new(config)?;
+    let mut messages = Vec::new();
+    for result in converter.records() {
+        let record = match result {
+            Ok(record) => record,
+            Err(e) if e.is_tshark_failure() => return Err(e),
+            Err(_) => continue,
+        };
+        let Some(packet_type) = record.km_packet_type else {
+            continue;
+        };
+        messages.push(Message {
+            frame_number: record.frame_number,
+            direction: record.direction,
+            packet_type,
+            attribute: record.km_attribute.unwrap_or_default(),
+            payload: hex::decode(&record.payload_hex)
+                .map_err(|source| ConverterError::HexDecode { payload: record.payload_hex.clone(), source })?,
+        });
+    }
+    Ok(messages)
+}
+
+pub(crate) fn compare_messages(left: &[Message], right: &[Message]) -> Comparison {
+    let counts = |messages: &[Message]| {
+        let mut counts = BTreeMap::new();
+        for message in messages {
+            *counts.entry(message.packet_type).or_insert(0) += 1;
+        }
+        counts
+    };
+
+    let divergence = (0..left.len().max(right.len())).find_map(|index| {
+        let (l, r) = (left.get(index), right.get(index));
+        if let (Some(l), Some(r)) = (l, r) {
+            if l.matches(r) {
+                return None;
+            }
+        }
+        Some(Divergence {
+            index,
+            left: l.cloned(),
+            right: r.cloned(),
+            differing_bytes: differing_bytes(
+                l.map_or(&[][..], |m| &m.payload[..]),
+                r.map_or(&[][..], |m| &m.payload[..]),
+            ),
+        })
+    });
+
+    Comparison { left_counts: counts(left), right_counts: counts(right), divergence }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:>11}  {:>8}  {:>8}", "Packet type", "Left", "Right")?;
+        let mut types: Vec<u8> = self.left_counts.keys().chain(self.right_counts.keys()).copied().collect();
+        types.sort_unstable();
+        types.dedup();
+        for packet_type in types {
+            let count = |counts: &BTreeMap<u8, usize>| counts.get(&packet_type).copied().unwrap_or(0);
+            let marker = if count(&self.left_counts) != count(&self.right_counts) { "  *" } else { "" };
+            writeln!(
+                f,
+                "{:>11}  {:>8}  {:>8}{}",
+                format!("0x{:02x}", packet_type),
+                count(&self.left_counts),
+                count(&self.right_counts),
+                marker
+            )?;
+        }
+
+        let Some(divergence) = &self.divergence else {
+            return write!(f, "\nMessage sequences match");
+        };
+        writeln!(f, "\nFirst divergence at message #{}:", divergence.index)?;
+        for (side, message) in [("left", &divergence.left), ("right", &divergence.right)] {
+            match message {
+                Some(m) => writeln!(
+                    f,
+                    "  {:<5} frame {} {} type 0x{:02x} attribute 0x{:04x}: {}",
+                    side,
+                    m.frame_number,
+                    m.direction,
+                    m.packet_type,
+                    m.attribute,
+                    hex::encode(&m.payload)
+                )?,
+                None => writeln!(f, "  {:<5} (no more messages)", side)?,
+            }
+        }
+        if !divergence.differing_bytes.is_empty() {
+            let listed: Vec<String> = divergence.differing_bytes.iter().take(MAX_LISTED_OFFSETS).map(|i| i.to_string()).collect();
+            let more = divergence.differing_bytes.len().saturating_sub(MAX_LISTED_OFFSETS);
+            write!(f, "  differing byte offsets: {}", listed.join(", "))?;
+            if more > 0 {
+                write!(f, " (+{} more)", more)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(frame_number: u32, direction: &str, payload: &[u8]) -> Message {
+        let header = km003c::parse_header(payload).unwrap();
+        Message {
+            frame_number,
+            direction: direction.to_string(),
+            packet_type: header.packet_type,
+            attribute: header.attribute,
+            payload: payload.to_vec(),
+        }
+    }
+
+    fn adc_put_data(id: u8, vbus: u8) -> Vec<u8> {
+        let mut payload = vec![CMD_PUT_DATA, id, 0x00, 0x03];
+        payload.extend_from_slice(&(km003c::ATT_ADC as u32 | 8 << 22).to_le_bytes());
+        payload.extend_from_slice(&[vbus, 0, 0, 0, 0, 0, 0, 0]);
+        payload
+    }
+
+    #[test]
+    fn ignores_transaction_ids_and_measurements() {
+        let left = [message(10, "H->D", &[0x0C, 0x01, 0x02, 0x00]), message(12, "D->H", &adc_put_data(0x01, 5))];
+        let right = [message(7, "H->D", &[0x0C, 0x09, 0x02, 0x00]), message(9, "D->H", &adc_put_data(0x09, 9))];
+        let comparison = compare_messages(&left, &right);
+        assert_eq!(comparison.divergence, None);
+        assert_eq!(comparison.left_counts, BTreeMap::from([(0x0C, 1), (0x41, 1)]));
+    }
+
+    #[test]
+    fn reports_first_divergent_command() {
+        let left = [message(10, "H->D", &[0x0C, 0x01, 0x02, 0x00]), message(20, "H->D", &[0x0C, 0x02, 0x02, 0x00])];
+        let right = [message(7, "H->D", &[0x0C, 0x04, 0x02, 0x00]), message(8, "H->D", &[0x0C, 0x05, 0x22, 0x00])];
+        let divergence = compare_messages(&left, &right).divergence.unwrap();
+        assert_eq!(divergence.index, 1);
+        assert_eq!((divergence.left.unwrap().frame_number, divergence.right.unwrap().frame_number), (20, 8));
+        assert_eq!(divergence.differing_bytes, [2]);
+
+        let shorter = compare_messages(&left[..1], &right);
+        assert_eq!(shorter.divergence.map(|d| (d.index, d.left.is_none())), Some((1, true)));
+    }
+}
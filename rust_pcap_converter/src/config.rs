@@ -0,0 +1,713 @@
+use clap::ValueEnum;
+use polars::prelude::*;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::experimental::ExperimentalField;
+use crate::Result;
+
+/// Zstd level used when `--compression zstd` is given without `--compression-level`.
+/// The hex payload columns are highly repetitive, so a higher level pays off.
+const DEFAULT_ZSTD_LEVEL: i32 = 9;
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    Snappy,
+    #[default]
+    Zstd,
+    Lz4,
+    Gzip,
+    None,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DedupKey {
+    /// Rows sharing a frame_number are duplicates
+    FrameNumber,
+    /// Rows sharing a packet_hash (same URB and payload) are duplicates
+    PacketHash,
+}
+
+impl DedupKey {
+    pub fn column(self) -> &'static str {
+        match self {
+            DedupKey::FrameNumber => "frame_number",
+            DedupKey::PacketHash => "packet_hash",
+        }
+    }
+}
+
+/// How the capture is read (`--parser`)
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParserKind {
+    /// Dissect every frame with tshark
+    #[default]
+    Tshark,
+    /// Read usbmon pcapng/pcap directly; class and descriptor dissection needs tshark
+    Native,
+}
+
+/// What makes `--append` treat the new capture as already stored and skip it (`--dedup-key`)
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AppendDedupKey {
+    /// The session ID already has rows on the same bus; one filtered pass over the stored session_id column
+    Session,
+    /// Any new packet_hash is already stored (same URB and payload); holds every stored hash in memory
+    Hash,
+    /// Any new URB (bus, urb_id, urb_type and URB timestamp) is already stored, whatever the payload;
+    /// holds one key per stored row in memory
+    #[value(alias = "urb_id")]
+    UrbId,
+    /// Always append
+    None,
+}
+
+/// Column used to split the output into Hive-style `<column>=<value>/` directories
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PartitionKey {
+    SessionId,
+    /// One directory per decoded packet type, holding one file per session
+    #[value(alias = "km_packet_type")]
+    KmPacketType,
+}
+
+impl PartitionKey {
+    pub fn column(self) -> &'static str {
+        match self {
+            PartitionKey::SessionId => "session_id",
+            PartitionKey::KmPacketType => "km_packet_type",
+        }
+    }
+}
+
+/// Inclusive `A-B` range of frame numbers, or a single frame `N` (`--exclude-frames`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameRange {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl FrameRange {
+    pub fn contains(&self, frame: u32) -> bool {
+        (self.start..=self.end).contains(&frame)
+    }
+}
+
+impl FromStr for FrameRange {
+    type Err = String;
+
+    fn from_str(spec: &str) -> std::result::Result<Self, String> {
+        let invalid = || format!("invalid frame range {:?}, expected A-B with A <= B", spec);
+        let (start, end) = spec.split_once('-').unwrap_or((spec, spec));
+        let start: u32 = start.trim().parse().map_err(|_| invalid())?;
+        let end: u32 = end.trim().parse().map_err(|_| invalid())?;
+        if start > end {
+            return Err(invalid());
+        }
+        Ok(Self { start, end })
+    }
+}
+
+/// Rows of the final DataFrame printed by `--preview`: `head:N` or `tail:N`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preview {
+    Head(usize),
+    Tail(usize),
+}
+
+impl FromStr for Preview {
+    type Err = String;
+
+    fn from_str(spec: &str) -> std::result::Result<Self, String> {
+        let invalid = || format!("invalid preview {:?}, expected head:N or tail:N", spec);
+        let (end, rows) = spec.split_once(':').ok_or_else(invalid)?;
+        let rows = rows.trim().parse().map_err(|_| invalid())?;
+        match end.trim() {
+            "head" => Ok(Self::Head(rows)),
+            "tail" => Ok(Self::Tail(rows)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// File format written to the output path
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Parquet,
+    Csv,
+    /// InfluxDB line protocol, decoded ADC rows only
+    Influx,
+    /// KM003C payloads as `[u32 length][bytes]` records, for external decoders
+    Framestream,
+}
+
+impl OutputFormat {
+    pub(crate) fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Parquet => "parquet",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Influx => "lp",
+            OutputFormat::Framestream => "bin",
+        }
+    }
+}
+
+/// Map the CLI codec and optional level onto a Polars Parquet compression setting
+pub(crate) fn parquet_compression(codec: Compression, level: Option<i32>) -> Result<ParquetCompression> {
+    let compression = match codec {
+        Compression::Zstd => {
+            let level = level.unwrap_or(DEFAULT_ZSTD_LEVEL);
+            ParquetCompression::Zstd(Some(ZstdLevel::try_new(level)?))
+        }
+        Compression::Gzip => {
+            let level = level
+                .map(|l| u8::try_from(l).map_err(|_| format!("Invalid gzip compression level: {}", l)))
+                .transpose()?
+                .map(GzipLevel::try_new)
+                .transpose()?;
+            ParquetCompression::Gzip(level)
+        }
+        Compression::Snappy | Compression::Lz4 | Compression::None if level.is_some() => {
+            return Err(format!("--compression-level is not supported for {:?}", codec).into());
+        }
+        Compression::Snappy => ParquetCompression::Snappy,
+        Compression::Lz4 => ParquetCompression::Lz4Raw,
+        Compression::None => ParquetCompression::Uncompressed,
+    };
+    Ok(compression)
+}
+
+/// Settings for one conversion run.
+///
+/// Built fluently from [`ConverterConfig::new`]; every setter mirrors one CLI flag:
+///
+/// ```no_run
+/// use pcap_to_parquet::config::{ConverterConfig, OutputFormat};
+///
+/// let config = ConverterConfig::new("capture.16.pcapng")
+///     .output("capture.parquet")
+///     .device_address(16)
+///     .payload_only(true)
+///     .limit(10_000)
+///     .format(OutputFormat::Parquet);
+/// pcap_to_parquet::convert(&config)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct ConverterConfig {
+    pub(crate) input: PathBuf,
+    pub(crate) output: PathBuf,
+    pub(crate) device_address: Option<u8>,
+    pub(crate) bus_id: Option<u8>,
+    pub(crate) session_id: Option<String>,
+    pub(crate) session_template: Option<String>,
+    pub(crate) output_dir: Option<PathBuf>,
+    pub(crate) output_template: Option<String>,
+    pub(crate) append: bool,
+    pub(crate) overwrite: bool,
+    pub(crate) since: bool,
+    pub(crate) dedup_keys: Vec<AppendDedupKey>,
+    pub(crate) payload_only: bool,
+    pub(crate) device_name: bool,
+    pub(crate) anonymize: bool,
+    pub(crate) coalesce_urbs: bool,
+    pub(crate) include_raw: bool,
+    pub(crate) max_payload: Option<usize>,
+    pub(crate) adc_precision: Option<u32>,
+    pub(crate) experimental_fields: Vec<ExperimentalField>,
+    pub(crate) check_transactions: bool,
+    pub(crate) check_drops: bool,
+    pub(crate) sort_on_write: bool,
+    pub(crate) continuous_time: bool,
+    pub(crate) manifest: bool,
+    pub(crate) no_stats: bool,
+    pub(crate) normalize: bool,
+    pub(crate) dedup: Option<DedupKey>,
+    pub(crate) fields: Option<Vec<String>>,
+    pub(crate) only_packet_types: Option<Vec<String>>,
+    pub(crate) urb_statuses: Vec<String>,
+    pub(crate) transaction_id: Option<u8>,
+    pub(crate) exclude_frames: Vec<FrameRange>,
+    pub(crate) preview: Option<Preview>,
+    pub(crate) errors_only: bool,
+    pub(crate) query: Option<String>,
+    pub(crate) limit: Option<usize>,
+    pub(crate) sample_rate: Option<usize>,
+    pub(crate) resample_ms: Option<u32>,
+    pub(crate) exchanges: bool,
+    pub(crate) energy: bool,
+    pub(crate) feature_bytes: Option<usize>,
+    pub(crate) throughput_json: Option<PathBuf>,
+    pub(crate) batch_size: Option<usize>,
+    pub(crate) resume: bool,
+    pub(crate) follow: bool,
+    pub(crate) format: OutputFormat,
+    pub(crate) partition_by: Option<PartitionKey>,
+    pub(crate) mkdir: bool,
+    pub(crate) duckdb: Option<PathBuf>,
+    pub(crate) duckdb_table: String,
+    pub(crate) parser: ParserKind,
+    pub(crate) tshark_path: Option<PathBuf>,
+    pub(crate) tshark_args: Vec<String>,
+    pub(crate) display_filter: Option<String>,
+    pub(crate) attribute_map: Option<PathBuf>,
+    pub(crate) schema: Option<PathBuf>,
+    pub(crate) wasm_decoder: Option<PathBuf>,
+    pub(crate) compression: Compression,
+    pub(crate) compression_level: Option<i32>,
+    pub(crate) verbose: bool,
+}
+
+impl Default for ConverterConfig {
+    fn default() -> Self {
+        Self {
+            input: PathBuf::new(),
+            output: PathBuf::from("usb_packets.parquet"),
+            device_address: None,
+            bus_id: None,
+            session_id: None,
+            session_template: None,
+            output_dir: None,
+            output_template: None,
+            append: false,
+            overwrite: false,
+            since: false,
+            dedup_keys: vec![AppendDedupKey::Session, AppendDedupKey::Hash],
+            payload_only: false,
+            device_name: false,
+            anonymize: false,
+            coalesce_urbs: false,
+            include_raw: false,
+            max_payload: None,
+            adc_precision: None,
+            experimental_fields: Vec::new(),
+            check_transactions: false,
+            check_drops: false,
+            sort_on_write: false,
+            continuous_time: false,
+            manifest: false,
+            no_stats: false,
+            normalize: false,
+            dedup: None,
+            fields: None,
+            only_packet_types: None,
+            urb_statuses: Vec::new(),
+            transaction_id: None,
+            exclude_frames: Vec::new(),
+            preview: None,
+            errors_only: false,
+            query: None,
+            limit: None,
+            sample_rate: None,
+            resample_ms: None,
+            exchanges: false,
+            energy: false,
+            feature_bytes: None,
+            throughput_json: None,
+            batch_size: None,
+            resume: false,
+            follow: false,
+            format: OutputFormat::default(),
+            partition_by: None,
+            mkdir: false,
+            duckdb: None,
+            duckdb_table: "usb_packets".to_string(),
+            parser: ParserKind::default(),
+            tshark_path: None,
+            tshark_args: Vec::new(),
+            display_filter: None,
+            attribute_map: None,
+            schema: None,
+            wasm_decoder: None,
+            compression: Compression::default(),
+            compression_level: None,
+            verbose: false,
+        }
+    }
+}
+
+impl ConverterConfig {
+    /// Start a configuration for the given capture file (`--input`); `-` reads standard input
+    pub fn new(input: impl Into<PathBuf>) -> Self {
+        Self {
+            input: input.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Output file path (`--output`)
+    pub fn output(mut self, output: impl Into<PathBuf>) -> Self {
+        self.output = output.into();
+        self
+    }
+
+    /// Directory for per-input outputs named by the output template (`--output-dir`)
+    pub fn output_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.output_dir = Some(dir.into());
+        self
+    }
+
+    /// Name outputs from `{stem}`, `{addr}` and `{date}` placeholders (`--output-template`);
+    /// defaults to `{stem}.<format extension>` when only an output directory is set
+    pub fn output_template(mut self, template: impl Into<String>) -> Self {
+        self.output_template = Some(template.into());
+        self
+    }
+
+    /// USB device address to convert (`--device-address`); auto-detected from the filename or capture when unset
+    pub fn device_address(mut self, address: u8) -> Self {
+        self.device_address = Some(address);
+        self
+    }
+
+    /// Only convert packets on this usbmon bus, for captures where the device address is
+    /// reused across controllers (`--bus-id`)
+    pub fn bus_id(mut self, bus_id: u8) -> Self {
+        self.bus_id = Some(bus_id);
+        self
+    }
+
+    /// Session ID stored on every row (`--session-id`); defaults to the input file stem
+    pub fn session_id(mut self, session_id: impl Into<String>) -> Self {
+        self.session_id = Some(session_id.into());
+        self
+    }
+
+    /// Derive the session ID from `{stem}`, `{addr}` and `{date}` placeholders (`--session-template`)
+    pub fn session_template(mut self, template: impl Into<String>) -> Self {
+        self.session_template = Some(template.into());
+        self
+    }
+
+    /// Append to an existing output file instead of overwriting it (`--append`)
+    pub fn append(mut self, append: bool) -> Self {
+        self.append = append;
+        self
+    }
+
+    /// Replace existing output, side-table or DuckDB files; without it or `append` the conversion refuses to run (`--overwrite`)
+    pub fn overwrite(mut self, overwrite: bool) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// With `append`, only convert frames newer than the session's stored data (`--since`)
+    pub fn since(mut self, since: bool) -> Self {
+        self.since = since;
+        self
+    }
+
+    /// Only keep packets carrying payload data (`--payload-only`)
+    pub fn payload_only(mut self, payload_only: bool) -> Self {
+        self.payload_only = payload_only;
+        self
+    }
+
+    /// Attach product names from device descriptors (`--device-name`)
+    pub fn device_name(mut self, device_name: bool) -> Self {
+        self.device_name = device_name;
+        self
+    }
+
+    /// Blank or pseudonymize host-identifying fields and rebase timestamps to zero (`--anonymize`)
+    pub fn anonymize(mut self, anonymize: bool) -> Self {
+        self.anonymize = anonymize;
+        self
+    }
+
+    /// Merge each submit URB with its completion into one row (`--coalesce-urbs`)
+    pub fn coalesce_urbs(mut self, coalesce: bool) -> Self {
+        self.coalesce_urbs = coalesce;
+        self
+    }
+
+    /// Reassemble full frame bytes into `frame_raw_hex` (`--include-raw`)
+    pub fn include_raw(mut self, include_raw: bool) -> Self {
+        self.include_raw = include_raw;
+        self
+    }
+
+    /// Keep only the first `bytes` of each payload and mark cut rows in `payload_truncated_by_flag` (`--max-payload`)
+    pub fn max_payload(mut self, bytes: usize) -> Self {
+        self.max_payload = Some(bytes);
+        self
+    }
+
+    /// Round the decoded `adc_*` float columns to this many decimal places (`--adc-precision`)
+    pub fn adc_precision(mut self, decimals: u32) -> Self {
+        self.adc_precision = Some(decimals);
+        self
+    }
+
+    /// Decode an ad-hoc payload field into its own column; unstable (`--experimental-field`)
+    pub fn experimental_field(mut self, field: ExperimentalField) -> Self {
+        self.experimental_fields.push(field);
+        self
+    }
+
+    /// Flag KM003C transaction id jumps in `transaction_gap` (`--check-transactions`)
+    pub fn check_transactions(mut self, check: bool) -> Self {
+        self.check_transactions = check;
+        self
+    }
+
+    /// Report regions where usbmon events were lost, from URBs missing their submit or completion (`--check-drops`)
+    pub fn check_drops(mut self, check: bool) -> Self {
+        self.check_drops = check;
+        self
+    }
+
+    /// Sort rows by URB time before writing, re-sorting the combined file when appending
+    /// (`--sort-on-write`); the whole dataset is held in memory and sorted in one pass
+    pub fn sort_on_write(mut self, sort: bool) -> Self {
+        self.sort_on_write = sort;
+        self
+    }
+
+    /// Chain sessions end to end in epoch order so `timestamp` is monotonic across appended
+    /// captures, keeping each session's own time in `timestamp_session` (`--continuous-time`)
+    pub fn continuous_time(mut self, continuous: bool) -> Self {
+        self.continuous_time = continuous;
+        self
+    }
+
+    /// Write `<output>.manifest.json` next to every output file, describing its source, sessions,
+    /// devices, record count, time range and columns (`--manifest`)
+    pub fn manifest(mut self, manifest: bool) -> Self {
+        self.manifest = manifest;
+        self
+    }
+
+    /// Skip the summary statistics printed after a conversion (`--no-stats`)
+    pub fn no_stats(mut self, no_stats: bool) -> Self {
+        self.no_stats = no_stats;
+        self
+    }
+
+    /// Write separate `_packets`, `_adc` and `_pd` Parquet tables keyed by frame (`--normalize`)
+    pub fn normalize(mut self, normalize: bool) -> Self {
+        self.normalize = normalize;
+        self
+    }
+
+    /// Checks that make `append` skip a capture already stored, in order; the default is
+    /// session then hash (`--dedup-key`)
+    pub fn dedup_keys(mut self, keys: impl IntoIterator<Item = AppendDedupKey>) -> Self {
+        self.dedup_keys = keys.into_iter().collect();
+        self
+    }
+
+    /// Drop duplicate rows within the capture (`--dedup`)
+    pub fn dedup(mut self, key: DedupKey) -> Self {
+        self.dedup = Some(key);
+        self
+    }
+
+    /// Only emit these columns, plus `frame_number` and `payload_hex` (`--fields`)
+    pub fn fields<I, S>(mut self, fields: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.fields = Some(fields.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Only keep KM003C packets of these types, e.g. `adc` or `get_data` (`--only-packet-types`)
+    pub fn only_packet_types<I, S>(mut self, types: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.only_packet_types = Some(types.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Also keep rows whose `urb_status` is this errno, e.g. `-32`; repeatable (`--urb-status`)
+    pub fn urb_status(mut self, status: impl Into<String>) -> Self {
+        self.urb_statuses.push(status.into());
+        self
+    }
+
+    /// Print the first or last rows of the final DataFrame before writing it (`--preview`)
+    pub fn preview(mut self, preview: Preview) -> Self {
+        self.preview = Some(preview);
+        self
+    }
+
+    /// Drop the frames in this inclusive range before any processing; repeatable (`--exclude-frames`)
+    pub fn exclude_frames(mut self, range: FrameRange) -> Self {
+        self.exclude_frames.push(range);
+        self
+    }
+
+    /// Keep only the request and response rows of one KM003C transaction id (`--transaction-id`)
+    pub fn transaction_id(mut self, id: u8) -> Self {
+        self.transaction_id = Some(id);
+        self
+    }
+
+    /// Keep only failed transfers: non-zero `urb_status` other than a pending submit (`--errors-only`)
+    pub fn errors_only(mut self, errors_only: bool) -> Self {
+        self.errors_only = errors_only;
+        self
+    }
+
+    /// Replace the output with the result of a Polars SQL statement over table `packets` (`--query`)
+    pub fn query(mut self, query: impl Into<String>) -> Self {
+        self.query = Some(query.into());
+        self
+    }
+
+    /// Stop after reading this many packets from tshark (`--limit`)
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Keep every `n`th packet read from tshark, starting with the first (`--sample-rate`)
+    pub fn sample_rate(mut self, n: usize) -> Self {
+        self.sample_rate = Some(n);
+        self
+    }
+
+    /// Also write ADC means in `interval_ms` bins to a separate Parquet file (`--resample`)
+    pub fn resample(mut self, interval_ms: u32) -> Self {
+        self.resample_ms = Some(interval_ms);
+        self
+    }
+
+    /// Also write one row per GetData/PutData exchange to a separate Parquet file (`--exchanges`)
+    pub fn exchanges(mut self, exchanges: bool) -> Self {
+        self.exchanges = exchanges;
+        self
+    }
+
+    /// Add `cumulative_wh`, ADC power integrated over time per session (`--energy`)
+    pub fn energy(mut self, energy: bool) -> Self {
+        self.energy = energy;
+        self
+    }
+
+    /// Also write a numeric feature matrix with the first `bytes` payload bytes per row (`--features`)
+    pub fn features(mut self, bytes: usize) -> Self {
+        self.feature_bytes = Some(bytes);
+        self
+    }
+
+    /// Also write per-endpoint throughput statistics as JSON (`--throughput-json`)
+    pub fn throughput_json(mut self, path: impl Into<PathBuf>) -> Self {
+        self.throughput_json = Some(path.into());
+        self
+    }
+
+    /// Checkpoint converted records to disk every `size` records (`--batch-size`)
+    pub fn batch_size(mut self, size: usize) -> Self {
+        self.batch_size = Some(size);
+        self
+    }
+
+    /// Continue an interrupted conversion from its checkpoint (`--resume`)
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// Keep re-reading a capture that is still being written and append new frames (`--follow`)
+    pub fn follow(mut self, follow: bool) -> Self {
+        self.follow = follow;
+        self
+    }
+
+    /// Output file format (`--format`)
+    pub fn format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Treat the output path as a dataset directory partitioned by this column (`--partition-by`)
+    pub fn partition_by(mut self, key: PartitionKey) -> Self {
+        self.partition_by = Some(key);
+        self
+    }
+
+    /// Read usbmon captures with tshark or the built-in parser (`--parser`)
+    pub fn parser(mut self, parser: ParserKind) -> Self {
+        self.parser = parser;
+        self
+    }
+
+    /// tshark binary or the directory containing it, when it is not on `PATH` (`--tshark-path`)
+    pub fn tshark_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.tshark_path = Some(path.into());
+        self
+    }
+
+    /// Extra tshark argument, `-o <pref>:<value>` or `-d <rule>`; repeatable (`--tshark-arg`)
+    pub fn tshark_arg(mut self, arg: impl Into<String>) -> Self {
+        self.tshark_args.push(arg.into());
+        self
+    }
+
+    /// Replace the generated tshark display filter (device address, `--payload-only`) entirely (`--filter`)
+    pub fn display_filter(mut self, filter: impl Into<String>) -> Self {
+        self.display_filter = Some(filter.into());
+        self
+    }
+
+    /// TOML or JSON `bit -> name` table merged over the built-in attribute names (`--attribute-map`)
+    pub fn attribute_map(mut self, path: impl Into<PathBuf>) -> Self {
+        self.attribute_map = Some(path.into());
+        self
+    }
+
+    /// Conform every output to the columns and dtypes listed in this TOML or JSON file (`--schema`)
+    pub fn schema(mut self, path: impl Into<PathBuf>) -> Self {
+        self.schema = Some(path.into());
+        self
+    }
+
+    /// WebAssembly module whose JSON output becomes `wasm_*` columns; needs the `wasm`
+    /// feature (`--wasm-decoder`)
+    pub fn wasm_decoder(mut self, path: impl Into<PathBuf>) -> Self {
+        self.wasm_decoder = Some(path.into());
+        self
+    }
+
+    /// Create missing parent directories of the output instead of failing (`--mkdir`)
+    pub fn mkdir(mut self, mkdir: bool) -> Self {
+        self.mkdir = mkdir;
+        self
+    }
+
+    /// Insert rows into this DuckDB database instead of writing an output file (`--duckdb`)
+    pub fn duckdb(mut self, path: impl Into<PathBuf>) -> Self {
+        self.duckdb = Some(path.into());
+        self
+    }
+
+    /// DuckDB table receiving the rows (`--table`)
+    pub fn table(mut self, table: impl Into<String>) -> Self {
+        self.duckdb_table = table.into();
+        self
+    }
+
+    /// Parquet compression codec (`--compression`)
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Codec-specific compression level (`--compression-level`)
+    pub fn compression_level(mut self, level: i32) -> Self {
+        self.compression_level = Some(level);
+        self
+    }
+
+    /// Print per-packet details while converting (`--verbose`)
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+}
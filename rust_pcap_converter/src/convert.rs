@@ -0,0 +1,1356 @@
+use log::{debug, error, info, warn};
+use polars::prelude::*;
+use polars_utils::plpath::PlPath;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::checkpoint::Checkpoint;
+use crate::config::{parquet_compression, AppendDedupKey, ConverterConfig, OutputFormat, PartitionKey};
+use crate::converter::{expand_template, log_progress, ConvertEvent, Converter, FrameFilter};
+use crate::energy::add_cumulative_energy;
+use crate::exchange::exchange_table;
+use crate::experimental::add_experimental_columns;
+use crate::features::feature_matrix;
+use crate::framestream::write_frame_stream;
+use crate::influx::write_line_protocol;
+use crate::km003c::PacketTypeFilter;
+use crate::metadata::{manifest_path, source_label, write_manifest, RunMetadata};
+use crate::normalize::{normalized_path, write_normalized, TABLE_SUFFIXES};
+use crate::record::{
+    anonymize_records, CATEGORICAL_COLUMNS, create_dataframe, enrich_device_names, mark_transaction_gaps, normalize_urb_status,
+    UsbPacketRecord,
+};
+use crate::resample::resample_adc;
+use crate::schema::{conform_to_schema, load_schema_file};
+use crate::stats::{print_preview, print_sampled_statistics, throughput_stats, write_throughput_json};
+use crate::timeline::continuous_timeline;
+use crate::urb::{coalesce_urbs, find_drop_spans};
+use crate::Result;
+
+/// Run a full conversion: read the capture with tshark, build the DataFrame and write the output file.
+///
+/// Row order is part of the output contract: the rows of one run are ordered by `frame_number`
+/// (ISO segments by segment index within a frame) however they were read or batched. With
+/// `--append` they follow the rows already stored; `--sort-on-write` and an ordering `--query`
+/// replace this order.
+pub fn convert(config: &ConverterConfig) -> Result<()> {
+    convert_with_observer(config, log_progress)
+}
+
+/// [`convert`], reporting read progress and checkpoints to `observer` instead of the log.
+/// `--follow` re-opens the capture on every poll and always logs.
+pub fn convert_with_observer(config: &ConverterConfig, observer: impl Fn(ConvertEvent) + 'static) -> Result<()> {
+    if config.follow {
+        return crate::follow::follow(config);
+    }
+    let started = Instant::now();
+    let mut converter = Converter::new(config.clone())?.with_observer(observer);
+    let device_address = converter.device_address();
+    let session_id = converter.session_id().to_string();
+
+    // With partitioning, the output path is the dataset root and this run writes one partition
+    let output = match config.partition_by {
+        Some(PartitionKey::SessionId) => partition_path(&config.output, "session_id", &session_id, config.format),
+        // Spread over every packet-type directory; this path only names the session's side outputs
+        Some(PartitionKey::KmPacketType) => {
+            config.output.join(format!("{}.{}", escape_partition_value(&session_id), config.format.extension()))
+        }
+        None => templated_output(config, converter.input_stem(), device_address)?.unwrap_or_else(|| config.output.clone()),
+    };
+
+    let mut pipeline = RowPipeline::new(config)?;
+    if config.duckdb.is_some() && !cfg!(feature = "duckdb") {
+        return Err("--duckdb requires pcap_to_parquet to be built with the `duckdb` feature".into());
+    }
+    // A missing output directory would otherwise only surface as an OS error after the tshark pass
+    let checked_output = if config.partition_by.is_some() { &config.output } else { &output };
+    ensure_output_dir(config.duckdb.as_deref().unwrap_or(checked_output), config.mkdir)?;
+    let session_partitions = match config.partition_by {
+        Some(PartitionKey::KmPacketType) => packet_type_partition_files(&config.output, &session_id, config.format)?,
+        _ => Vec::new(),
+    };
+    if config.append && !session_partitions.is_empty() {
+        return Err("--append cannot extend a session split by --partition-by km-packet-type; re-convert it with --overwrite".into());
+    }
+    // Every file the run replaces, side tables included, is checked before the tshark pass
+    for path in planned_outputs(&output, &session_partitions, config) {
+        check_overwrite(&path, config)?;
+    }
+    if let Some(path) = &config.throughput_json {
+        ensure_output_dir(path, config.mkdir)?;
+    }
+    if config.normalize
+        && (config.format != OutputFormat::Parquet || config.append || config.partition_by.is_some() || config.duckdb.is_some())
+    {
+        return Err("--normalize writes new Parquet tables and cannot be combined with --format, --append, --partition-by or --duckdb".into());
+    }
+    if config.normalize && (config.fields.is_some() || config.query.is_some()) {
+        return Err("--normalize needs the full column set and cannot be combined with --fields or --query".into());
+    }
+    if config.sample_rate == Some(0) {
+        return Err("--sample-rate must be at least 1".into());
+    }
+    if config.resume
+        && (config.coalesce_urbs || config.anonymize || config.check_transactions || config.check_drops || config.energy || config.device_name)
+    {
+        return Err("--resume cannot be combined with --coalesce-urbs, --anonymize, --check-transactions, --check-drops, --energy or --device-name, which need the whole capture in one pass".into());
+    }
+    if config.since && config.energy {
+        return Err("--energy integrates whole sessions and cannot be combined with --since".into());
+    }
+
+    info!("Processing file: {:?}", config.input);
+    info!("Output file: {:?}", output);
+    info!("Device address: {}", device_address);
+    info!("Session ID: {}", session_id);
+    if let Some(filter) = &config.display_filter {
+        info!("Mode: custom display filter: {}", filter);
+        warn!("⚠️  --filter replaces the generated filter; device address {} is still auto-detected for metadata and naming but not filtered on", device_address);
+    } else if config.payload_only {
+        info!("Mode: payload-only (excluding control/setup packets)");
+    } else {
+        info!("Mode: complete capture (all USB packets to device)");
+    }
+
+    // Incremental append: only read frames newer than what is already stored for this session
+    let since_mark = if config.since && config.append && output.exists() {
+        load_since_mark(&output, &session_id, device_address)?
+    } else {
+        None
+    };
+    if let Some(mark) = &since_mark {
+        info!("Incremental append: skipping frames up to {:.6}s already stored", mark.max_timestamp);
+        converter.push_filter(FrameFilter::AfterTime(mark.max_timestamp));
+    }
+
+    // Checkpointing: continue after the last saved frame, or drop stale state before a fresh run
+    let mut checkpoint = Checkpoint::for_output(&output);
+    let resumed_df = if config.resume {
+        match checkpoint.last_frame()? {
+            Some(frame) => {
+                info!("Resuming from checkpoint: skipping frames up to {}", frame);
+                converter.push_filter(FrameFilter::AfterFrame(frame));
+                checkpoint.load()?
+            }
+            None => {
+                info!("No checkpoint found for {:?}, starting from the beginning", output);
+                None
+            }
+        }
+    } else {
+        if config.batch_size.is_some() && checkpoint.exists() {
+            info!("Discarding stale checkpoint for {:?} (use --resume to continue it)", output);
+            checkpoint.clear()?;
+        }
+        None
+    };
+    
+    debug!("Display filter: {}", converter.display_filter());
+    let run_metadata = RunMetadata {
+        tshark_version: converter.tshark_version(),
+        display_filter: converter.display_filter(),
+        source_file: source_label(&config.input, config.anonymize),
+        sample_rate: config.sample_rate,
+    };
+
+    let records = read_records(&mut converter, config, Some(&mut checkpoint))?;
+
+    if records.is_empty() && resumed_df.is_none() {
+        if since_mark.is_some() {
+            info!("✅ No frames newer than the stored data. Dataset remains unchanged.");
+        } else {
+            warn!("No USB data packets found. Check your filter settings.");
+        }
+        return Ok(());
+    }
+
+    // Guard against a reset timeline: relative timestamps are only comparable within one capture
+    if let (Some(mark), Some(first)) = (&since_mark, records.first()) {
+        let origin_us = capture_origin_us(first.timestamp, first.urb_ts_sec, first.urb_ts_usec);
+        if (origin_us - mark.origin_us).abs() > SINCE_ORIGIN_TOLERANCE_US {
+            return Err(format!(
+                "Capture timeline does not match the stored session '{}' (clock reset or different capture); re-run without --since",
+                session_id
+            )
+            .into());
+        }
+    }
+
+    // Resolve compression before doing any work on the output
+    let compression = parquet_compression(config.compression, config.compression_level)?;
+    if config.append && config.format != OutputFormat::Parquet {
+        return Err("--append is only supported for Parquet output".into());
+    }
+
+    let mut new_df = pipeline.dataframe(records, config)?;
+    if let Some(resumed) = resumed_df {
+        info!("Restored {} records from checkpoint", resumed.height());
+        let resumed = pipeline.restored(resumed, config)?;
+        new_df = polars::functions::concat_df_diagonal(&[resumed, new_df])?;
+    }
+
+    new_df = pipeline.select(new_df, config)?;
+
+    #[cfg(feature = "duckdb")]
+    if let Some(db) = &config.duckdb {
+        if let Some(preview) = config.preview {
+            print_preview(&new_df, preview);
+        }
+        info!("Inserting {} records into DuckDB table '{}' in {:?}", new_df.height(), config.duckdb_table, db);
+        crate::duckdb_sink::write_duckdb(&new_df, db, &config.duckdb_table, config.append)?;
+        info!("Successfully saved {} records to {:?}", new_df.height(), db);
+        if !config.no_stats {
+            if let Err(e) = print_sampled_statistics(&new_df, config.sample_rate) {
+                warn!("⚠️  Statistics display error (data is fine): {}", e);
+            }
+        }
+        info!("{}", run_summary(started.elapsed(), converter.packet_count(), new_df.height(), peak_memory_kib()));
+        return Ok(());
+    }
+    
+    // Handle file merging/appending
+    let final_df = if config.append && output.exists() {
+        info!("Loading existing data from {:?}", output);
+        let existing_df = LazyFrame::scan_parquet(PlPath::new(output.to_str().unwrap()), ScanArgsParquet::default())?
+            .collect()?;
+        
+        // --since already reads only frames newer than the stored ones
+        if since_mark.is_none() && already_stored(&existing_df, &new_df, &session_id, &config.dedup_keys)? {
+            info!("✅ No new data added. Dataset remains unchanged.");
+            return Ok(());
+        }
+
+        // Files written before the Categorical columns existed store them as strings
+        let mut existing_df = existing_df;
+        for name in CATEGORICAL_COLUMNS {
+            let new_dtype = new_df.column(name).map(|c| c.dtype().clone());
+            if let (Ok(existing), Ok(dtype)) = (existing_df.column(name), new_dtype) {
+                if existing.dtype() != &dtype {
+                    let cast = existing.cast(&dtype)?;
+                    existing_df.with_column(cast)?;
+                }
+            }
+        }
+
+        // Combine datasets; diagonal concat keeps files written before newer columns existed appendable
+        let combined_df = polars::functions::concat_df_diagonal(&[existing_df.clone(), new_df.clone()])?;
+        
+        info!("Combined {} existing + {} new = {} total records", 
+                existing_df.height(), new_df.height(), combined_df.height());
+        
+        combined_df
+    } else {
+        if output.exists() {
+            info!("Overwriting existing file: {:?}", output);
+        }
+        new_df
+    };
+    let final_df = if config.continuous_time { continuous_timeline(final_df)? } else { final_df };
+    let final_df = if config.sort_on_write { sort_by_capture_time(final_df)? } else { final_df };
+    if let Some(preview) = config.preview {
+        print_preview(&final_df, preview);
+    }
+
+    if config.normalize {
+        write_normalized(&final_df, &output, compression, &run_metadata, config.manifest)?;
+    } else if config.partition_by == Some(PartitionKey::KmPacketType) {
+        for stale in &session_partitions {
+            std::fs::remove_file(stale)?;
+            let stale_manifest = manifest_path(stale);
+            if stale_manifest.exists() {
+                std::fs::remove_file(stale_manifest)?;
+            }
+        }
+        for partition in final_df.partition_by_stable(["km_packet_type"], true)? {
+            let packet_type = partition.column("km_packet_type")?.u32()?.get(0);
+            let path = packet_type_partition_path(&config.output, packet_type, &session_id, config.format);
+            write_output(&partition, &path, config, compression, &run_metadata)?;
+            info!("Saved {} records to {:?}", partition.height(), path);
+        }
+        info!("Successfully saved {} records under {:?}", final_df.height(), config.output);
+    } else {
+        write_output(&final_df, &output, config, compression, &run_metadata)?;
+        info!("Successfully saved {} records to {:?}", final_df.height(), output);
+    }
+    checkpoint.clear()?;
+
+    if let Some(path) = &config.throughput_json {
+        write_throughput_json(&throughput_stats(final_df.clone().lazy())?, path)?;
+        info!("Saved endpoint throughput statistics to {:?}", path);
+    }
+
+    if let Some(interval_ms) = config.resample_ms {
+        let mut bins = resample_adc(&final_df, interval_ms)?;
+        let resampled_path = normalized_path(&output, &format!("resampled_{}ms", interval_ms));
+        ParquetWriter::new(std::fs::File::create(&resampled_path)?)
+            .with_compression(compression)
+            .finish(&mut bins)?;
+        info!("Saved {} resampled ADC bins ({} ms) to {:?}", bins.height(), interval_ms, resampled_path);
+    }
+
+    if config.exchanges {
+        let mut exchanges = exchange_table(&final_df)?;
+        let exchanges_path = normalized_path(&output, "exchanges");
+        ParquetWriter::new(std::fs::File::create(&exchanges_path)?)
+            .with_compression(compression)
+            .finish(&mut exchanges)?;
+        let unmatched = exchanges.column("status")?.str()?.into_iter().filter(|s| *s != Some("matched")).count();
+        if config.verbose {
+            report_incomplete_responses(&exchanges)?;
+        }
+        info!("Saved {} GetData/PutData exchanges ({} unmatched) to {:?}", exchanges.height(), unmatched, exchanges_path);
+    }
+
+    if let Some(width) = config.feature_bytes {
+        let mut features = feature_matrix(&final_df, width)?;
+        let features_path = normalized_path(&output, "features");
+        ParquetWriter::new(std::fs::File::create(&features_path)?)
+            .with_compression(compression)
+            .finish(&mut features)?;
+        info!("Saved {}x{} feature matrix to {:?}", features.height(), features.width(), features_path);
+    }
+
+    // Print some statistics (with error handling)
+    if config.no_stats {
+        debug!("Skipping statistics (--no-stats)");
+    } else if let Err(e) = print_sampled_statistics(&final_df, config.sample_rate) {
+        warn!("⚠️  Statistics display error (data is fine): {}", e);
+        info!("✅ Dataset saved successfully with {} records", final_df.height());
+    }
+    info!("{}", run_summary(started.elapsed(), converter.packet_count(), final_df.height(), peak_memory_kib()));
+
+    Ok(())
+}
+
+/// Convert an in-memory pcapng capture and return the DataFrame instead of writing it.
+///
+/// The bytes go through a temporary file and the same read and row pipeline as
+/// [`convert`]. Options that only concern the output file (`--output`, `--append`,
+/// `--resume`, `--partition-by`, ...) are ignored. Without a `session_id` the rows are
+/// labelled `in_memory` rather than after the temporary file.
+pub fn convert_bytes(pcapng: &[u8], config: &ConverterConfig) -> Result<DataFrame> {
+    let mut capture = tempfile::Builder::new().suffix(".pcapng").tempfile()?;
+    capture.write_all(pcapng)?;
+    capture.flush()?;
+    let mut config = config.clone();
+    config.input = capture.path().to_path_buf();
+    config.session_id.get_or_insert_with(|| "in_memory".to_string());
+
+    let mut pipeline = RowPipeline::new(&config)?;
+    let mut converter = Converter::new(config.clone())?;
+    let records = read_records(&mut converter, &config, None)?;
+    let df = pipeline.dataframe(records, &config)?;
+    pipeline.select(df, &config)
+}
+
+/// Read every record tshark yields, applying `--sample-rate` and saving `--batch-size`
+/// checkpoints when a checkpoint is given
+fn read_records(converter: &mut Converter, config: &ConverterConfig, mut checkpoint: Option<&mut Checkpoint>) -> Result<Vec<UsbPacketRecord>> {
+    let mut records = Vec::new();
+    let mut flushed = 0;
+    let observer = converter.observer();
+    let mut matched = 0usize;
+
+    info!("Reading packets...");
+    if let Some(n) = config.sample_rate {
+        info!("Sampling 1 in {} packets", n);
+    }
+    for result in converter.records() {
+        match result {
+            Ok(record) => {
+                matched += 1;
+                if !keeps_sample(matched - 1, config.sample_rate) {
+                    continue;
+                }
+                records.push(record);
+            }
+            // tshark failures abort the run; malformed packets are counted by the converter and skipped
+            Err(e) if e.is_tshark_failure() => return Err(e),
+            Err(e) => {
+                debug!("Skipping malformed packet: {}", e);
+                continue;
+            }
+        }
+
+        if let Some(checkpoint) = checkpoint.as_deref_mut() {
+            if config.batch_size.is_some_and(|size| records.len() - flushed >= size) {
+                checkpoint.save(&records[flushed..])?;
+                flushed = records.len();
+                observer(ConvertEvent::BatchFlushed { records: flushed });
+            }
+        }
+    }
+    info!(
+        "Processed {} packets, extracted {} USB data packets",
+        converter.packet_count(),
+        records.len()
+    );
+    if config.bus_id.is_none() && config.display_filter.is_none() {
+        let mut buses: Vec<u8> = records.iter().map(|r| r.bus_id).collect();
+        buses.sort_unstable();
+        buses.dedup();
+        if buses.len() > 1 {
+            warn!(
+                "⚠️  Device address {} appears on buses {:?}; these may be different devices, pass --bus-id to pick one",
+                converter.device_address(),
+                buses
+            );
+        }
+    }
+    if converter.non_usb_count() > 0 || converter.malformed_count() > 0 {
+        warn!(
+            "Skipped {} non-USB frames; {} malformed USB packets could not be converted",
+            converter.non_usb_count(),
+            converter.malformed_count()
+        );
+    }
+    Ok(records)
+}
+
+/// Row-level steps shared by [`convert`], [`convert_bytes`] and `--follow`, with their
+/// options validated up front so mistakes surface before the tshark pass
+pub(crate) struct RowPipeline {
+    selected_fields: Option<Vec<String>>,
+    packet_type_filter: Option<Expr>,
+    urb_status_filter: Option<Expr>,
+    schema: Option<Vec<(String, DataType)>>,
+    #[cfg(feature = "wasm")]
+    wasm_decoder: Option<crate::wasm_decoder::WasmDecoder>,
+}
+
+impl RowPipeline {
+    pub(crate) fn new(config: &ConverterConfig) -> Result<Self> {
+        let selected_fields = config.fields.as_deref().map(select_fields).transpose()?;
+        let packet_type_filter = config.only_packet_types.as_deref().map(packet_type_filter).transpose()?;
+        let urb_status_filter = urb_status_filter(&config.urb_statuses, config.errors_only)?;
+        let schema = config.schema.as_deref().map(load_schema_file).transpose()?;
+        if let Some(query) = &config.query {
+            run_query(&create_dataframe(Vec::new())?, query)?;
+        }
+        if config.check_drops && (config.payload_only || config.sample_rate.is_some() || config.display_filter.is_some()) {
+            return Err("--check-drops needs every submit and completion and cannot be combined with --payload-only, --sample-rate or --filter".into());
+        }
+        if config.wasm_decoder.is_some() && !cfg!(feature = "wasm") {
+            return Err("--wasm-decoder requires pcap_to_parquet to be built with the `wasm` feature".into());
+        }
+        // Side tables are built after the main file is written, so reject their bad sizes up front
+        if config.resample_ms == Some(0) {
+            return Err("--resample interval must be at least 1 ms".into());
+        }
+        if config.feature_bytes == Some(0) {
+            return Err("--features needs at least 1 payload byte".into());
+        }
+        let side_tables = config.exchanges || config.feature_bytes.is_some() || config.resample_ms.is_some() || config.throughput_json.is_some();
+        if side_tables && (config.fields.is_some() || config.query.is_some() || config.schema.is_some()) {
+            return Err("--exchanges, --features, --resample and --throughput-json need the full column set and cannot be combined with --fields, --query or --schema".into());
+        }
+        Ok(Self {
+            selected_fields,
+            packet_type_filter,
+            urb_status_filter,
+            schema,
+            #[cfg(feature = "wasm")]
+            wasm_decoder: config.wasm_decoder.as_deref().map(crate::wasm_decoder::WasmDecoder::load).transpose()?,
+        })
+    }
+
+    /// Whole-capture record passes, then the DataFrame with its derived columns.
+    ///
+    /// Records are put in `frame_number` order first (stable, so ISO segments of one frame
+    /// keep their index order), making the output independent of the order they were read in.
+    pub(crate) fn dataframe(&mut self, mut records: Vec<UsbPacketRecord>, config: &ConverterConfig) -> Result<DataFrame> {
+        records.sort_by_key(|r| r.frame_number);
+        if !config.exclude_frames.is_empty() {
+            let before = records.len();
+            records.retain(|r| !config.exclude_frames.iter().any(|range| range.contains(r.frame_number)));
+            info!("Excluded {} rows in {} --exclude-frames range(s)", before - records.len(), config.exclude_frames.len());
+        }
+
+        if config.device_name {
+            enrich_device_names(&mut records);
+        }
+
+        if config.check_transactions {
+            let gap_frames = mark_transaction_gaps(&mut records);
+            if gap_frames.is_empty() {
+                info!("Transaction ids are contiguous in both directions");
+            } else {
+                warn!("⚠️  {} transaction id gaps (possible dropped URBs) at frames: {:?}", gap_frames.len(), gap_frames);
+            }
+        }
+
+        if config.check_drops {
+            let spans = find_drop_spans(&records);
+            if spans.is_empty() {
+                info!("Every URB has its submit and completion: no sign of dropped usbmon events");
+            } else {
+                let unmatched: usize = spans.iter().map(|s| s.unmatched).sum();
+                warn!("⚠️  {} half-seen URBs in {} regions suggest dropped usbmon events; timing there is unreliable", unmatched, spans.len());
+                for span in &spans {
+                    warn!("   frames {}-{}: {} unmatched", span.first_frame, span.last_frame, span.unmatched);
+                }
+            }
+        }
+
+        if config.coalesce_urbs {
+            let before = records.len();
+            records = coalesce_urbs(records);
+            info!("Coalesced submit/complete URBs: {} rows -> {} transactions", before, records.len());
+        }
+
+        if config.anonymize {
+            info!("Anonymizing host-identifying fields and rebasing timestamps");
+            anonymize_records(&mut records);
+        }
+
+        self.derive_columns(create_dataframe(records)?, config)
+    }
+
+    /// Rows restored from a `--resume` checkpoint, which stores them before any pass ran:
+    /// `--exclude-frames` and the derived columns of [`Self::dataframe`] are applied here
+    pub(crate) fn restored(&mut self, mut df: DataFrame, config: &ConverterConfig) -> Result<DataFrame> {
+        let excluded = config
+            .exclude_frames
+            .iter()
+            .map(|range| col("frame_number").gt_eq(lit(range.start)).and(col("frame_number").lt_eq(lit(range.end))))
+            .reduce(|a, b| a.or(b));
+        if let Some(excluded) = excluded {
+            df = df.lazy().filter(excluded.not()).collect()?;
+        }
+        self.derive_columns(df, config)
+    }
+
+    fn derive_columns(&mut self, mut df: DataFrame, config: &ConverterConfig) -> Result<DataFrame> {
+        if let Some(decimals) = config.adc_precision {
+            df = round_adc_columns(df, decimals)?;
+        }
+        df = add_experimental_columns(df, &config.experimental_fields)?;
+        if config.energy {
+            df = add_cumulative_energy(df)?;
+        }
+        #[cfg(feature = "wasm")]
+        if let Some(decoder) = &mut self.wasm_decoder {
+            df = crate::wasm_decoder::add_wasm_columns(df, decoder)?;
+        }
+        Ok(df)
+    }
+
+    /// Deduplication, row filters, `--query`, `--fields` and `--schema`, in that order
+    pub(crate) fn select(&self, mut df: DataFrame, config: &ConverterConfig) -> Result<DataFrame> {
+        // Drop duplicate rows within this capture
+        if let Some(key) = config.dedup {
+            let before = df.height();
+            df = df.unique_stable(Some(&[key.column().to_string()]), UniqueKeepStrategy::First, None)?;
+            info!("Deduplicated on {}: removed {} duplicate rows", key.column(), before - df.height());
+        }
+
+        if let Some(filter) = &self.packet_type_filter {
+            let before = df.height();
+            df = df.lazy().filter(filter.clone()).collect()?;
+            info!("Kept only packet types {:?}: {} rows -> {} rows", config.only_packet_types.as_deref().unwrap_or_default(), before, df.height());
+        }
+        if let Some(filter) = &self.urb_status_filter {
+            let before = df.height();
+            df = df.lazy().filter(filter.clone()).collect()?;
+            info!("URB status filter matched {} of {} rows", df.height(), before);
+        }
+        if let Some(id) = config.transaction_id {
+            let before = df.height();
+            df = filter_transaction(df, id)?;
+            info!("Transaction id {}: {} of {} rows", id, df.height(), before);
+        }
+
+        if let Some(query) = &config.query {
+            let before = df.height();
+            df = run_query(&df, query)?;
+            info!("Applied --query: {} rows -> {} rows", before, df.height());
+        }
+
+        if let Some(fields) = &self.selected_fields {
+            df = df.select(fields.iter().map(String::as_str))?;
+        }
+        if let Some(schema) = &self.schema {
+            df = conform_to_schema(df, schema)?;
+        }
+        Ok(df)
+    }
+}
+
+/// Rows of one exchange, both directions, by decoded `km_transaction_id` (`--transaction-id`)
+fn filter_transaction(df: DataFrame, id: u8) -> Result<DataFrame> {
+    if df.column("km_transaction_id")?.null_count() == df.height() {
+        return Err(format!(
+            "--transaction-id {}: no row has a decoded KM003C header, so there are no transaction ids to match \
+             (the capture has no KM003C bulk payloads for this device, or the filters removed them)",
+            id
+        )
+        .into());
+    }
+    Ok(df.lazy().filter(col("km_transaction_id").eq(lit(id as u32))).collect()?)
+}
+
+/// Final summary line: wall time, sustained packet rate and, when known, peak memory
+fn run_summary(elapsed: Duration, packets: usize, records: usize, peak_kib: Option<u64>) -> String {
+    let secs = elapsed.as_secs_f64();
+    let rate = if secs > 0.0 { packets as f64 / secs } else { 0.0 };
+    let mut summary = format!("Finished in {:.2}s: {} packets ({:.0} packets/s), {} records written", secs, packets, rate, records);
+    if let Some(kib) = peak_kib {
+        summary.push_str(&format!(", peak memory {:.1} MiB", kib as f64 / 1024.0));
+    }
+    summary
+}
+
+/// Peak resident set size of this process, from `/proc/self/status` (Linux only)
+fn peak_memory_kib() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Log each answered GetData whose response body is shorter or longer than its mask asks for
+fn report_incomplete_responses(exchanges: &DataFrame) -> Result<()> {
+    let complete = exchanges.column("response_complete")?.bool()?;
+    let frames = exchanges.column("response_frame")?.u32()?;
+    let expected = exchanges.column("expected_len")?.u32()?;
+    let returned = exchanges.column("response_len")?.u32()?;
+    for row in (0..exchanges.height()).filter(|&row| complete.get(row) == Some(false)) {
+        warn!(
+            "Frame {}: response body is {} bytes, request expects {}",
+            frames.get(row).unwrap_or_default(),
+            returned.get(row).unwrap_or_default(),
+            expected.get(row).unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+/// Write the final rows to `output` in the configured format
+fn write_output(
+    final_df: &DataFrame,
+    output: &Path,
+    config: &ConverterConfig,
+    compression: ParquetCompression,
+    run_metadata: &RunMetadata,
+) -> Result<()> {
+    if let Some(dir) = output.parent().filter(|_| config.partition_by.is_some()) {
+        std::fs::create_dir_all(dir)?;
+    }
+    // Write next to the output and rename over it, so an interrupted write never leaves a truncated file
+    let temp_output = {
+        let mut path = output.as_os_str().to_owned();
+        path.push(".tmp");
+        PathBuf::from(path)
+    };
+    let mut file = std::fs::File::create(&temp_output)?;
+    match config.format {
+        OutputFormat::Parquet => {
+            info!("Saving to Parquet file: {:?}", output);
+            ParquetWriter::new(&mut file)
+                .with_compression(compression)
+                .with_key_value_metadata(Some(run_metadata.key_value_metadata()))
+                .finish(&mut final_df.clone())?;
+        }
+        OutputFormat::Csv => {
+            info!("Saving to CSV file: {:?}", output);
+            CsvWriter::new(&mut file).finish(&mut final_df.clone())?;
+        }
+        OutputFormat::Influx => {
+            info!("Saving ADC rows as InfluxDB line protocol: {:?}", output);
+            let mut writer = std::io::BufWriter::new(&mut file);
+            let lines = write_line_protocol(final_df, &mut writer)?;
+            writer.flush()?;
+            info!("Wrote {} line protocol points", lines);
+        }
+        OutputFormat::Framestream => {
+            info!("Saving KM003C payloads as a length-prefixed frame stream: {:?}", output);
+            let mut writer = std::io::BufWriter::new(&mut file);
+            let frames = write_frame_stream(final_df, &mut writer)?;
+            writer.flush()?;
+            info!("Wrote {} protocol frames", frames);
+        }
+    }
+
+    drop(file);
+    std::fs::rename(&temp_output, output)?;
+    if config.manifest {
+        write_manifest(final_df, output, run_metadata)?;
+    }
+    Ok(())
+}
+
+/// Convert several captures with one configuration (repeated `--input`).
+///
+/// Each input gets its own output from `--output-dir` / `--output-template`, which are
+/// required when there is more than one input. Stops at the first failing capture.
+pub fn convert_batch(config: &ConverterConfig, inputs: &[PathBuf]) -> Result<()> {
+    if inputs.len() > 1 && config.output_dir.is_none() && config.output_template.is_none() {
+        return Err("Converting several inputs needs --output-dir or --output-template to name each output".into());
+    }
+    for (index, input) in inputs.iter().enumerate() {
+        if inputs.len() > 1 {
+            info!("\n[{}/{}] {:?}", index + 1, inputs.len(), input);
+        }
+        let mut config = config.clone();
+        config.input = input.clone();
+        convert(&config).inspect_err(|e| error!("❌ Failed to convert {:?}: {}", input, e))?;
+    }
+    Ok(())
+}
+
+/// Output path named by `--output-dir` / `--output-template`, or `None` to use `--output`
+pub(crate) fn templated_output(config: &ConverterConfig, stem: &str, device_address: u8) -> Result<Option<PathBuf>> {
+    if config.output_dir.is_none() && config.output_template.is_none() {
+        return Ok(None);
+    }
+    if config.partition_by.is_some() {
+        return Err("--output-dir / --output-template cannot be combined with --partition-by".into());
+    }
+    let name = match &config.output_template {
+        Some(template) => expand_template("--output-template", template, stem, device_address)?,
+        None => format!("{}.{}", stem, config.format.extension()),
+    };
+    Ok(Some(match &config.output_dir {
+        Some(dir) => dir.join(name),
+        None => PathBuf::from(name),
+    }))
+}
+
+/// Run the `--dedup-key` checks in order; the first that finds the new rows already
+/// stored logs why and stops the append
+fn already_stored(existing: &DataFrame, new: &DataFrame, session_id: &str, keys: &[AppendDedupKey]) -> Result<bool> {
+    if keys.contains(&AppendDedupKey::None) && keys.len() > 1 {
+        return Err("--dedup-key none cannot be combined with other keys".into());
+    }
+    for key in keys {
+        let overlap = match key {
+            AppendDedupKey::Session => {
+                if session_already_stored(existing, new, session_id)? {
+                    warn!("⚠️  Session ID '{}' already exists on the same bus. Skipping to prevent duplicates.", session_id);
+                    return Ok(true);
+                }
+                continue;
+            }
+            AppendDedupKey::Hash => key_overlap(existing, new, &["packet_hash"])?,
+            // usbmon recycles URB ids, so the URB's own timestamp and type narrow them to one submission
+            AppendDedupKey::UrbId => key_overlap(existing, new, &["bus_id", "urb_id", "urb_type", "urb_ts_sec", "urb_ts_usec"])?,
+            AppendDedupKey::None => continue,
+        };
+        if let Some(overlap) = overlap {
+            info!("{:?} overlap with existing data: {} of {} new rows", key, overlap, new.height());
+            if overlap > 0 {
+                warn!("⚠️  Detected duplicate data (packets already stored). Skipping to prevent duplicates.");
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// Number of new rows whose values in `columns` match a stored row, or `None` when either
+/// side lacks one of the columns
+fn key_overlap(existing: &DataFrame, new: &DataFrame, columns: &[&str]) -> Result<Option<usize>> {
+    // Compared as strings so Categorical columns match files that stored them as plain strings
+    let keys = |df: &DataFrame| -> Result<Option<Vec<String>>> {
+        let mut parts = Vec::with_capacity(columns.len());
+        for name in columns {
+            let Ok(column) = df.column(name) else {
+                return Ok(None);
+            };
+            parts.push(column.cast(&DataType::String)?.str()?.clone());
+        }
+        let joined = (0..df.height())
+            .map(|row| parts.iter().map(|part| part.get(row).unwrap_or_default()).collect::<Vec<_>>().join("\u{1f}"))
+            .collect();
+        Ok(Some(joined))
+    };
+    let (Some(stored), Some(new)) = (keys(existing)?, keys(new)?) else {
+        return Ok(None);
+    };
+    let stored: std::collections::HashSet<String> = stored.into_iter().collect();
+    Ok(Some(new.iter().filter(|key| stored.contains(*key)).count()))
+}
+
+/// Decoded float columns rounded by `--adc-precision`
+const ADC_FLOAT_COLUMNS: [&str; 4] = ["adc_vbus_v", "adc_ibus_a", "adc_power_w", "adc_temp_c"];
+
+/// Round the ADC measurement columns to `decimals` places (`--adc-precision`)
+pub(crate) fn round_adc_columns(df: DataFrame, decimals: u32) -> Result<DataFrame> {
+    let rounded: Vec<Expr> = ADC_FLOAT_COLUMNS
+        .into_iter()
+        .filter(|c| df.get_column_index(c).is_some())
+        .map(|c| col(c).round(decimals, RoundMode::HalfAwayFromZero))
+        .collect();
+    Ok(df.lazy().with_columns(rounded).collect()?)
+}
+
+/// Columns `--sort-on-write` orders by, when present: wall-clock URB time first so
+/// sessions appended from different captures interleave correctly, then relative time
+const SORT_ON_WRITE_KEYS: [&str; 3] = ["urb_ts_sec", "urb_ts_usec", "timestamp"];
+
+/// Stable sort of the rows about to be written by capture time (`--sort-on-write`)
+fn sort_by_capture_time(df: DataFrame) -> Result<DataFrame> {
+    let keys: Vec<&str> = SORT_ON_WRITE_KEYS.into_iter().filter(|k| df.get_column_index(k).is_some()).collect();
+    if keys.is_empty() {
+        return Ok(df);
+    }
+    Ok(df.sort(keys, SortMultipleOptions::default().with_maintain_order(true))?)
+}
+
+/// Whether the packet at 0-based `position` survives `--sample-rate`: the 1st, N+1th, ...
+fn keeps_sample(position: usize, sample_rate: Option<usize>) -> bool {
+    sample_rate.is_none_or(|n| position.is_multiple_of(n))
+}
+
+/// Maximum drift between capture origins before `--since` treats the timeline as reset
+const SINCE_ORIGIN_TOLERANCE_US: i64 = 1_000_000;
+
+/// Whether `existing` already has rows of `session_id` on one of the buses in `new`, so a
+/// dual-controller capture can be appended one bus at a time under the same session ID
+fn session_already_stored(existing: &DataFrame, new: &DataFrame, session_id: &str) -> Result<bool> {
+    let stored = existing.clone().lazy().filter(col("session_id").eq(lit(session_id))).collect()?;
+    if stored.height() == 0 {
+        return Ok(false);
+    }
+    // Files written before bus_id was tracked only know the session
+    let (Ok(stored_buses), Ok(new_buses)) = (stored.column("bus_id"), new.column("bus_id")) else {
+        return Ok(true);
+    };
+    let stored_buses: Vec<Option<u32>> = stored_buses.u32()?.unique()?.into_iter().collect();
+    Ok(new_buses.u32()?.into_iter().any(|bus| stored_buses.contains(&bus)))
+}
+
+/// Newest stored frame of a session, used by `--since` incremental appends
+struct SinceMark {
+    max_timestamp: f64,
+    /// Wall-clock start of the stored capture (URB time minus relative time), in microseconds
+    origin_us: i64,
+}
+
+/// Wall-clock instant at which a capture's relative timeline started
+fn capture_origin_us(timestamp: f64, urb_ts_sec: u64, urb_ts_usec: u32) -> i64 {
+    (urb_ts_sec as i64 * 1_000_000 + urb_ts_usec as i64) - (timestamp * 1e6).round() as i64
+}
+
+/// Run a `--query` SQL statement against the converted rows, registered as table `packets`
+fn run_query(df: &DataFrame, query: &str) -> Result<DataFrame> {
+    let mut ctx = polars::sql::SQLContext::new();
+    ctx.register("packets", df.clone().lazy());
+    ctx.execute(query)
+        .and_then(|lf| lf.collect())
+        .map_err(|e| format!("Invalid --query: {}", e).into())
+}
+
+/// Build the `--only-packet-types` row filter, rejecting unknown type names
+fn packet_type_filter(types: &[String]) -> Result<Expr> {
+    let mut filter = lit(false);
+    for name in types {
+        let condition = match PacketTypeFilter::from_name(name.trim()) {
+            Some(PacketTypeFilter::Header(packet_type)) => col("km_packet_type").eq(lit(packet_type as u32)),
+            Some(PacketTypeFilter::PutData(attribute)) => {
+                col("km_logical_attributes").and(lit(attribute as u32)).neq(lit(0u32))
+            }
+            None => {
+                let known: Vec<&str> = PacketTypeFilter::names().collect();
+                return Err(format!("Unknown packet type '{}' in --only-packet-types (known: {})", name.trim(), known.join(", ")).into());
+            }
+        };
+        filter = filter.or(condition);
+    }
+    Ok(filter)
+}
+
+/// `--urb-status` / `--errors-only` row filter; `None` when neither is given
+fn urb_status_filter(statuses: &[String], errors_only: bool) -> Result<Option<Expr>> {
+    let status = || col("urb_status").cast(DataType::String);
+    let mut conditions = Vec::new();
+    for value in statuses {
+        let canonical = normalize_urb_status(value);
+        if canonical == "Unknown" {
+            return Err(format!("Invalid --urb-status '{}': expected an errno such as 0 or -32", value).into());
+        }
+        conditions.push(status().eq(lit(canonical)));
+    }
+    if errors_only {
+        // usbmon logs every submit as -EINPROGRESS (-115) before it completes; that is not a failure
+        let pending_submit = col("urb_type").cast(DataType::String).eq(lit("S")).and(status().eq(lit("-115")));
+        conditions.push(status().neq(lit("0")).and(status().neq(lit("Unknown"))).and(pending_submit.not()));
+    }
+    Ok(conditions.into_iter().reduce(|a, b| a.or(b)))
+}
+
+/// Columns always emitted by `--fields`, so rows stay identifiable and decodable
+const ALWAYS_KEPT_FIELDS: [&str; 2] = ["frame_number", "payload_hex"];
+
+/// Resolve a `--fields` allowlist into output column order, rejecting unknown names
+fn select_fields(fields: &[String]) -> Result<Vec<String>> {
+    let schema = create_dataframe(Vec::new())?;
+    let valid: Vec<&str> = schema.get_column_names().into_iter().map(|c| c.as_str()).collect();
+    let unknown: Vec<&str> = fields
+        .iter()
+        .map(|f| f.trim())
+        .filter(|f| !valid.contains(f))
+        .collect();
+    if !unknown.is_empty() {
+        return Err(format!("Unknown field(s): {}. Valid columns: {}", unknown.join(", "), valid.join(", ")).into());
+    }
+    Ok(valid
+        .into_iter()
+        .filter(|c| ALWAYS_KEPT_FIELDS.contains(c) || fields.iter().any(|f| f.trim() == *c))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Percent-escape a partition value into a single safe path component
+fn escape_partition_value(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Hive-style location of one partition's data file below the dataset root
+fn partition_path(root: &Path, column: &str, value: &str, format: OutputFormat) -> PathBuf {
+    root.join(format!("{}={}", column, escape_partition_value(value))).join(format!("data.{}", format.extension()))
+}
+
+/// One session's file in a `km_packet_type=<type>` partition; undecoded rows go to `unknown`
+fn packet_type_partition_path(root: &Path, packet_type: Option<u32>, session_id: &str, format: OutputFormat) -> PathBuf {
+    let value = packet_type.map_or_else(|| "unknown".to_string(), |t| t.to_string());
+    root.join(format!("km_packet_type={}", value))
+        .join(format!("{}.{}", escape_partition_value(session_id), format.extension()))
+}
+
+/// Files already written for this session in any packet-type partition
+fn packet_type_partition_files(root: &Path, session_id: &str, format: OutputFormat) -> Result<Vec<PathBuf>> {
+    let Ok(entries) = std::fs::read_dir(root) else {
+        return Ok(Vec::new());
+    };
+    let file_name = format!("{}.{}", escape_partition_value(session_id), format.extension());
+    let mut files = Vec::new();
+    for entry in entries {
+        let dir = entry?.path();
+        let is_partition = dir.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with("km_packet_type="));
+        if is_partition && dir.join(&file_name).is_file() {
+            files.push(dir.join(&file_name));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn load_since_mark(output: &Path, session_id: &str, device_address: u8) -> Result<Option<SinceMark>> {
+    let path = output.to_str().ok_or("Output path is not valid UTF-8")?;
+    let mut stored = LazyFrame::scan_parquet(PlPath::new(path), ScanArgsParquet::default())?;
+    // After --continuous-time the session's own clock is timestamp_session
+    if stored.collect_schema()?.contains("timestamp_session") {
+        stored = stored.with_column(col("timestamp_session").fill_null(col("timestamp")).alias("timestamp"));
+    }
+    let session_rows = stored
+        .filter(
+            col("session_id")
+                .eq(lit(session_id))
+                .and(col("device_address").eq(lit(device_address as u32))),
+        )
+        .sort(["timestamp"], SortMultipleOptions::default().with_order_descending(true))
+        .select([col("timestamp"), col("urb_ts_sec"), col("urb_ts_usec")])
+        .limit(1)
+        .collect()?;
+
+    if session_rows.height() == 0 {
+        return Ok(None);
+    }
+    let max_timestamp = session_rows.column("timestamp")?.f64()?.get(0).unwrap_or(0.0);
+    let urb_ts_sec = session_rows.column("urb_ts_sec")?.u64()?.get(0).unwrap_or(0);
+    let urb_ts_usec = session_rows.column("urb_ts_usec")?.u32()?.get(0).unwrap_or(0);
+    Ok(Some(SinceMark {
+        max_timestamp,
+        origin_us: capture_origin_us(max_timestamp, urb_ts_sec, urb_ts_usec),
+    }))
+}
+
+/// Refuse to replace an existing output unless `--append` or `--overwrite` says what to do with it
+pub(crate) fn check_overwrite(output: &Path, config: &ConverterConfig) -> Result<()> {
+    if output.exists() && !config.append && !config.overwrite {
+        return Err(format!("Output {:?} already exists; pass --append to add to it or --overwrite to replace it", output).into());
+    }
+    Ok(())
+}
+
+/// Files a conversion to `output` will create or replace: the DuckDB database, the
+/// normalized tables, the session's existing partitions or the output itself, then any side tables
+fn planned_outputs(output: &Path, session_partitions: &[PathBuf], config: &ConverterConfig) -> Vec<PathBuf> {
+    if let Some(db) = &config.duckdb {
+        return vec![db.clone()];
+    }
+    let mut paths = if config.normalize {
+        TABLE_SUFFIXES.iter().map(|suffix| normalized_path(output, suffix)).collect()
+    } else if !session_partitions.is_empty() {
+        session_partitions.to_vec()
+    } else {
+        vec![output.to_path_buf()]
+    };
+    paths.extend(config.resample_ms.map(|ms| normalized_path(output, &format!("resampled_{}ms", ms))));
+    if config.exchanges {
+        paths.push(normalized_path(output, "exchanges"));
+    }
+    if config.feature_bytes.is_some() {
+        paths.push(normalized_path(output, "features"));
+    }
+    paths.extend(config.throughput_json.clone());
+    paths
+}
+
+/// Make sure the directory `path` will be written into exists, creating it with `--mkdir`
+fn ensure_output_dir(path: &Path, mkdir: bool) -> Result<()> {
+    let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) else {
+        return Ok(());
+    };
+    if dir.is_dir() {
+        return Ok(());
+    }
+    if !mkdir {
+        return Err(format!("Output directory {:?} does not exist; create it or pass --mkdir", dir).into());
+    }
+    info!("Creating missing output directory {:?}", dir);
+    std::fs::create_dir_all(dir)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_packet;
+    use crate::test_support::{adc_put_data, bulk, packet_with};
+
+    fn record(session: &str, timestamp: &str, urb_ts_sec: &str, urb_ts_usec: &str) -> crate::UsbPacketRecord {
+        let usb = [("usb.device_address", "16"), ("usb.urb_ts_sec", urb_ts_sec), ("usb.urb_ts_usec", urb_ts_usec)];
+        process_packet(&packet_with(&[("frame.time_relative", timestamp)], &usb), session, false).unwrap()
+    }
+
+    #[test]
+    fn since_mark_uses_newest_row_of_the_session() {
+        let path = std::env::temp_dir().join(format!("since_mark_{}.parquet", std::process::id()));
+        let mut df = create_dataframe(vec![
+            record("a", "1.0", "100", "0"),
+            record("a", "2.5", "101", "500000"),
+            record("b", "9.0", "500", "0"),
+        ])
+        .unwrap();
+        ParquetWriter::new(std::fs::File::create(&path).unwrap()).finish(&mut df).unwrap();
+
+        let mark = load_since_mark(&path, "a", 16).unwrap().unwrap();
+        assert_eq!(mark.max_timestamp, 2.5);
+        assert_eq!(mark.origin_us, 99_000_000);
+        assert!(load_since_mark(&path, "missing", 16).unwrap().is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn only_packet_types_matches_header_types_and_put_data_contents() {
+        let df = create_dataframe(vec![bulk(1, "0", "0c:0a:02:00"), bulk(2, "1", &adc_put_data(0x0a, 0, 0))]).unwrap();
+        let kept = |types: &[&str]| {
+            let types: Vec<String> = types.iter().map(|t| t.to_string()).collect();
+            df.clone().lazy().filter(packet_type_filter(&types).unwrap()).collect().unwrap().height()
+        };
+
+        assert_eq!(kept(&["get_data"]), 1);
+        assert_eq!(kept(&["adc"]), 1);
+        assert_eq!(kept(&["adc", " get_data"]), 2);
+        assert_eq!(kept(&["pd"]), 0);
+        let err = packet_type_filter(&["adcs".to_string()]).unwrap_err().to_string();
+        assert!(err.contains("Unknown packet type 'adcs'") && err.contains("put_data"));
+    }
+
+    #[test]
+    fn urb_status_filter_isolates_failed_transfers() {
+        let urb = |urb_type: &str, status: &str| {
+            let usb = [("usb.device_address", "16"), ("usb.urb_type", urb_type), ("usb.urb_status", status)];
+            crate::test_support::record(&[("frame.number", "1")], &usb)
+        };
+        let df = create_dataframe(vec![urb("S", "-115"), urb("C", "0"), urb("C", "-32"), urb("C", "-ETIMEDOUT (-110)")]).unwrap();
+        let kept = |statuses: &[&str], errors_only: bool| {
+            let statuses: Vec<String> = statuses.iter().map(|s| s.to_string()).collect();
+            let filter = urb_status_filter(&statuses, errors_only).unwrap().unwrap();
+            df.clone().lazy().filter(filter).collect().unwrap().height()
+        };
+
+        assert_eq!(kept(&["-32"], false), 1);
+        assert_eq!(kept(&["0", "-115"], false), 2);
+        assert_eq!(kept(&[], true), 2);
+        assert!(urb_status_filter(&[], false).unwrap().is_none());
+        assert!(urb_status_filter(&["stall".to_string()], false).is_err());
+    }
+
+    #[test]
+    fn rows_come_out_in_frame_order_whatever_the_read_order() {
+        let records: Vec<_> = (1..=6)
+            .map(|frame| {
+                let mut record = record("a", &format!("{}.0", frame), "100", "0");
+                record.frame_number = frame;
+                record
+            })
+            .collect();
+        let mut shuffled = records.clone();
+        shuffled.reverse();
+        shuffled.rotate_left(2);
+
+        let config = ConverterConfig::new("in.pcapng");
+        let mut pipeline = RowPipeline::new(&config).unwrap();
+        let in_order = pipeline.dataframe(records, &config).unwrap();
+        let reordered = pipeline.dataframe(shuffled, &config).unwrap();
+        assert!(in_order.equals_missing(&reordered));
+        let frames: Vec<u32> = reordered.column("frame_number").unwrap().u32().unwrap().into_no_null_iter().collect();
+        assert_eq!(frames, [1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn restored_rows_get_exclusions_and_rounding() {
+        let records: Vec<_> = (1..=4)
+            .map(|frame| {
+                let mut record = record("a", &format!("{}.0", frame), "100", "0");
+                record.frame_number = frame;
+                record.adc_vbus_v = Some(5.123456);
+                record
+            })
+            .collect();
+        let config = ConverterConfig::new("in.pcapng").exclude_frames("2-3".parse().unwrap()).adc_precision(2);
+        let mut pipeline = RowPipeline::new(&config).unwrap();
+        let restored = pipeline.restored(create_dataframe(records).unwrap(), &config).unwrap();
+        let frames: Vec<u32> = restored.column("frame_number").unwrap().u32().unwrap().into_no_null_iter().collect();
+        assert_eq!(frames, [1, 4]);
+        assert_eq!(restored.column("adc_vbus_v").unwrap().f64().unwrap().get(0), Some(5.12));
+    }
+
+    #[test]
+    fn frame_ranges_are_inclusive_and_validated() {
+        let range: crate::config::FrameRange = "1200-1350".parse().unwrap();
+        assert!(range.contains(1200) && range.contains(1350) && !range.contains(1351));
+        assert_eq!("7".parse::<crate::config::FrameRange>().unwrap(), crate::config::FrameRange { start: 7, end: 7 });
+        for bad in ["5-3", "a-b", "1-", "-4", "1-2-3"] {
+            assert!(bad.parse::<crate::config::FrameRange>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn append_guard_counts_packet_hash_overlap_not_urb_ids() {
+        // usbmon reuses URB ids, so these two unrelated captures share every urb_id
+        let existing = df!["urb_id" => ["0xffff1", "0xffff2"], "packet_hash" => ["aa", "bb"]].unwrap();
+        let distinct = df!["urb_id" => ["0xffff1", "0xffff2"], "packet_hash" => ["cc", "dd"]].unwrap();
+        let repeated = df!["urb_id" => ["0xffff2", "0xffff3"], "packet_hash" => ["bb", "ee"]].unwrap();
+
+        assert_eq!(key_overlap(&existing, &distinct, &["packet_hash"]).unwrap(), Some(0));
+        assert_eq!(key_overlap(&existing, &repeated, &["packet_hash"]).unwrap(), Some(1));
+        assert_eq!(key_overlap(&existing.drop("packet_hash").unwrap(), &repeated, &["packet_hash"]).unwrap(), None);
+    }
+
+    #[test]
+    fn dedup_keys_choose_the_append_guard() {
+        let existing = df![
+            "session_id" => ["a"], "bus_id" => [1u32], "urb_id" => ["0xffff1"], "urb_type" => ["S"],
+            "urb_ts_sec" => [10u64], "urb_ts_usec" => [5u32], "packet_hash" => ["aa"],
+        ]
+        .unwrap();
+        // Same URB re-captured under a new session with a different payload (so a new hash)
+        let new = existing.clone().lazy().with_columns([lit("b").alias("session_id"), lit("bb").alias("packet_hash")]).collect().unwrap();
+        let stored = |keys: &[AppendDedupKey]| already_stored(&existing, &new, "b", keys).unwrap();
+
+        assert!(!stored(&[AppendDedupKey::Session, AppendDedupKey::Hash]));
+        assert!(stored(&[AppendDedupKey::UrbId]));
+        assert!(already_stored(&existing, &existing, "a", &[AppendDedupKey::Session]).unwrap());
+        assert!(!already_stored(&existing, &existing, "a", &[AppendDedupKey::None]).unwrap());
+        assert!(already_stored(&existing, &new, "b", &[AppendDedupKey::None, AppendDedupKey::Hash]).is_err());
+    }
+
+    #[test]
+    fn session_guard_allows_the_same_session_on_another_bus() {
+        let existing = df!["session_id" => ["dual", "dual", "other"], "bus_id" => [1u32, 1, 2]].unwrap();
+        let bus = |id: u32| df!["session_id" => ["dual"], "bus_id" => [id]].unwrap();
+
+        assert!(session_already_stored(&existing, &bus(1), "dual").unwrap());
+        assert!(!session_already_stored(&existing, &bus(2), "dual").unwrap());
+        assert!(!session_already_stored(&existing, &bus(1), "new").unwrap());
+        assert!(session_already_stored(&existing.drop("bus_id").unwrap(), &bus(2), "dual").unwrap());
+    }
+
+    #[test]
+    fn transaction_filter_keeps_both_directions_and_needs_headers() {
+        let df = df![
+            "direction" => ["H->D", "D->H", "H->D", "H->D"],
+            "km_transaction_id" => [Some(7u32), Some(7), Some(8), None],
+        ]
+        .unwrap();
+        let exchange = filter_transaction(df.clone(), 7).unwrap();
+        assert_eq!(exchange.column("direction").unwrap().str().unwrap().into_no_null_iter().collect::<Vec<_>>(), ["H->D", "D->H"]);
+
+        let undecoded = df!["km_transaction_id" => [None::<u32>, None]].unwrap();
+        assert!(filter_transaction(undecoded, 7).unwrap_err().to_string().contains("no row has a decoded KM003C header"));
+    }
+
+    #[test]
+    fn adc_precision_rounds_only_measurement_columns() {
+        let df = df![
+            "timestamp" => [0.123456789],
+            "adc_vbus_v" => [Some(5.123456)],
+            "adc_ibus_a" => [None::<f64>],
+            "adc_power_w" => [Some(-0.0049)],
+        ]
+        .unwrap();
+        let rounded = round_adc_columns(df, 2).unwrap();
+        assert_eq!(rounded.column("adc_vbus_v").unwrap().f64().unwrap().get(0), Some(5.12));
+        assert_eq!(rounded.column("adc_ibus_a").unwrap().f64().unwrap().get(0), None);
+        assert_eq!(rounded.column("adc_power_w").unwrap().f64().unwrap().get(0), Some(-0.0));
+        assert_eq!(rounded.column("timestamp").unwrap().f64().unwrap().get(0), Some(0.123456789));
+    }
+
+    #[test]
+    fn sample_rate_keeps_every_nth_packet_from_the_first() {
+        let kept: Vec<usize> = (0..10).filter(|&i| keeps_sample(i, Some(4))).collect();
+        assert_eq!(kept, [0, 4, 8]);
+        assert!((0..10).all(|i| keeps_sample(i, None) && keeps_sample(i, Some(1))));
+    }
+
+    #[test]
+    fn run_summary_reports_rate_and_peak_memory() {
+        let summary = run_summary(Duration::from_millis(2500), 5000, 1200, Some(2048));
+        assert_eq!(summary, "Finished in 2.50s: 5000 packets (2000 packets/s), 1200 records written, peak memory 2.0 MiB");
+        assert!(!run_summary(Duration::ZERO, 0, 0, None).contains("peak"));
+    }
+
+    #[test]
+    fn select_fields_keeps_identity_columns_and_rejects_unknown() {
+        let fields = select_fields(&["timestamp".to_string(), " direction".to_string()]).unwrap();
+        assert_eq!(fields, ["frame_number", "timestamp", "direction", "payload_hex"]);
+
+        let err = select_fields(&["timestmp".to_string()]).unwrap_err().to_string();
+        assert!(err.contains("Unknown field(s): timestmp"));
+        assert!(err.contains("session_id"));
+    }
+
+    #[test]
+    fn query_runs_against_packets_table() {
+        let df = create_dataframe(vec![record("a", "1.0", "100", "0"), record("a", "2.5", "101", "0")]).unwrap();
+        let result = run_query(&df, "SELECT frame_number, timestamp FROM packets WHERE timestamp > 2").unwrap();
+        assert_eq!(result.shape(), (1, 2));
+
+        let err = run_query(&df, "SELECT nope FROM packets").unwrap_err().to_string();
+        assert!(err.starts_with("Invalid --query:"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_empty_side_tables_before_reading() {
+        assert!(RowPipeline::new(&ConverterConfig::new("x").resample(0)).is_err());
+        assert!(RowPipeline::new(&ConverterConfig::new("x").features(0)).is_err());
+        assert!(RowPipeline::new(&ConverterConfig::new("x").resample(10).features(8)).is_ok());
+    }
+
+    #[test]
+    fn side_tables_need_the_full_column_set() {
+        let narrowed = ConverterConfig::new("x").fields(vec!["frame_number".to_string(), "payload_hex".to_string()]);
+        let err = RowPipeline::new(&narrowed.clone().exchanges(true)).err().unwrap().to_string();
+        assert!(err.contains("--exchanges"), "{}", err);
+        assert!(RowPipeline::new(&narrowed.features(8)).is_err());
+        assert!(RowPipeline::new(&ConverterConfig::new("x").query("SELECT * FROM packets").exchanges(true)).is_err());
+    }
+
+    #[test]
+    fn partition_path_is_hive_style_and_escaped() {
+        let root = Path::new("dataset");
+        assert_eq!(
+            partition_path(root, "session_id", "orig_adc_1000hz.6", OutputFormat::Parquet),
+            root.join("session_id=orig_adc_1000hz.6").join("data.parquet")
+        );
+        assert_eq!(
+            partition_path(root, "session_id", "a/b c", OutputFormat::Csv),
+            root.join("session_id=a%2Fb%20c").join("data.csv")
+        );
+    }
+
+    #[test]
+    fn packet_type_partitions_hold_one_file_per_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let adc = packet_type_partition_path(root, Some(0x41), "run 1", OutputFormat::Parquet);
+        assert_eq!(adc, root.join("km_packet_type=65").join("run%201.parquet"));
+        let unknown = packet_type_partition_path(root, None, "run 1", OutputFormat::Parquet);
+        assert_eq!(unknown, root.join("km_packet_type=unknown").join("run%201.parquet"));
+
+        assert!(packet_type_partition_files(root, "run 1", OutputFormat::Parquet).unwrap().is_empty());
+        for path in [&adc, &unknown, &packet_type_partition_path(root, Some(0x41), "other", OutputFormat::Parquet)] {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, b"").unwrap();
+        }
+        assert_eq!(packet_type_partition_files(root, "run 1", OutputFormat::Parquet).unwrap(), [adc, unknown]);
+    }
+
+    #[test]
+    fn sort_on_write_orders_sessions_by_urb_time() {
+        let df = create_dataframe(vec![
+            record("b", "0.5", "1700000100", "0"),
+            record("a", "0.0", "1700000000", "500"),
+            record("b", "0.0", "1700000099", "500000"),
+            record("a", "1.0", "1700000001", "500"),
+        ])
+        .unwrap();
+        let sorted = sort_by_capture_time(df).unwrap();
+        let sessions: Vec<&str> = sorted.column("session_id").unwrap().str().unwrap().into_no_null_iter().collect();
+        assert_eq!(sessions, ["a", "a", "b", "b"]);
+        assert_eq!(sorted.column("timestamp").unwrap().f64().unwrap().get(2), Some(0.0));
+    }
+
+    #[test]
+    fn output_template_names_each_input() {
+        let config = ConverterConfig::new("x").output_dir("out").output_template("{stem}_dev{addr}.parquet");
+        assert_eq!(templated_output(&config, "orig_adc.16", 16).unwrap(), Some(PathBuf::from("out/orig_adc.16_dev16.parquet")));
+        let csv = ConverterConfig::new("x").output_dir("out").format(OutputFormat::Csv);
+        assert_eq!(templated_output(&csv, "run", 3).unwrap(), Some(PathBuf::from("out/run.csv")));
+        assert_eq!(templated_output(&ConverterConfig::new("x"), "run", 3).unwrap(), None);
+
+        let inputs = [PathBuf::from("a.16.pcapng"), PathBuf::from("b.16.pcapng")];
+        assert!(convert_batch(&ConverterConfig::new("x"), &inputs).unwrap_err().to_string().contains("--output-dir"));
+    }
+
+    #[test]
+    fn missing_output_dir_errors_unless_mkdir() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("fresh/nested/out.parquet");
+        let err = ensure_output_dir(&output, false).unwrap_err();
+        assert!(err.to_string().contains("--mkdir"), "{}", err);
+        ensure_output_dir(&output, true).unwrap();
+        assert!(dir.path().join("fresh/nested").is_dir());
+        ensure_output_dir(Path::new("out.parquet"), false).unwrap();
+    }
+
+    #[test]
+    fn plans_every_file_the_run_writes() {
+        let output = Path::new("out/usb.parquet");
+        let config = ConverterConfig::new("x").resample(100).exchanges(true).features(8).throughput_json("out/tp.json");
+        assert_eq!(planned_outputs(output, &[], &config), [
+            PathBuf::from("out/usb.parquet"),
+            PathBuf::from("out/usb_resampled_100ms.parquet"),
+            PathBuf::from("out/usb_exchanges.parquet"),
+            PathBuf::from("out/usb_features.parquet"),
+            PathBuf::from("out/tp.json"),
+        ]);
+        let normalized = planned_outputs(output, &[], &ConverterConfig::new("x").normalize(true));
+        assert_eq!(normalized, [PathBuf::from("out/usb_packets.parquet"), PathBuf::from("out/usb_adc.parquet"), PathBuf::from("out/usb_pd.parquet")]);
+        assert_eq!(planned_outputs(output, &[], &ConverterConfig::new("x").duckdb("usb.duckdb")), [PathBuf::from("usb.duckdb")]);
+    }
+
+    #[test]
+    fn existing_output_needs_append_or_overwrite() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("usb.parquet");
+        let config = ConverterConfig::new("in.pcapng");
+        check_overwrite(&output, &config).unwrap();
+
+        std::fs::write(&output, b"PAR1").unwrap();
+        let err = check_overwrite(&output, &config).unwrap_err();
+        assert!(err.to_string().contains("--overwrite"), "{}", err);
+        check_overwrite(&output, &config.clone().append(true)).unwrap();
+        check_overwrite(&output, &config.overwrite(true)).unwrap();
+    }
+}
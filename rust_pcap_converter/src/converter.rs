@@ -0,0 +1,766 @@
+use log::{debug, info, warn};
+use rtshark::{RTShark, RTSharkBuilder, RTSharkBuilderReady};
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::config::{ConverterConfig, ParserKind};
+use crate::input::CaptureInput;
+use crate::km003c::{AttributeNames, SessionPhase, ATTRIBUTE_BITS, CMD_PUT_DATA};
+use crate::native::{NativeCapture, NativePacket};
+use crate::record::{known_device_name, parse_tshark_u16, process_packet, split_iso_segments, UsbPacketRecord};
+use crate::source::{MetadataSource, PacketSource};
+use crate::{ConverterError, Result};
+
+const TSHARK_BIN: &str = if cfg!(windows) { "tshark.exe" } else { "tshark" };
+
+/// How to launch tshark (`--tshark-path` / `--tshark-arg`), validated up front
+#[derive(Debug, Default)]
+struct TsharkSetup {
+    /// Directory holding the tshark binary, used as its `PATH`
+    env_path: Option<String>,
+    /// `-o` preference overrides
+    options: Vec<String>,
+    /// `-d` decode-as rules
+    decode_as: Vec<String>,
+}
+
+impl TsharkSetup {
+    fn from_config(config: &ConverterConfig) -> Result<Self> {
+        let mut setup = Self::default();
+        if let Some(path) = &config.tshark_path {
+            let dir = tshark_dir(path)?;
+            setup.env_path = Some(dir.to_str().ok_or("tshark path is not valid UTF-8")?.to_string());
+        }
+        for arg in &config.tshark_args {
+            let arg = arg.trim();
+            if let Some(option) = arg.strip_prefix("-o") {
+                setup.options.push(option.trim().to_string());
+            } else if let Some(rule) = arg.strip_prefix("-d") {
+                setup.decode_as.push(rule.trim().to_string());
+            } else {
+                return Err(format!(
+                    "Unsupported --tshark-arg {:?}: only `-o <pref>:<value>` and `-d <decode-as rule>` can be passed to tshark",
+                    arg
+                )
+                .into());
+            }
+        }
+        Ok(setup)
+    }
+
+    fn builder<'a>(&'a self, input: &'a str) -> RTSharkBuilderReady<'a> {
+        let mut builder = RTSharkBuilder::builder().input_path(input);
+        if let Some(dir) = &self.env_path {
+            builder = builder.env_path(dir);
+        }
+        for option in &self.options {
+            builder = builder.option(option);
+        }
+        for rule in &self.decode_as {
+            builder = builder.decode_as(rule);
+        }
+        builder
+    }
+
+    /// First line of `tshark --version`, or `None` when tshark cannot be run
+    fn version(&self) -> Option<String> {
+        let mut command = std::process::Command::new(TSHARK_BIN);
+        if let Some(dir) = &self.env_path {
+            command.env("PATH", dir);
+        }
+        let output = command.arg("--version").output().ok().filter(|o| o.status.success())?;
+        String::from_utf8_lossy(&output.stdout).lines().next().map(str::to_string)
+    }
+}
+
+/// Directory of the tshark binary named by `--tshark-path` (the binary itself or its directory)
+fn tshark_dir(path: &Path) -> Result<PathBuf> {
+    let binary = if path.is_dir() { path.join(TSHARK_BIN) } else { path.to_path_buf() };
+    if !binary.is_file() {
+        return Err(format!("tshark not found at {:?} (check --tshark-path)", binary).into());
+    }
+    if binary.file_name().and_then(|n| n.to_str()) != Some(TSHARK_BIN) {
+        return Err(format!("--tshark-path must name the {} binary or its directory, got {:?}", TSHARK_BIN, binary).into());
+    }
+    Ok(binary.parent().map(Path::to_path_buf).unwrap_or_default())
+}
+
+/// Whether to read with the native parser (`--parser native`); a custom `--filter` or
+/// `--tshark-arg` only means something to tshark, so those fall back to it
+fn use_native_parser(config: &ConverterConfig) -> bool {
+    if config.parser != ParserKind::Native {
+        return false;
+    }
+    if config.display_filter.is_some() || !config.tshark_args.is_empty() {
+        warn!("--parser native cannot apply --filter or --tshark-arg; reading the capture with tshark instead");
+        return false;
+    }
+    true
+}
+
+/// Built-in attribute names with the `--attribute-map` file merged over them.
+///
+/// The file maps bit numbers to names, as TOML (`5 = "name"`) when it ends in `.toml`
+/// and as a JSON object (`{"5": "name"}`) otherwise.
+fn load_attribute_names(path: Option<&Path>) -> Result<AttributeNames> {
+    let Some(path) = path else {
+        return Ok(AttributeNames::default());
+    };
+    let text = std::fs::read_to_string(path)?;
+    let table: std::collections::BTreeMap<String, String> = if path.extension().is_some_and(|e| e == "toml") {
+        toml::from_str(&text).map_err(|e| format!("Invalid --attribute-map {:?}: {}", path, e))?
+    } else {
+        serde_json::from_str(&text).map_err(|e| format!("Invalid --attribute-map {:?}: {}", path, e))?
+    };
+    let overrides = table
+        .into_iter()
+        .map(|(bit, name)| match bit.trim().parse::<u8>() {
+            Ok(n) if n < ATTRIBUTE_BITS => Ok((n, name)),
+            _ => Err(format!("Invalid bit {:?} in --attribute-map {:?}: expected 0..{}", bit, path, ATTRIBUTE_BITS - 1)),
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    Ok(AttributeNames::default().merge(overrides))
+}
+
+/// Parse the device address from a `name.<addr>.pcapng` file name
+fn address_from_filename(filename: &str) -> Option<u8> {
+    let (before_ext, _) = filename.rsplit_once('.')?;
+    let (_, potential_id) = before_ext.rsplit_once('.')?;
+    potential_id.parse().ok()
+}
+
+/// One USB device seen in a capture
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceSummary {
+    pub address: u8,
+    pub packets: usize,
+    /// VID/PID from the device descriptor, if the capture includes enumeration
+    pub id_vendor: Option<u16>,
+    pub id_product: Option<u16>,
+}
+
+impl DeviceSummary {
+    /// Product name for known VID/PID pairs
+    pub fn product_name(&self) -> Option<&'static str> {
+        known_device_name(self.id_vendor?, self.id_product?)
+    }
+}
+
+/// Enumerate the devices in the configured capture (`--list-devices`), busiest first
+pub fn list_devices(config: &ConverterConfig) -> Result<Vec<DeviceSummary>> {
+    let tshark = TsharkSetup::from_config(config)?;
+    let input = CaptureInput::open(&config.input)?;
+    scan_device_addresses(input.path(), &tshark, use_native_parser(config))
+}
+
+/// Expand `{stem}`, `{addr}` and `{date}` in the template given to `flag`; unknown or
+/// unclosed placeholders are errors
+pub(crate) fn expand_template(flag: &str, template: &str, stem: &str, addr: u8) -> Result<String> {
+    let mut expanded = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        expanded.push_str(&rest[..open]);
+        let close = rest[open..]
+            .find('}')
+            .ok_or_else(|| format!("Unclosed placeholder in {} {:?}", flag, template))?;
+        match &rest[open + 1..open + close] {
+            "stem" => expanded.push_str(stem),
+            "addr" => expanded.push_str(&addr.to_string()),
+            "date" => expanded.push_str(&chrono::Local::now().format("%Y-%m-%d").to_string()),
+            other => {
+                return Err(format!(
+                    "Unknown placeholder {{{}}} in {}; use {{stem}}, {{addr}} or {{date}}",
+                    other, flag
+                )
+                .into())
+            }
+        }
+        rest = &rest[open + close + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Count USB packets per `usb.device_address` with a minimal tshark (or native) pass, busiest first
+fn scan_device_addresses(path: &Path, tshark: &TsharkSetup, native: bool) -> Result<Vec<DeviceSummary>> {
+    let mut devices: HashMap<u8, DeviceSummary> = HashMap::new();
+    if native {
+        let mut capture = NativeCapture::open(path)?;
+        while let Some(packet) = capture.next_packet()? {
+            if let Some(usb) = packet.layer("usb") {
+                count_device(&mut devices, usb);
+            }
+        }
+    } else {
+        let file_path = path.to_str().ok_or("File path is not valid UTF-8")?;
+        let mut rtshark = tshark
+            .builder(file_path)
+            .display_filter("usb")
+            .metadata_whitelist("usb.device_address")
+            .metadata_whitelist("usb.idVendor")
+            .metadata_whitelist("usb.idProduct")
+            .spawn()
+            .map_err(ConverterError::TsharkSpawn)?;
+        while let Some(packet) = rtshark.read()? {
+            if let Some(usb) = packet.layer("usb") {
+                count_device(&mut devices, usb);
+            }
+        }
+    }
+    let mut devices: Vec<DeviceSummary> = devices.into_values().collect();
+    devices.sort_by(|a, b| b.packets.cmp(&a.packets).then(a.address.cmp(&b.address)));
+    Ok(devices)
+}
+
+/// Add one USB packet to its device's tally, picking up VID/PID from a device descriptor
+fn count_device<L: MetadataSource>(devices: &mut HashMap<u8, DeviceSummary>, usb: &L) {
+    let Some(address) = usb.get("usb.device_address").and_then(|a| a.parse().ok()) else {
+        return;
+    };
+    let device = devices.entry(address).or_insert_with(|| DeviceSummary {
+        address,
+        packets: 0,
+        id_vendor: None,
+        id_product: None,
+    });
+    device.packets += 1;
+    if let (Some(vid), Some(pid)) = (
+        usb.get("usb.idVendor").and_then(parse_tshark_u16),
+        usb.get("usb.idProduct").and_then(parse_tshark_u16),
+    ) {
+        device.id_vendor = Some(vid);
+        device.id_product = Some(pid);
+    }
+}
+
+/// Busiest address and its share of all counted packets
+fn most_common_address(devices: &[DeviceSummary]) -> Option<(u8, f64)> {
+    let total: usize = devices.iter().map(|d| d.packets).sum();
+    let busiest = devices
+        .iter()
+        .max_by(|a, b| a.packets.cmp(&b.packets).then(b.address.cmp(&a.address)))?;
+    Some((busiest.address, busiest.packets as f64 / total as f64))
+}
+
+/// Rows compared when looking for a usbmon double-logged URB
+const RETRANSMIT_WINDOW: usize = 8;
+
+/// Packet hashes of the last [`RETRANSMIT_WINDOW`] rows
+#[derive(Debug, Default)]
+struct RetransmitWindow {
+    recent: VecDeque<String>,
+}
+
+impl RetransmitWindow {
+    /// Record a row's `packet_hash` and report whether it repeats a recent row
+    fn observe(&mut self, packet_hash: &str) -> bool {
+        let repeated = self.recent.iter().any(|h| h == packet_hash);
+        self.recent.push_back(packet_hash.to_string());
+        if self.recent.len() > RETRANSMIT_WINDOW {
+            self.recent.pop_front();
+        }
+        repeated
+    }
+}
+
+/// Incremental clause ANDed into the read filter (`--since`, `--resume`, `--follow`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum FrameFilter {
+    /// Frames numbered above this one
+    AfterFrame(u32),
+    /// Frames more than this many seconds into the capture
+    AfterTime(f64),
+}
+
+impl FrameFilter {
+    fn display_filter(self) -> String {
+        match self {
+            FrameFilter::AfterFrame(frame) => format!("frame.number > {}", frame),
+            FrameFilter::AfterTime(seconds) => format!("frame.time_relative > {:.9}", seconds),
+        }
+    }
+
+    fn matches(self, packet: &NativePacket) -> bool {
+        match self {
+            FrameFilter::AfterFrame(frame) => packet.frame_number > frame,
+            FrameFilter::AfterTime(seconds) => packet.time_relative > seconds,
+        }
+    }
+}
+
+/// Where [`Converter::records`] reads packets from
+enum PacketReader {
+    Tshark(RTShark),
+    Native(NativeCapture),
+}
+
+/// A packet from either reader
+enum ReadPacket {
+    Tshark(rtshark::Packet),
+    Native(NativePacket),
+}
+
+/// Progress of a conversion, reported to the [`Converter::with_observer`] callback
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConvertEvent {
+    /// A packet passed the display filter; `packets` read so far
+    PacketRead { packets: usize },
+    /// A record was yielded; `records` so far (ISO segments count separately)
+    RecordBuilt { records: usize },
+    /// A `--batch-size` checkpoint was saved; `records` checkpointed so far
+    BatchFlushed { records: usize },
+    /// The capture is exhausted, `--limit` was reached or reading failed
+    Done { packets: usize, records: usize },
+}
+
+/// Default observer: the progress lines the CLI logs
+pub fn log_progress(event: ConvertEvent) {
+    match event {
+        ConvertEvent::PacketRead { packets } if packets.is_multiple_of(100) => info!("Processed {} packets...", packets),
+        ConvertEvent::BatchFlushed { records } => debug!("Checkpointed {} records", records),
+        ConvertEvent::Done { packets, records } => debug!("Finished reading: {} packets, {} records", packets, records),
+        _ => {}
+    }
+}
+
+/// A capture opened for conversion, yielding records one packet at a time.
+///
+/// [`convert`](crate::convert) drives this and then builds the output DataFrame;
+/// library users can consume [`Converter::records`] directly for online analysis.
+pub struct Converter {
+    config: ConverterConfig,
+    tshark: TsharkSetup,
+    native: bool,
+    input: CaptureInput,
+    device_address: u8,
+    session_id: String,
+    stem: String,
+    extra_filters: Vec<FrameFilter>,
+    packet_count: usize,
+    non_usb_count: usize,
+    malformed_count: usize,
+    phase: SessionPhase,
+    retransmits: RetransmitWindow,
+    attribute_names: AttributeNames,
+    observer: Rc<dyn Fn(ConvertEvent)>,
+    record_count: usize,
+}
+
+impl Converter {
+    /// Open the configured input and resolve the device address and session ID
+    pub fn new(config: ConverterConfig) -> Result<Self> {
+        let tshark = TsharkSetup::from_config(&config)?;
+        let native = use_native_parser(&config);
+        let attribute_names = load_attribute_names(config.attribute_map.as_deref())?;
+        if let Some(template) = &config.session_template {
+            expand_template("--session-template", template, "", 0)?;
+        }
+
+        // Decompress .gz/.zst captures to a temp file that lives until the conversion ends
+        let input = CaptureInput::open(&config.input)?;
+
+        // Device address: explicit, then the `name.<addr>.pcapng` filename pattern,
+        // then the most common address in the capture itself
+        let device_address = match config.device_address {
+            Some(addr) => addr,
+            // A scan would consume the stream before conversion could read it
+            None if input.is_stdin() => {
+                return Err("Reading the capture from stdin requires --device-address".into());
+            }
+            None => match address_from_filename(input.name()) {
+                Some(id) => {
+                    info!("Auto-detected device address from filename: {}", id);
+                    id
+                }
+                None => {
+                    info!("No device address in filename, scanning capture for the busiest device...");
+                    let devices = scan_device_addresses(input.path(), &tshark, native)?;
+                    let (id, share) = most_common_address(&devices)
+                        .ok_or("Could not auto-detect device address: capture has no USB packets. Please provide --device-address")?;
+                    info!("Auto-detected device address from capture: {} ({:.1}% of USB packets)", id, share * 100.0);
+                    id
+                }
+            },
+        };
+
+        // Session ID: explicit, then the template, then the filename stem
+        let filename = input.name();
+        let stem = match filename.rfind('.') {
+            Some(dot_pos) => &filename[..dot_pos],
+            None => filename,
+        }
+        .to_string();
+        let session_id = match (&config.session_id, &config.session_template) {
+            (Some(id), _) => id.clone(),
+            (None, Some(template)) => expand_template("--session-template", template, &stem, device_address)?,
+            (None, None) => stem.clone(),
+        };
+
+        Ok(Self {
+            config,
+            tshark,
+            native,
+            input,
+            device_address,
+            session_id,
+            stem,
+            extra_filters: Vec::new(),
+            packet_count: 0,
+            non_usb_count: 0,
+            malformed_count: 0,
+            phase: SessionPhase::default(),
+            retransmits: RetransmitWindow::default(),
+            attribute_names,
+            observer: Rc::new(log_progress),
+            record_count: 0,
+        })
+    }
+
+    /// Report progress to `observer` instead of [`log_progress`]
+    pub fn with_observer(mut self, observer: impl Fn(ConvertEvent) + 'static) -> Self {
+        self.observer = Rc::new(observer);
+        self
+    }
+
+    /// The observer, for reporting steps taken while [`Converter::records`] is borrowed
+    pub(crate) fn observer(&self) -> Rc<dyn Fn(ConvertEvent)> {
+        self.observer.clone()
+    }
+
+    fn notify(&self, event: ConvertEvent) {
+        (self.observer)(event)
+    }
+
+    fn notify_done(&self) {
+        self.notify(ConvertEvent::Done { packets: self.packet_count, records: self.record_count });
+    }
+
+    pub fn device_address(&self) -> u8 {
+        self.device_address
+    }
+
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// Input file name without its capture extension, e.g. `orig_adc.16`
+    pub fn input_stem(&self) -> &str {
+        &self.stem
+    }
+
+    /// Packets read from the capture so far
+    pub fn packet_count(&self) -> usize {
+        self.packet_count
+    }
+
+    /// Packets skipped because they carry no USB layer (not an error)
+    pub fn non_usb_count(&self) -> usize {
+        self.non_usb_count
+    }
+
+    /// USB packets that failed to convert
+    pub fn malformed_count(&self) -> usize {
+        self.malformed_count
+    }
+
+    /// Version line of the tshark binary this converter runs, if it can be queried;
+    /// `None` with the native parser
+    pub fn tshark_version(&self) -> Option<String> {
+        if self.native {
+            return None;
+        }
+        self.tshark.version()
+    }
+
+    /// AND an additional clause into the read filter
+    pub(crate) fn push_filter(&mut self, filter: FrameFilter) {
+        self.extra_filters.push(filter);
+    }
+
+    /// tshark display filter used by [`Converter::records`]; the native parser evaluates
+    /// the same clauses itself
+    pub fn display_filter(&self) -> String {
+        let mut filter_parts = match &self.config.display_filter {
+            // A custom --filter replaces the generated clauses; incremental ones still apply
+            Some(custom) => vec![format!("({})", custom)],
+            None => {
+                let mut parts = vec![format!("usb.device_address == {}", self.device_address)];
+                if let Some(bus_id) = self.config.bus_id {
+                    parts.push(format!("usb.bus_id == {}", bus_id));
+                }
+                // Payload-only mode; interrupt data claimed by a HID/CDC dissector has no usb.capdata
+                if self.config.payload_only {
+                    parts.push("(usb.capdata || (usb.transfer_type == 0x01 && usb.data_len > 0))".to_string());
+                }
+                parts
+            }
+        };
+        filter_parts.extend(self.extra_filters.iter().map(|f| f.display_filter()));
+        filter_parts.join(" && ")
+    }
+
+    fn open_reader(&self) -> Result<PacketReader> {
+        if self.native {
+            return Ok(PacketReader::Native(NativeCapture::open(self.input.path())?));
+        }
+        let file_path = self.input.path().to_str().ok_or("File path is not valid UTF-8")?;
+        let rtshark = self.tshark.builder(file_path).display_filter(&self.display_filter()).spawn().map_err(ConverterError::TsharkSpawn)?;
+        Ok(PacketReader::Tshark(rtshark))
+    }
+
+    /// [`Converter::display_filter`] for a natively parsed frame (never a custom `--filter`)
+    fn native_filter_matches(&self, packet: &NativePacket) -> bool {
+        let Some(usb) = packet.usb else {
+            return false;
+        };
+        usb.device == self.device_address
+            && self.config.bus_id.is_none_or(|bus| usb.bus == u16::from(bus))
+            && (!self.config.payload_only || packet.has_payload || (usb.transfer_type == 0x01 && usb.data_len > 0))
+            && self.extra_filters.iter().all(|f| f.matches(packet))
+    }
+
+    /// Next packet passing the display filter
+    fn read_packet(&self, reader: &mut PacketReader) -> Result<Option<ReadPacket>> {
+        match reader {
+            PacketReader::Tshark(shark) => Ok(shark.read()?.map(ReadPacket::Tshark)),
+            PacketReader::Native(capture) => {
+                while let Some(packet) = capture.next_packet()? {
+                    if self.native_filter_matches(&packet) {
+                        return Ok(Some(ReadPacket::Native(packet)));
+                    }
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// Stream converted records from tshark (or the native parser) without collecting them,
+    /// reporting each step to the observer.
+    ///
+    /// Frames without a USB layer are skipped and counted in [`Converter::non_usb_count`].
+    /// A USB packet that fails to convert yields its error and the stream continues; a
+    /// tshark failure ([`ConverterError::is_tshark_failure`]) is yielded last. ISO transfers yield one record per
+    /// segment. Every record carries the `session_phase` reached so far in the stream, and
+    /// `is_retransmit` when it repeats one of the previous few rows exactly.
+    /// The stream stops early once `limit` packets have been read.
+    pub fn records(&mut self) -> impl Iterator<Item = Result<UsbPacketRecord>> + '_ {
+        let (mut reader, mut error) = match self.open_reader() {
+            Ok(reader) => (Some(reader), None),
+            Err(e) => (None, Some(e)),
+        };
+        let mut pending = VecDeque::new();
+
+        std::iter::from_fn(move || loop {
+            if let Some(record) = pending.pop_front() {
+                self.record_count += 1;
+                self.notify(ConvertEvent::RecordBuilt { records: self.record_count });
+                return Some(Ok(record));
+            }
+            if let Some(e) = error.take() {
+                self.notify_done();
+                return Some(Err(e));
+            }
+            let source = reader.as_mut()?;
+            if self.config.limit.is_some_and(|limit| self.packet_count >= limit) {
+                info!("Reached packet limit of {}, stopping early", self.packet_count);
+                if let PacketReader::Tshark(shark) = source {
+                    shark.kill();
+                }
+                reader = None;
+                self.notify_done();
+                return None;
+            }
+            let packet = match self.read_packet(source) {
+                Ok(Some(packet)) => packet,
+                Ok(None) => {
+                    reader = None;
+                    self.notify_done();
+                    return None;
+                }
+                Err(e) => {
+                    reader = None;
+                    self.notify_done();
+                    return Some(Err(e));
+                }
+            };
+            self.packet_count += 1;
+            self.notify(ConvertEvent::PacketRead { packets: self.packet_count });
+
+            let converted = match &packet {
+                ReadPacket::Tshark(packet) => self.convert_packet(packet),
+                ReadPacket::Native(packet) => self.convert_packet(packet),
+            };
+            match converted {
+                Ok(records) => pending.extend(records),
+                Err(e) => {
+                    self.malformed_count += 1;
+                    return Some(Err(e));
+                }
+            }
+        })
+    }
+
+    /// Records for one packet; frames without a USB layer yield none
+    fn convert_packet<P: PacketSource>(&mut self, packet: &P) -> Result<Vec<UsbPacketRecord>> {
+        if packet.layer("usb").is_none() {
+            self.non_usb_count += 1;
+            return Ok(Vec::new());
+        }
+        process_packet(packet, &self.session_id, self.config.verbose)
+            .map(|mut record| {
+                if self.config.include_raw {
+                    record.frame_raw_hex = packet.raw_bytes().map(hex::encode);
+                }
+                record
+            })
+            .and_then(|record| split_iso_segments(packet, record))
+            .map(|mut records| {
+                for record in &mut records {
+                    if let Some(packet_type) = record.km_packet_type {
+                        self.phase = self.phase.advance(packet_type);
+                    }
+                    record.session_phase = Some(self.phase.as_str().to_string());
+                    record.is_retransmit = self.retransmits.observe(&record.packet_hash);
+                    // PutData's header field is a word count, so name its logical packets instead
+                    let attribute = match record.km_packet_type {
+                        Some(CMD_PUT_DATA) => record.km_logical_attributes,
+                        Some(_) => record.km_attribute,
+                        None => None,
+                    };
+                    record.km_attribute_name = attribute.and_then(|a| self.attribute_names.decode_attribute(a));
+                    if let Some(max_bytes) = self.config.max_payload {
+                        record.truncate_payload(max_bytes);
+                    }
+                }
+                records
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_address_session_and_filter_from_filename() {
+        let mut converter = Converter::new(ConverterConfig::new("captures/orig_adc.16.pcapng").payload_only(true)).unwrap();
+        assert_eq!(converter.device_address(), 16);
+        assert_eq!(converter.session_id(), "orig_adc.16");
+        converter.push_filter(FrameFilter::AfterFrame(10));
+        assert_eq!(converter.display_filter(), "usb.device_address == 16 && (usb.capdata || (usb.transfer_type == 0x01 && usb.data_len > 0)) && frame.number > 10");
+
+        let mut custom = Converter::new(ConverterConfig::new("captures/orig_adc.16.pcapng").display_filter("usb.transfer_type == 0x03 || usb.src == \"host\"")).unwrap();
+        custom.push_filter(FrameFilter::AfterFrame(10));
+        assert_eq!(custom.display_filter(), "(usb.transfer_type == 0x03 || usb.src == \"host\") && frame.number > 10");
+
+        let on_bus = Converter::new(ConverterConfig::new("captures/orig_adc.16.pcapng").bus_id(3)).unwrap();
+        assert_eq!(on_bus.display_filter(), "usb.device_address == 16 && usb.bus_id == 3");
+    }
+
+    #[test]
+    fn flags_repeats_within_the_retransmit_window() {
+        let mut window = RetransmitWindow::default();
+        assert!(!window.observe("a"));
+        assert!(!window.observe("b"));
+        assert!(window.observe("a"));
+        for filler in 0..RETRANSMIT_WINDOW {
+            window.observe(&filler.to_string());
+        }
+        assert!(!window.observe("b"));
+    }
+
+    #[test]
+    fn validates_tshark_path_and_args() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = ConverterConfig::new("x.pcapng").tshark_path(dir.path());
+        let err = TsharkSetup::from_config(&missing).unwrap_err();
+        assert!(err.to_string().contains("tshark not found"), "{}", err);
+
+        std::fs::write(dir.path().join(TSHARK_BIN), b"").unwrap();
+        let config = ConverterConfig::new("x.pcapng")
+            .tshark_path(dir.path().join(TSHARK_BIN))
+            .tshark_arg("-o usb.try_heuristics:FALSE")
+            .tshark_arg("-dusb.product==0x0121,usbms");
+        let setup = TsharkSetup::from_config(&config).unwrap();
+        assert_eq!(setup.env_path.as_deref(), dir.path().to_str());
+        assert_eq!(setup.options, ["usb.try_heuristics:FALSE"]);
+        assert_eq!(setup.decode_as, ["usb.product==0x0121,usbms"]);
+
+        assert!(TsharkSetup::from_config(&ConverterConfig::new("x").tshark_arg("-Y usb")).is_err());
+    }
+
+    #[test]
+    fn stdin_input_requires_device_address() {
+        let err = Converter::new(ConverterConfig::new("-")).err().unwrap();
+        assert!(err.to_string().contains("--device-address"), "{}", err);
+        let converter = Converter::new(ConverterConfig::new("-").device_address(7)).unwrap();
+        assert_eq!((converter.device_address(), converter.session_id()), (7, "stdin"));
+    }
+
+    #[test]
+    fn merges_attribute_map_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let toml_path = dir.path().join("attrs.toml");
+        std::fs::write(&toml_path, "6 = \"graph\"\n0 = \"adc_main\"\n").unwrap();
+        let names = load_attribute_names(Some(&toml_path)).unwrap();
+        assert_eq!(names.decode_attribute(0x0043).as_deref(), Some("adc_main|adc_queue|graph"));
+
+        let json_path = dir.path().join("attrs.json");
+        std::fs::write(&json_path, r#"{"15": "too_high"}"#).unwrap();
+        assert!(load_attribute_names(Some(&json_path)).unwrap_err().to_string().contains("\"15\""));
+        assert_eq!(load_attribute_names(None).unwrap(), AttributeNames::default());
+    }
+
+    #[test]
+    fn expands_session_template() {
+        assert_eq!(expand_template("--session-template", "device{addr}_{stem}", "orig_adc", 16).unwrap(), "device16_orig_adc");
+        let dated = expand_template("--session-template", "{date}", "", 0).unwrap();
+        assert_eq!(dated, chrono::Local::now().format("%Y-%m-%d").to_string());
+        assert!(expand_template("--session-template", "{host}_{stem}", "x", 1).unwrap_err().to_string().contains("{host}"));
+        assert!(expand_template("--output-template", "{stem", "x", 1).is_err());
+
+        let converter = Converter::new(ConverterConfig::new("captures/orig_adc.16.pcapng").session_template("d{addr}-{stem}")).unwrap();
+        assert_eq!(converter.session_id(), "d16-orig_adc.16");
+    }
+
+    #[test]
+    fn parses_address_from_filename_pattern() {
+        assert_eq!(address_from_filename("orig_adc.16.pcapng"), Some(16));
+        assert_eq!(address_from_filename("capture.pcapng"), None);
+        assert_eq!(address_from_filename("capture.300.pcapng"), None);
+    }
+
+    fn device(address: u8, packets: usize) -> DeviceSummary {
+        DeviceSummary { address, packets, id_vendor: None, id_product: None }
+    }
+
+    #[test]
+    fn picks_busiest_address_with_confidence() {
+        assert_eq!(most_common_address(&[device(16, 75), device(1, 20), device(0, 5)]), Some((16, 0.75)));
+        assert_eq!(most_common_address(&[device(3, 5), device(2, 5)]), Some((2, 0.5)));
+        assert_eq!(most_common_address(&[]), None);
+    }
+
+    #[test]
+    fn names_known_devices() {
+        let km003c = DeviceSummary { id_vendor: Some(0x5FC9), id_product: Some(0x0063), ..device(16, 1) };
+        assert_eq!(km003c.product_name(), Some("ChargerLAB POWER-Z KM003C"));
+        assert_eq!(device(3, 1).product_name(), None);
+    }
+
+    #[test]
+    fn observer_sees_every_packet_record_and_the_end() {
+        let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/km003c_adc.pcapng");
+        let events = Rc::new(std::cell::RefCell::new(Vec::new()));
+        let sink = events.clone();
+        let config = ConverterConfig::new(fixture).device_address(5).parser(ParserKind::Native);
+        let mut converter = Converter::new(config).unwrap().with_observer(move |event| sink.borrow_mut().push(event));
+        assert_eq!(converter.records().count(), 4);
+
+        let events = events.borrow();
+        assert_eq!(events.iter().filter(|e| matches!(e, ConvertEvent::PacketRead { .. })).count(), 4);
+        assert_eq!(events[..2], [ConvertEvent::PacketRead { packets: 1 }, ConvertEvent::RecordBuilt { records: 1 }]);
+        assert_eq!(events.last(), Some(&ConvertEvent::Done { packets: 4, records: 4 }));
+    }
+}
@@ -0,0 +1,322 @@
+//! Pluggable decoders for KM003C application payloads.
+//!
+//! Each [`PayloadDecoder`] handles one main-header packet type and fills the record
+//! columns it knows about; [`DecoderRegistry`] runs every decoder registered for a
+//! payload's packet type and merges their output.
+
+use crate::km003c::{self, CMD_PUT_DATA};
+
+/// Record columns produced by payload decoders; `None` means "not decoded here"
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DecodedFields {
+    pub checksum_valid: Option<bool>,
+    pub adc_vbus_v: Option<f64>,
+    pub adc_ibus_a: Option<f64>,
+    pub adc_power_w: Option<f64>,
+    pub adc_temp_c: Option<f64>,
+    pub adc_packet_kind: Option<String>,
+    pub cc_orientation: Option<String>,
+    pub cc_attached: Option<bool>,
+    pub cc_advertised_current: Option<String>,
+    pub pd_rdo_object_position: Option<u8>,
+    pub pd_rdo_op_current: Option<f64>,
+    pub pd_rdo_max_current: Option<f64>,
+    pub pd_rdo_give_back: Option<bool>,
+    pub pd_rdo_capability_mismatch: Option<bool>,
+    pub pd_rdo_usb_comm_capable: Option<bool>,
+    pub pd_is_extended: Option<bool>,
+    pub pd_is_bist: Option<bool>,
+    pub device_sample_rate_hz: Option<f64>,
+    pub km_group_count: Option<u32>,
+    pub km_groups: Option<String>,
+}
+
+impl DecodedFields {
+    /// Fill columns still unset from `other`; earlier decoders win on conflicts
+    fn merge(&mut self, other: DecodedFields) {
+        self.checksum_valid = self.checksum_valid.or(other.checksum_valid);
+        self.adc_vbus_v = self.adc_vbus_v.or(other.adc_vbus_v);
+        self.adc_ibus_a = self.adc_ibus_a.or(other.adc_ibus_a);
+        self.adc_power_w = self.adc_power_w.or(other.adc_power_w);
+        self.adc_temp_c = self.adc_temp_c.or(other.adc_temp_c);
+        self.adc_packet_kind = self.adc_packet_kind.take().or(other.adc_packet_kind);
+        self.cc_orientation = self.cc_orientation.take().or(other.cc_orientation);
+        self.cc_attached = self.cc_attached.or(other.cc_attached);
+        self.cc_advertised_current = self.cc_advertised_current.take().or(other.cc_advertised_current);
+        self.pd_rdo_object_position = self.pd_rdo_object_position.or(other.pd_rdo_object_position);
+        self.pd_rdo_op_current = self.pd_rdo_op_current.or(other.pd_rdo_op_current);
+        self.pd_rdo_max_current = self.pd_rdo_max_current.or(other.pd_rdo_max_current);
+        self.pd_rdo_give_back = self.pd_rdo_give_back.or(other.pd_rdo_give_back);
+        self.pd_rdo_capability_mismatch = self.pd_rdo_capability_mismatch.or(other.pd_rdo_capability_mismatch);
+        self.pd_rdo_usb_comm_capable = self.pd_rdo_usb_comm_capable.or(other.pd_rdo_usb_comm_capable);
+        self.pd_is_extended = self.pd_is_extended.or(other.pd_is_extended);
+        self.pd_is_bist = self.pd_is_bist.or(other.pd_is_bist);
+        self.device_sample_rate_hz = self.device_sample_rate_hz.or(other.device_sample_rate_hz);
+        self.km_group_count = self.km_group_count.or(other.km_group_count);
+        self.km_groups = self.km_groups.take().or(other.km_groups);
+    }
+}
+
+/// Decoder for one KM003C packet type
+pub trait PayloadDecoder: Send + Sync {
+    /// Main-header packet type this decoder handles
+    fn packet_type(&self) -> u8;
+
+    /// Decode a complete application payload, main header included
+    fn decode(&self, body: &[u8]) -> DecodedFields;
+}
+
+/// Main VBUS/IBUS measurement from ADC logical packets
+pub struct AdcDecoder;
+
+impl PayloadDecoder for AdcDecoder {
+    fn packet_type(&self) -> u8 {
+        CMD_PUT_DATA
+    }
+
+    fn decode(&self, body: &[u8]) -> DecodedFields {
+        let adc = km003c::decode_adc(body);
+        DecodedFields {
+            adc_vbus_v: adc.map(|a| a.vbus_v),
+            adc_ibus_a: adc.map(|a| a.ibus_a),
+            adc_power_w: adc.map(|a| a.power_w()),
+            adc_packet_kind: adc.map(|_| "adc".to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+/// Internal temperature, from full ADC samples or temperature-only ADC packets
+pub struct TemperatureDecoder;
+
+impl PayloadDecoder for TemperatureDecoder {
+    fn packet_type(&self) -> u8 {
+        CMD_PUT_DATA
+    }
+
+    fn decode(&self, body: &[u8]) -> DecodedFields {
+        let temp_c = km003c::decode_temperature(body);
+        DecodedFields {
+            adc_temp_c: temp_c,
+            // Only reached as the kind when AdcDecoder found no full sample
+            adc_packet_kind: temp_c.map(|_| "temperature".to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+/// Type-C CC state from PdPacket connection events
+pub struct ConnectionDecoder;
+
+impl PayloadDecoder for ConnectionDecoder {
+    fn packet_type(&self) -> u8 {
+        CMD_PUT_DATA
+    }
+
+    fn decode(&self, body: &[u8]) -> DecodedFields {
+        let cc_status = km003c::decode_cc_event(body);
+        DecodedFields {
+            cc_orientation: cc_status.as_ref().and_then(|c| c.orientation.map(str::to_string)),
+            cc_attached: cc_status.as_ref().map(|c| c.attached),
+            cc_advertised_current: cc_status.as_ref().and_then(|c| c.advertised_current.map(str::to_string)),
+            ..Default::default()
+        }
+    }
+}
+
+/// Request data object from PD Request messages in PdPacket event streams
+pub struct PdRequestDecoder;
+
+impl PayloadDecoder for PdRequestDecoder {
+    fn packet_type(&self) -> u8 {
+        CMD_PUT_DATA
+    }
+
+    fn decode(&self, body: &[u8]) -> DecodedFields {
+        let rdo = km003c::decode_pd_request(body);
+        DecodedFields {
+            pd_rdo_object_position: rdo.map(|r| r.object_position),
+            pd_rdo_op_current: rdo.map(|r| r.op_current_a),
+            pd_rdo_max_current: rdo.map(|r| r.max_current_a),
+            pd_rdo_give_back: rdo.map(|r| r.give_back),
+            pd_rdo_capability_mismatch: rdo.map(|r| r.capability_mismatch),
+            pd_rdo_usb_comm_capable: rdo.map(|r| r.usb_comm_capable),
+            ..Default::default()
+        }
+    }
+}
+
+/// Flags for PD messages whose body is not a list of power data objects
+pub struct PdMessageKindDecoder;
+
+impl PayloadDecoder for PdMessageKindDecoder {
+    fn packet_type(&self) -> u8 {
+        CMD_PUT_DATA
+    }
+
+    fn decode(&self, body: &[u8]) -> DecodedFields {
+        let messages = km003c::decode_pd_messages(body);
+        if messages.is_empty() {
+            return DecodedFields::default();
+        }
+        DecodedFields {
+            pd_is_extended: Some(messages.iter().any(km003c::PdMessage::is_extended)),
+            pd_is_bist: Some(messages.iter().any(km003c::PdMessage::is_bist)),
+            ..Default::default()
+        }
+    }
+}
+
+/// CRC check of Settings blocks
+pub struct SettingsChecksumDecoder;
+
+impl PayloadDecoder for SettingsChecksumDecoder {
+    fn packet_type(&self) -> u8 {
+        CMD_PUT_DATA
+    }
+
+    fn decode(&self, body: &[u8]) -> DecodedFields {
+        DecodedFields {
+            checksum_valid: km003c::verify_checksum(body),
+            ..Default::default()
+        }
+    }
+}
+
+/// Configured sample rate from Settings responses
+pub struct SettingsDecoder;
+
+impl PayloadDecoder for SettingsDecoder {
+    fn packet_type(&self) -> u8 {
+        CMD_PUT_DATA
+    }
+
+    fn decode(&self, body: &[u8]) -> DecodedFields {
+        DecodedFields {
+            device_sample_rate_hz: km003c::decode_settings_sample_rate(body),
+            ..Default::default()
+        }
+    }
+}
+
+/// Layout of the data groups in a PutData body answering a combined GetData.
+///
+/// The value decoders above each look up their own attribute's group wherever it sits
+/// in the body; this records every group, including ones none of them understand.
+pub struct GroupsDecoder;
+
+impl PayloadDecoder for GroupsDecoder {
+    fn packet_type(&self) -> u8 {
+        CMD_PUT_DATA
+    }
+
+    fn decode(&self, body: &[u8]) -> DecodedFields {
+        let count = km003c::logical_packets(body).len() as u32;
+        DecodedFields {
+            km_group_count: Some(count).filter(|&n| n > 0),
+            km_groups: km003c::describe_logical_packets(body),
+            ..Default::default()
+        }
+    }
+}
+
+/// Decoders keyed by packet type, applied in registration order
+pub struct DecoderRegistry {
+    decoders: Vec<Box<dyn PayloadDecoder>>,
+}
+
+impl DecoderRegistry {
+    /// Registry with no decoders
+    pub fn empty() -> Self {
+        Self { decoders: Vec::new() }
+    }
+
+    pub fn register(mut self, decoder: impl PayloadDecoder + 'static) -> Self {
+        self.decoders.push(Box::new(decoder));
+        self
+    }
+
+    /// Run every decoder matching the payload's packet type and merge their columns
+    pub fn decode(&self, payload: &[u8]) -> DecodedFields {
+        let mut fields = DecodedFields::default();
+        let Some(header) = km003c::parse_header(payload) else {
+            return fields;
+        };
+        for decoder in self.decoders.iter().filter(|d| d.packet_type() == header.packet_type) {
+            fields.merge(decoder.decode(payload));
+        }
+        fields
+    }
+}
+
+impl Default for DecoderRegistry {
+    /// All built-in decoders
+    fn default() -> Self {
+        Self::empty()
+            .register(AdcDecoder)
+            .register(TemperatureDecoder)
+            .register(ConnectionDecoder)
+            .register(PdRequestDecoder)
+            .register(PdMessageKindDecoder)
+            .register(SettingsChecksumDecoder)
+            .register(SettingsDecoder)
+            .register(GroupsDecoder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FixedDecoder(u8);
+
+    impl PayloadDecoder for FixedDecoder {
+        fn packet_type(&self) -> u8 {
+            self.0
+        }
+
+        fn decode(&self, _body: &[u8]) -> DecodedFields {
+            DecodedFields { cc_attached: Some(self.0 == 0x0C), checksum_valid: Some(true), ..Default::default() }
+        }
+    }
+
+    #[test]
+    fn dispatches_on_packet_type_and_merges_columns() {
+        let registry = DecoderRegistry::empty()
+            .register(FixedDecoder(0x0C))
+            .register(FixedDecoder(0x41))
+            .register(SettingsChecksumDecoder);
+
+        let control = registry.decode(&[0x0C, 0x05, 0x22, 0x00]);
+        assert_eq!(control.cc_attached, Some(true));
+
+        // The PutData fixed decoder sets checksum_valid first; the real checksum decoder finds nothing
+        let mut put_data = vec![CMD_PUT_DATA, 0x02, 0x00, 0x00];
+        put_data.extend_from_slice(&(km003c::ATT_ADC as u32 | 44 << 22).to_le_bytes());
+        put_data.extend_from_slice(&[0; 44]);
+        let decoded = registry.decode(&put_data);
+        assert_eq!((decoded.cc_attached, decoded.checksum_valid), (Some(false), Some(true)));
+
+        assert_eq!(registry.decode(&[0x0C]), DecodedFields::default());
+        assert_eq!(DecoderRegistry::default().decode(&put_data).adc_vbus_v, Some(0.0));
+    }
+
+    #[test]
+    fn decodes_every_group_of_a_combined_response() {
+        // PD group, then an unknown attribute (bit 6), then the ADC group: 5.0 V, 1.5 A
+        let mut put_data = vec![CMD_PUT_DATA, 0x0a, 0x00, 0x00];
+        for (attribute, body) in [(km003c::ATT_PD_PACKET, vec![0; 12]), (0x0040, vec![0xff; 4])] {
+            put_data.extend_from_slice(&(attribute as u32 | 0x8000 | (body.len() as u32) << 22).to_le_bytes());
+            put_data.extend_from_slice(&body);
+        }
+        put_data.extend_from_slice(&(km003c::ATT_ADC as u32 | 44 << 22).to_le_bytes());
+        put_data.extend_from_slice(&5_000_000i32.to_le_bytes());
+        put_data.extend_from_slice(&1_500_000i32.to_le_bytes());
+        put_data.extend_from_slice(&[0; 36]);
+
+        let decoded = DecoderRegistry::default().decode(&put_data);
+        assert_eq!((decoded.adc_vbus_v, decoded.adc_ibus_a), (Some(5.0), Some(1.5)));
+        assert_eq!(decoded.km_group_count, Some(3));
+        assert_eq!(decoded.km_groups.as_deref(), Some("pd_packet:12,bit6:4,adc:44"));
+    }
+}
@@ -0,0 +1,121 @@
+use duckdb::types::Value;
+use duckdb::{appender_params_from_iter, Connection};
+use polars::prelude::*;
+use std::path::Path;
+
+use crate::Result;
+
+/// DuckDB column type for a Polars dtype; everything without a native mapping is stored as text
+fn sql_type(dtype: &DataType) -> &'static str {
+    match dtype {
+        DataType::Boolean => "BOOLEAN",
+        DataType::UInt32 => "UINTEGER",
+        DataType::UInt64 => "UBIGINT",
+        DataType::Int32 => "INTEGER",
+        DataType::Int64 => "BIGINT",
+        DataType::Float64 => "DOUBLE",
+        _ => "VARCHAR",
+    }
+}
+
+fn sql_value(value: AnyValue) -> Value {
+    match value {
+        AnyValue::Boolean(b) => Value::Boolean(b),
+        AnyValue::UInt32(v) => Value::UInt(v),
+        AnyValue::UInt64(v) => Value::UBigInt(v),
+        AnyValue::Int32(v) => Value::Int(v),
+        AnyValue::Int64(v) => Value::BigInt(v),
+        AnyValue::Float64(v) => Value::Double(v),
+        AnyValue::String(s) => Value::Text(s.to_string()),
+        AnyValue::StringOwned(s) => Value::Text(s.to_string()),
+        _ => Value::Null,
+    }
+}
+
+fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Insert the DataFrame into a DuckDB table (`--duckdb` / `--table`).
+///
+/// Without `append` the table is replaced. With `append` rows go into the existing
+/// table; columns it lacks are added first, and columns missing from `df` are NULL.
+pub(crate) fn write_duckdb(df: &DataFrame, path: &Path, table: &str, append: bool) -> Result<()> {
+    // Render Categorical and other non-native columns as text so every value maps onto a SQL type
+    let mut df = df.clone();
+    for column in df.get_columns().to_vec() {
+        if sql_type(column.dtype()) == "VARCHAR" && column.dtype() != &DataType::String {
+            df.with_column(column.cast(&DataType::String)?)?;
+        }
+    }
+
+    let conn = Connection::open(path)?;
+    let quoted_table = quote_ident(table);
+    if !append {
+        conn.execute_batch(&format!("DROP TABLE IF EXISTS {}", quoted_table))?;
+    }
+    let columns_sql: Vec<String> = df
+        .get_columns()
+        .iter()
+        .map(|c| format!("{} {}", quote_ident(c.name()), sql_type(c.dtype())))
+        .collect();
+    conn.execute_batch(&format!("CREATE TABLE IF NOT EXISTS {} ({})", quoted_table, columns_sql.join(", ")))?;
+
+    let mut table_columns: Vec<String> = conn
+        .prepare("SELECT column_name FROM information_schema.columns WHERE table_name = ? ORDER BY ordinal_position")?
+        .query_map([table], |row| row.get(0))?
+        .collect::<std::result::Result<_, _>>()?;
+    for column in df.get_columns() {
+        if !table_columns.iter().any(|c| c == column.name().as_str()) {
+            conn.execute_batch(&format!(
+                "ALTER TABLE {} ADD COLUMN {} {}",
+                quoted_table,
+                quote_ident(column.name()),
+                sql_type(column.dtype())
+            ))?;
+            table_columns.push(column.name().to_string());
+        }
+    }
+
+    let ordered: Vec<Option<&Column>> = table_columns.iter().map(|name| df.column(name).ok()).collect();
+    let mut appender = conn.appender(table)?;
+    for row in 0..df.height() {
+        let values = ordered
+            .iter()
+            .map(|column| match column {
+                Some(column) => column.get(row).map(sql_value),
+                None => Ok(Value::Null),
+            })
+            .collect::<PolarsResult<Vec<Value>>>()?;
+        appender.append_row(appender_params_from_iter(values))?;
+    }
+    appender.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn creates_replaces_and_appends_tables() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("packets.duckdb");
+        let first = df!["frame_number" => [1u32, 2], "direction" => ["H->D", "D->H"]].unwrap();
+        let second = df!["frame_number" => [3u32], "adc_vbus_v" => [Some(5.0)]].unwrap();
+
+        write_duckdb(&first, &path, "usb_packets", false).unwrap();
+        write_duckdb(&second, &path, "usb_packets", true).unwrap();
+        let conn = Connection::open(&path).unwrap();
+        let (rows, vbus_rows): (i64, i64) = conn
+            .query_row("SELECT count(*), count(adc_vbus_v) FROM usb_packets", [], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap();
+        assert_eq!((rows, vbus_rows), (3, 1));
+        drop(conn);
+
+        write_duckdb(&first, &path, "usb_packets", false).unwrap();
+        let conn = Connection::open(&path).unwrap();
+        let rows: i64 = conn.query_row("SELECT count(*) FROM usb_packets", [], |r| r.get(0)).unwrap();
+        assert_eq!(rows, 2);
+    }
+}
@@ -0,0 +1,55 @@
+use polars::prelude::*;
+use std::collections::HashMap;
+
+use crate::Result;
+
+/// Append `cumulative_wh`, the energy delivered so far in each session (`--energy`).
+///
+/// Integrates `adc_power_w` over `timestamp` with the trapezoidal rule between
+/// consecutive ADC samples, so uneven polling intervals are weighted by their real
+/// length. Rows without an ADC reading and usbmon double-logs (`is_retransmit`) get null.
+pub(crate) fn add_cumulative_energy(mut df: DataFrame) -> Result<DataFrame> {
+    let sessions = df.column("session_id")?.str()?;
+    let timestamps = df.column("timestamp")?.f64()?;
+    let power = df.column("adc_power_w")?.f64()?;
+    let retransmits = df.column("is_retransmit")?.bool()?;
+
+    let mut order: Vec<usize> = (0..df.height())
+        .filter(|&row| power.get(row).is_some() && timestamps.get(row).is_some() && retransmits.get(row) != Some(true))
+        .collect();
+    order.sort_by(|&a, &b| timestamps.get(a).unwrap().total_cmp(&timestamps.get(b).unwrap()));
+
+    let mut cumulative: Vec<Option<f64>> = vec![None; df.height()];
+    // Per session: (timestamp, power, watt-hours) of the previous sample
+    let mut previous: HashMap<Option<&str>, (f64, f64, f64)> = HashMap::new();
+    for row in order {
+        let (t, p) = (timestamps.get(row).unwrap(), power.get(row).unwrap());
+        let wh = match previous.get(&sessions.get(row)) {
+            Some(&(prev_t, prev_p, prev_wh)) => prev_wh + (p + prev_p) / 2.0 * (t - prev_t) / 3600.0,
+            None => 0.0,
+        };
+        previous.insert(sessions.get(row), (t, p, wh));
+        cumulative[row] = Some(wh);
+    }
+    df.with_column(Column::new("cumulative_wh".into(), cumulative))?;
+    Ok(df)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integrates_power_over_irregular_intervals_per_session() {
+        let df = df![
+            "session_id" => ["a", "a", "b", "a", "a"],
+            "timestamp" => [0.0, 3600.0, 1.0, 3601.0, 10800.0],
+            "adc_power_w" => [Some(10.0), Some(20.0), Some(5.0), None, Some(20.0)],
+            "is_retransmit" => [false, false, false, false, false],
+        ]
+        .unwrap();
+        let wh: Vec<Option<f64>> = add_cumulative_energy(df).unwrap().column("cumulative_wh").unwrap().f64().unwrap().into_iter().collect();
+        // 1 h ramping 10 -> 20 W, then 2 h at 20 W
+        assert_eq!(wh, [Some(0.0), Some(15.0), Some(0.0), None, Some(55.0)]);
+    }
+}
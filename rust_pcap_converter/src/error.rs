@@ -0,0 +1,77 @@
+//! Error type returned by every fallible function in the crate.
+
+use polars::error::PolarsError;
+
+/// Why a conversion step failed
+#[derive(Debug, thiserror::Error)]
+pub enum ConverterError {
+    /// A dissected packet lacks a layer the converter needs (`frame` or `usb`)
+    #[error("Missing {0} layer")]
+    MissingLayer(&'static str),
+
+    /// `usb.capdata` (or another hex field) is not valid hex after normalization
+    #[error("Failed to decode hex payload '{payload}': {source}")]
+    HexDecode {
+        payload: String,
+        #[source]
+        source: hex::FromHexError,
+    },
+
+    /// tshark could not be started, e.g. missing binary or unreadable input
+    #[error("Failed to start tshark: {0}")]
+    TsharkSpawn(#[source] std::io::Error),
+
+    #[error(transparent)]
+    PolarsError(#[from] PolarsError),
+
+    /// File I/O, and tshark failing while its output is being read
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[cfg(feature = "duckdb")]
+    #[error(transparent)]
+    DuckDb(#[from] duckdb::Error),
+
+    /// Invalid options or input that has no dedicated variant
+    #[error("{0}")]
+    Other(String),
+}
+
+impl ConverterError {
+    /// Whether tshark itself failed, which ends the packet stream; every other error
+    /// from [`Converter::records`](crate::Converter::records) concerns one packet
+    pub fn is_tshark_failure(&self) -> bool {
+        matches!(self, ConverterError::TsharkSpawn(_) | ConverterError::Io(_))
+    }
+}
+
+impl From<String> for ConverterError {
+    fn from(message: String) -> Self {
+        ConverterError::Other(message)
+    }
+}
+
+impl From<&str> for ConverterError {
+    fn from(message: &str) -> Self {
+        ConverterError::Other(message.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_messages_and_classifies_tshark_failures() {
+        let hex_err = ConverterError::HexDecode { payload: "0g".to_string(), source: hex::decode("0g").unwrap_err() };
+        assert!(hex_err.to_string().starts_with("Failed to decode hex payload '0g'"));
+        assert!(!hex_err.is_tshark_failure());
+        assert_eq!(ConverterError::MissingLayer("USB").to_string(), "Missing USB layer");
+        assert_eq!(ConverterError::from("bad option").to_string(), "bad option");
+        let spawn = ConverterError::TsharkSpawn(std::io::Error::new(std::io::ErrorKind::NotFound, "tshark"));
+        assert!(spawn.is_tshark_failure());
+    }
+}
@@ -0,0 +1,171 @@
+use polars::prelude::*;
+use std::collections::HashMap;
+
+use crate::km003c::{expected_body_len, CMD_GET_DATA, CMD_PUT_DATA};
+use crate::Result;
+
+/// Decoded response columns carried over into each exchange row
+const RESPONSE_VALUE_COLUMNS: [&str; 4] = ["adc_vbus_v", "adc_ibus_a", "adc_power_w", "adc_temp_c"];
+
+/// One GetData request and/or the PutData that answered it
+#[derive(Default)]
+struct Exchange {
+    session_id: String,
+    transaction_id: u32,
+    request: Option<usize>,
+    response: Option<usize>,
+}
+
+impl Exchange {
+    fn status(&self) -> &'static str {
+        match (self.request, self.response) {
+            (Some(_), Some(_)) => "matched",
+            (Some(_), None) => "unanswered",
+            _ => "unsolicited",
+        }
+    }
+}
+
+/// Pair each GetData request with the PutData response carrying its transaction id
+/// (`--exchanges`), one row per exchange in request order.
+///
+/// Pairing walks the rows in order per session, so a recycled 8-bit transaction id
+/// matches the next response rather than every earlier one. A request superseded
+/// before its answer arrives is `unanswered`; a response with no pending request is
+/// `unsolicited`. `attributes_match` compares the requested mask with the attributes
+/// of the returned logical packets, and `response_complete` compares the body length
+/// the mask calls for (`expected_len`, fixed-size attributes only) with the logical
+/// packet bodies actually returned (`response_len`, measured before `--max-payload`). Usbmon double-logs (`is_retransmit`) are skipped.
+pub fn exchange_table(df: &DataFrame) -> Result<DataFrame> {
+    let sessions = df.column("session_id")?.str()?;
+    let frames = df.column("frame_number")?.u32()?;
+    let timestamps = df.column("timestamp")?.f64()?;
+    let directions = df.column("direction")?.str()?;
+    let packet_types = df.column("km_packet_type")?.u32()?;
+    let transaction_ids = df.column("km_transaction_id")?.u32()?;
+    let masks = df.column("km_attribute")?.u32()?;
+    let returned = df.column("km_logical_attributes")?.u32()?;
+    let names = df.column("km_attribute_name")?.str()?;
+    let body_lengths = df.column("km_logical_body_len")?.u32()?;
+    let retransmits = df.column("is_retransmit")?.bool()?;
+
+    let mut exchanges: Vec<Exchange> = Vec::new();
+    let mut pending: HashMap<(&str, u32), usize> = HashMap::new();
+    for row in 0..df.height() {
+        let (Some(session), Some(packet_type), Some(tid)) = (sessions.get(row), packet_types.get(row), transaction_ids.get(row))
+        else {
+            continue;
+        };
+        if retransmits.get(row) == Some(true) {
+            continue;
+        }
+        let key = (session, tid);
+        match (packet_type as u8, directions.get(row)) {
+            (CMD_GET_DATA, Some("H->D")) => {
+                let exchange = Exchange { session_id: session.to_string(), transaction_id: tid, request: Some(row), response: None };
+                exchanges.push(exchange);
+                pending.insert(key, exchanges.len() - 1);
+            }
+            (CMD_PUT_DATA, Some("D->H")) => match pending.remove(&key) {
+                Some(index) => exchanges[index].response = Some(row),
+                None => exchanges.push(Exchange { session_id: session.to_string(), transaction_id: tid, request: None, response: Some(row) }),
+            },
+            _ => {}
+        }
+    }
+
+    let requested_mask: Vec<Option<u32>> = exchanges.iter().map(|e| e.request.and_then(|row| masks.get(row))).collect();
+    let returned_mask: Vec<Option<u32>> = exchanges.iter().map(|e| e.response.and_then(|row| returned.get(row))).collect();
+    let expected_len: Vec<Option<u32>> =
+        requested_mask.iter().map(|mask| expected_body_len((*mask)? as u16).map(|len| len as u32)).collect();
+    let response_len: Vec<Option<u32>> = exchanges.iter().map(|e| e.response.and_then(|row| body_lengths.get(row))).collect();
+    let mut columns = vec![
+        Column::new("session_id".into(), exchanges.iter().map(|e| e.session_id.clone()).collect::<Vec<_>>()),
+        Column::new("transaction_id".into(), exchanges.iter().map(|e| e.transaction_id).collect::<Vec<_>>()),
+        Column::new("status".into(), exchanges.iter().map(Exchange::status).collect::<Vec<_>>()),
+        Column::new("request_frame".into(), exchanges.iter().map(|e| e.request.and_then(|row| frames.get(row))).collect::<Vec<_>>()),
+        Column::new("response_frame".into(), exchanges.iter().map(|e| e.response.and_then(|row| frames.get(row))).collect::<Vec<_>>()),
+        Column::new(
+            "latency_us".into(),
+            exchanges
+                .iter()
+                .map(|e| Some((timestamps.get(e.response?)? - timestamps.get(e.request?)?) * 1e6))
+                .collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "requested_attributes".into(),
+            exchanges.iter().map(|e| e.request.and_then(|row| names.get(row))).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "returned_attributes".into(),
+            exchanges.iter().map(|e| e.response.and_then(|row| names.get(row))).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "attributes_match".into(),
+            requested_mask.iter().zip(&returned_mask).map(|(req, ret)| Some(req.as_ref()? == ret.as_ref()?)).collect::<Vec<_>>(),
+        ),
+        Column::new("expected_len".into(), &expected_len),
+        Column::new("response_len".into(), &response_len),
+        Column::new(
+            "response_complete".into(),
+            expected_len.iter().zip(&response_len).map(|(expected, got)| Some(expected.as_ref()? == got.as_ref()?)).collect::<Vec<_>>(),
+        ),
+    ];
+    for name in RESPONSE_VALUE_COLUMNS {
+        let values = df.column(name)?.f64()?;
+        let returned: Vec<Option<f64>> = exchanges.iter().map(|e| e.response.and_then(|row| values.get(row))).collect();
+        columns.push(Column::new(name.into(), returned));
+    }
+    Ok(DataFrame::new(columns)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_dataframe;
+    use crate::test_support::{adc_put_data, bulk};
+
+    #[test]
+    fn pairs_get_data_with_put_data_by_transaction_id() {
+        // 5.0 V, 1.5 A ADC response to transaction 0x0a
+        let records = vec![
+            bulk(1, "0", "0c:0a:02:00"),
+            bulk(2, "1", &adc_put_data(0x0a, 5_000_000, 1_500_000)),
+            bulk(3, "0", "0c:0b:02:00"),
+            bulk(4, "1", &adc_put_data(0x0c, 5_000_000, 1_500_000)),
+        ];
+        let exchanges = exchange_table(&create_dataframe(records).unwrap()).unwrap();
+
+        let status: Vec<_> = exchanges.column("status").unwrap().str().unwrap().into_iter().flatten().collect();
+        assert_eq!(status, ["matched", "unanswered", "unsolicited"]);
+        let frames = |name| exchanges.column(name).unwrap().u32().unwrap().into_iter().collect::<Vec<_>>();
+        assert_eq!(frames("request_frame"), [Some(1), Some(3), None]);
+        assert_eq!(frames("response_frame"), [Some(2), None, Some(4)]);
+        assert_eq!(exchanges.column("attributes_match").unwrap().bool().unwrap().get(0), Some(true));
+        assert_eq!(exchanges.column("adc_vbus_v").unwrap().f64().unwrap().get(0), Some(5.0));
+        assert!((exchanges.column("latency_us").unwrap().f64().unwrap().get(0).unwrap() - 1000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn flags_responses_shorter_than_the_requested_attributes() {
+        let adc = adc_put_data(0x0a, 0, 0);
+        // Temperature-only ADC body: 0x08 bytes instead of 44
+        let short = format!("41:0b:82:02:01:00:00:02:{}", ["00"; 8].join(":"));
+        let records = vec![bulk(1, "0", "0c:0a:02:00"), bulk(2, "1", &adc), bulk(3, "0", "0c:0b:02:00"), bulk(4, "1", &short)];
+        let exchanges = exchange_table(&create_dataframe(records).unwrap()).unwrap();
+
+        let lengths = |name| exchanges.column(name).unwrap().u32().unwrap().into_iter().collect::<Vec<_>>();
+        assert_eq!(lengths("expected_len"), [Some(44), Some(44)]);
+        assert_eq!(lengths("response_len"), [Some(44), Some(8)]);
+        let complete: Vec<_> = exchanges.column("response_complete").unwrap().bool().unwrap().into_iter().collect();
+        assert_eq!(complete, [Some(true), Some(false)]);
+
+        // --max-payload cuts payload_hex after decoding; the full response still counts as complete
+        let mut truncated = bulk(2, "1", &adc);
+        truncated.truncate_payload(8);
+        let records = vec![bulk(1, "0", "0c:0a:02:00"), truncated];
+        let exchanges = exchange_table(&create_dataframe(records).unwrap()).unwrap();
+        assert_eq!(exchanges.column("response_len").unwrap().u32().unwrap().get(0), Some(44));
+        assert_eq!(exchanges.column("response_complete").unwrap().bool().unwrap().get(0), Some(true));
+    }
+}
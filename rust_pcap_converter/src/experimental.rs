@@ -0,0 +1,167 @@
+//! `--experimental-field`: decode an ad-hoc payload field into a named column.
+//!
+//! A research aid for trying out field layouts against a real capture without recompiling.
+//! Unstable: the spec syntax and the columns it produces may change in any release.
+
+use polars::prelude::*;
+use std::str::FromStr;
+
+use crate::Result;
+
+/// Binary encoding of an experimental field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldType {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    U64,
+    I64,
+    F32,
+    F64,
+}
+
+impl FieldType {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "u8" => Self::U8,
+            "i8" => Self::I8,
+            "u16" => Self::U16,
+            "i16" => Self::I16,
+            "u32" => Self::U32,
+            "i32" => Self::I32,
+            "u64" => Self::U64,
+            "i64" => Self::I64,
+            "f32" => Self::F32,
+            "f64" => Self::F64,
+            _ => return None,
+        })
+    }
+
+    fn width(self) -> usize {
+        match self {
+            Self::U8 | Self::I8 => 1,
+            Self::U16 | Self::I16 => 2,
+            Self::U32 | Self::I32 | Self::F32 => 4,
+            Self::U64 | Self::I64 | Self::F64 => 8,
+        }
+    }
+}
+
+/// One ad-hoc field, parsed from `NAME=OFFSET:TYPE[:le|be][:SCALE]`.
+///
+/// `OFFSET` is a byte offset into the payload (decimal or `0x` hex), `TYPE` one of
+/// `u8`..`u64`, `i8`..`i64`, `f32` or `f64`. Little-endian and a scale of 1 are the
+/// defaults; the decoded value times the scale becomes a Float64 column `NAME`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExperimentalField {
+    name: String,
+    offset: usize,
+    ty: FieldType,
+    big_endian: bool,
+    scale: f64,
+}
+
+impl FromStr for ExperimentalField {
+    type Err = String;
+
+    fn from_str(spec: &str) -> std::result::Result<Self, String> {
+        let usage = || format!("invalid experimental field {:?}, expected NAME=OFFSET:TYPE[:le|be][:SCALE]", spec);
+        let (name, layout) = spec.split_once('=').ok_or_else(usage)?;
+        let mut parts = layout.split(':');
+        let offset = parts.next().ok_or_else(usage)?;
+        let offset = match offset.strip_prefix("0x") {
+            Some(hex) => usize::from_str_radix(hex, 16),
+            None => offset.parse(),
+        }
+        .map_err(|_| usage())?;
+        let ty = parts.next().and_then(FieldType::from_name).ok_or_else(usage)?;
+        let mut big_endian = false;
+        let mut scale = 1.0;
+        let mut rest = parts.peekable();
+        if let Some(endian @ ("le" | "be")) = rest.peek().copied() {
+            big_endian = endian == "be";
+            rest.next();
+        }
+        if let Some(value) = rest.next() {
+            scale = value.parse().map_err(|_| usage())?;
+        }
+        if name.is_empty() || rest.next().is_some() {
+            return Err(usage());
+        }
+        Ok(Self { name: name.to_string(), offset, ty, big_endian, scale })
+    }
+}
+
+impl ExperimentalField {
+    /// Decoded value, or `None` when the payload is too short
+    fn decode(&self, payload: &[u8]) -> Option<f64> {
+        let mut bytes = payload.get(self.offset..self.offset + self.ty.width())?.to_vec();
+        if self.big_endian {
+            bytes.reverse();
+        }
+        let mut le = [0u8; 8];
+        le[..bytes.len()].copy_from_slice(&bytes);
+        let value = match self.ty {
+            FieldType::U8 => le[0] as f64,
+            FieldType::I8 => le[0] as i8 as f64,
+            FieldType::U16 => u16::from_le_bytes([le[0], le[1]]) as f64,
+            FieldType::I16 => i16::from_le_bytes([le[0], le[1]]) as f64,
+            FieldType::U32 => u32::from_le_bytes([le[0], le[1], le[2], le[3]]) as f64,
+            FieldType::I32 => i32::from_le_bytes([le[0], le[1], le[2], le[3]]) as f64,
+            FieldType::U64 => u64::from_le_bytes(le) as f64,
+            FieldType::I64 => i64::from_le_bytes(le) as f64,
+            FieldType::F32 => f32::from_le_bytes([le[0], le[1], le[2], le[3]]) as f64,
+            FieldType::F64 => f64::from_le_bytes(le),
+        };
+        Some(value * self.scale)
+    }
+}
+
+/// Append one column per experimental field, decoded from `payload_hex`
+pub(crate) fn add_experimental_columns(mut df: DataFrame, fields: &[ExperimentalField]) -> Result<DataFrame> {
+    if fields.is_empty() {
+        return Ok(df);
+    }
+    let payloads: Vec<Option<Vec<u8>>> =
+        df.column("payload_hex")?.str()?.into_iter().map(|hex_payload| hex_payload.and_then(|p| hex::decode(p).ok())).collect();
+    for field in fields {
+        if df.get_column_index(&field.name).is_some() {
+            return Err(format!("--experimental-field {} clashes with an existing column", field.name).into());
+        }
+        let values: Vec<Option<f64>> = payloads.iter().map(|payload| payload.as_deref().and_then(|p| field.decode(p))).collect();
+        df.with_column(Column::new(field.name.as_str().into(), values))?;
+    }
+    Ok(df)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_specs_with_defaults() {
+        let field: ExperimentalField = "vbus=0x08:i32".parse().unwrap();
+        assert_eq!((field.offset, field.ty, field.big_endian, field.scale), (8, FieldType::I32, false, 1.0));
+        let field: ExperimentalField = "t=2:u16:be:0.5".parse().unwrap();
+        assert_eq!((field.big_endian, field.scale), (true, 0.5));
+        assert_eq!("t=2:u16:1e-3".parse::<ExperimentalField>().unwrap().scale, 1e-3);
+        for bad in ["2:u16", "t=2:u24", "t=x:u8", "t=2:u8:le:1:extra"] {
+            assert!(bad.parse::<ExperimentalField>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn decodes_payload_fields_into_columns() {
+        let df = df!("payload_hex" => [Some("0102fffe"), Some("01"), None]).unwrap();
+        let fields = ["le=0:u16".parse().unwrap(), "be=0:u16:be".parse().unwrap(), "neg=2:i16:le:0.5".parse().unwrap()];
+        let df = add_experimental_columns(df, &fields).unwrap();
+        let values = |name| df.column(name).unwrap().f64().unwrap().into_iter().collect::<Vec<_>>();
+        assert_eq!(values("le"), vec![Some(513.0), None, None]);
+        assert_eq!(values("be"), vec![Some(258.0), None, None]);
+        assert_eq!(values("neg"), vec![Some(-128.5), None, None]);
+        assert!(add_experimental_columns(df, &["le=0:u8".parse().unwrap()]).is_err());
+    }
+}
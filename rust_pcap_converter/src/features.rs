@@ -0,0 +1,64 @@
+use polars::prelude::*;
+
+use crate::Result;
+
+/// Value used for payload bytes past the end of a short payload and for rows without a
+/// decoded KM003C header, so every cell of the matrix is a plain integer
+pub const FEATURE_PADDING: i16 = -1;
+
+/// Fixed-width numeric matrix for classifiers (`--features`).
+///
+/// One row per packet: `frame_number`, `km_packet_type` and `payload_len`, then the first
+/// `width` payload bytes as `b0`..`b{width-1}`. String columns are dropped and missing
+/// values are [`FEATURE_PADDING`] rather than null.
+pub fn feature_matrix(df: &DataFrame, width: usize) -> Result<DataFrame> {
+    if width == 0 {
+        return Err("--features needs at least 1 payload byte".into());
+    }
+    let payloads: Vec<Vec<u8>> = df
+        .column("payload_hex")?
+        .str()?
+        .into_iter()
+        .map(|hex_payload| hex_payload.and_then(|p| hex::decode(p).ok()).unwrap_or_default())
+        .collect();
+    let packet_types: Vec<i16> = df
+        .column("km_packet_type")?
+        .u32()?
+        .into_iter()
+        .map(|t| t.map_or(FEATURE_PADDING, |t| t as i16))
+        .collect();
+
+    let mut columns = vec![
+        df.column("frame_number")?.clone(),
+        Column::new("km_packet_type".into(), packet_types),
+        Column::new("payload_len".into(), payloads.iter().map(|p| p.len() as u32).collect::<Vec<_>>()),
+    ];
+    for index in 0..width {
+        let bytes: Vec<i16> = payloads.iter().map(|p| p.get(index).map_or(FEATURE_PADDING, |&b| b as i16)).collect();
+        columns.push(Column::new(format!("b{}", index).into(), bytes));
+    }
+    Ok(DataFrame::new(columns)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pads_short_payloads_and_missing_types() {
+        let df = df![
+            "frame_number" => [1u32, 2],
+            "km_packet_type" => [Some(0x0Cu32), None],
+            "payload_hex" => ["0c0a0200", "ff"],
+        ]
+        .unwrap();
+        let features = feature_matrix(&df, 3).unwrap();
+
+        assert_eq!(features.get_column_names(), ["frame_number", "km_packet_type", "payload_len", "b0", "b1", "b2"]);
+        let column = |name| features.column(name).unwrap().i16().unwrap().into_no_null_iter().collect::<Vec<_>>();
+        assert_eq!(column("km_packet_type"), [12, -1]);
+        assert_eq!(column("b0"), [0x0C, 0xFF]);
+        assert_eq!(column("b2"), [0x02, -1]);
+        assert!(feature_matrix(&df, 0).is_err());
+    }
+}
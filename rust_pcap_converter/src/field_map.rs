@@ -0,0 +1,91 @@
+//! Field names that differ between tshark versions.
+//!
+//! [`process_packet`](crate::process_packet) asks for the current dissector names;
+//! [`FieldMap`] falls back to the names older (or newer) releases emit, so control
+//! and endpoint columns are not silently null on another Wireshark version.
+
+use log::debug;
+use std::collections::HashSet;
+use std::sync::{LazyLock, Mutex};
+
+use crate::source::MetadataSource;
+
+/// Current field name and the alternatives other tshark releases use for it
+const FIELD_ALIASES: &[(&str, &[&str])] = &[
+    ("usb.bmRequestType", &["usb.setup.bmRequestType"]),
+    ("usb.setup.bRequest", &["usb.bRequest"]),
+    ("usb.setup.wValue", &["usb.wValue"]),
+    ("usb.setup.wIndex", &["usb.wIndex"]),
+    ("usb.setup.wLength", &["usb.wLength"]),
+    ("usb.setup.wValue.descriptor_index", &["usb.DescriptorIndex"]),
+    ("usb.setup.wValue.language_id", &["usb.LanguageId"]),
+    // Wireshark before 3.2 named the endpoint address field usb.endpoint_number
+    ("usb.endpoint_address", &["usb.endpoint_number"]),
+    ("usb.endpoint_address.direction", &["usb.endpoint_number.direction"]),
+    ("usb.endpoint_address.number", &["usb.endpoint_number.endpoint"]),
+];
+
+/// Aliases already reported in verbose mode, so each is logged once per run
+static LOGGED_ALIASES: LazyLock<Mutex<HashSet<&'static str>>> = LazyLock::new(Default::default);
+
+/// Known alternatives for a field, empty when it has none
+pub(crate) fn aliases(key: &str) -> &'static [&'static str] {
+    FIELD_ALIASES.iter().find(|(name, _)| *name == key).map_or(&[], |(_, aliases)| *aliases)
+}
+
+/// A layer whose lookups fall back to [`FIELD_ALIASES`] when the current name is absent
+pub(crate) struct FieldMap<'a, L> {
+    layer: &'a L,
+    verbose: bool,
+}
+
+impl<'a, L: MetadataSource> FieldMap<'a, L> {
+    pub(crate) fn new(layer: &'a L, verbose: bool) -> Self {
+        Self { layer, verbose }
+    }
+}
+
+impl<L: MetadataSource> MetadataSource for FieldMap<'_, L> {
+    fn get(&self, key: &str) -> Option<&str> {
+        if let Some(value) = self.layer.get(key) {
+            return Some(value);
+        }
+        let (alias, value) = aliases(key).iter().find_map(|alias| self.layer.get(alias).map(|value| (*alias, value)))?;
+        if self.verbose && LOGGED_ALIASES.lock().is_ok_and(|mut logged| logged.insert(alias)) {
+            debug!("tshark field {} not present; using {} (older/newer Wireshark naming)", key, alias);
+        }
+        Some(value)
+    }
+
+    fn get_all(&self, key: &str) -> Vec<&str> {
+        let values = self.layer.get_all(key);
+        if !values.is_empty() {
+            return values;
+        }
+        aliases(key).iter().map(|alias| self.layer.get_all(alias)).find(|values| !values.is_empty()).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn falls_back_to_aliases_only_when_the_current_name_is_missing() {
+        let old = HashMap::from([
+            ("usb.setup.bmRequestType".to_string(), "0xc2".to_string()),
+            ("usb.endpoint_number".to_string(), "0x81".to_string()),
+        ]);
+        let layer = FieldMap::new(&old, false);
+        assert_eq!(layer.get("usb.bmRequestType"), Some("0xc2"));
+        assert_eq!(layer.get("usb.endpoint_address"), Some("0x81"));
+        assert_eq!(layer.get("usb.urb_id"), None);
+
+        let both = HashMap::from([
+            ("usb.bmRequestType".to_string(), "0x40".to_string()),
+            ("usb.setup.bmRequestType".to_string(), "0xc2".to_string()),
+        ]);
+        assert_eq!(FieldMap::new(&both, false).get("usb.bmRequestType"), Some("0x40"));
+    }
+}
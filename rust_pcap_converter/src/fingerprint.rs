@@ -0,0 +1,66 @@
+//! `fingerprint` subcommand: a hash of a converted file's content that ignores when it was converted.
+
+use polars::prelude::*;
+use polars_utils::plpath::PlPath;
+use std::path::Path;
+
+use crate::record::fnv1a_hex;
+use crate::Result;
+
+/// Columns that differ between two conversions of the same capture: the conversion time and
+/// wall-clock capture times
+const VOLATILE_COLUMNS: [&str; 5] = ["added_datetime", "timestamp_absolute", "timestamp_utc", "urb_ts_sec", "urb_ts_usec"];
+
+/// Stable hash of the rows of `df` outside [`VOLATILE_COLUMNS`].
+///
+/// Columns are taken in name order and rows are sorted by their rendered values, so
+/// neither the column layout nor `--sort-on-write` changes the result.
+pub fn dataframe_fingerprint(df: &DataFrame) -> Result<String> {
+    let mut columns: Vec<&Column> = df.get_columns().iter().filter(|c| !VOLATILE_COLUMNS.contains(&c.name().as_str())).collect();
+    columns.sort_by_key(|c| c.name().as_str());
+
+    let mut rows = vec![String::new(); df.height()];
+    for column in &columns {
+        for (row, value) in rows.iter_mut().zip(column.as_materialized_series().iter()) {
+            row.push_str(&value.to_string());
+            row.push('\u{1f}');
+        }
+    }
+    rows.sort_unstable();
+    let header: Vec<&str> = columns.iter().map(|c| c.name().as_str()).collect();
+    let header = header.join("\u{1f}");
+    Ok(fnv1a_hex(&std::iter::once(header.as_str()).chain(rows.iter().map(String::as_str)).collect::<Vec<_>>()))
+}
+
+/// Print the fingerprint of a converted Parquet file (`fingerprint` subcommand)
+pub fn print_parquet_fingerprint(path: &Path) -> Result<()> {
+    let path_str = path.to_str().ok_or("Parquet path is not valid UTF-8")?;
+    let df = LazyFrame::scan_parquet(PlPath::new(path_str), ScanArgsParquet::default())?.collect()?;
+    println!("{}  {}", dataframe_fingerprint(&df)?, path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignores_volatile_columns_and_row_order() {
+        let df = df![
+            "frame_number" => [1u32, 2],
+            "payload_hex" => ["0c0a0200", "410a"],
+            "added_datetime" => ["2024-01-01 10:00:00", "2024-01-01 10:00:00"],
+        ]
+        .unwrap();
+        let reconverted = df![
+            "added_datetime" => ["2025-06-30 08:00:00", "2025-06-30 08:00:00"],
+            "payload_hex" => ["410a", "0c0a0200"],
+            "frame_number" => [2u32, 1],
+        ]
+        .unwrap();
+        let changed = df!["frame_number" => [1u32, 2], "payload_hex" => ["0c0a0200", "410b"]].unwrap();
+
+        assert_eq!(dataframe_fingerprint(&df).unwrap(), dataframe_fingerprint(&reconverted).unwrap());
+        assert_ne!(dataframe_fingerprint(&df).unwrap(), dataframe_fingerprint(&changed).unwrap());
+    }
+}
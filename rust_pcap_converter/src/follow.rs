@@ -0,0 +1,185 @@
+//! `--follow`: convert a capture that is still being written, appending new frames as they land.
+
+use log::{debug, info};
+use polars::prelude::*;
+use polars_utils::plpath::PlPath;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::config::{parquet_compression, ConverterConfig, OutputFormat};
+use crate::convert::{check_overwrite, templated_output, RowPipeline};
+use crate::converter::{Converter, FrameFilter};
+use crate::input::STDIN_INPUT;
+use crate::metadata::{write_manifest, RunMetadata};
+use crate::Result;
+
+/// Pause between re-reads of the growing capture
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Re-read the input every [`POLL_INTERVAL`] and append frames past the last one written.
+///
+/// Runs until the process is interrupted. Each batch is written to a temporary file and
+/// renamed over the output, so stopping at any point leaves a complete Parquet file. A
+/// tshark error on the last, half-written block of the capture ends that poll only.
+pub(crate) fn follow(config: &ConverterConfig) -> Result<()> {
+    if config.format != OutputFormat::Parquet || config.partition_by.is_some() || config.duckdb.is_some() {
+        return Err("--follow only writes a single Parquet file (no --format csv, --partition-by or --duckdb)".into());
+    }
+    if config.resume
+        || config.batch_size.is_some()
+        || config.coalesce_urbs
+        || config.anonymize
+        || config.check_transactions
+        || config.check_drops
+        || config.normalize
+        || config.sample_rate.is_some()
+        || config.wasm_decoder.is_some()
+        || config.energy
+        || !config.exclude_frames.is_empty()
+        || config.schema.is_some()
+        || config.continuous_time
+        || config.device_name
+        || config.transaction_id.is_some()
+    {
+        return Err("--follow cannot be combined with --resume, --batch-size, --coalesce-urbs, --anonymize, --check-transactions, --check-drops, --normalize, --sample-rate, --wasm-decoder, --energy, --exclude-frames, --schema, --continuous-time, --device-name or --transaction-id".into());
+    }
+    if config.input == Path::new(STDIN_INPUT) {
+        return Err("--follow needs a capture file to re-read, not stdin".into());
+    }
+    let compression = parquet_compression(config.compression, config.compression_level)?;
+    // Each poll's rows get the same columns and filters as a one-shot conversion
+    let mut pipeline = RowPipeline::new(config)?;
+
+    // Pin the address and session so later polls neither rescan nor re-expand templates
+    let mut config = config.clone();
+    let first = Converter::new(config.clone())?;
+    config.device_address = Some(first.device_address());
+    config.session_id = Some(first.session_id().to_string());
+    if let Some(output) = templated_output(&config, first.input_stem(), first.device_address())? {
+        config.output = output;
+    }
+    let run_metadata = RunMetadata {
+        tshark_version: first.tshark_version(),
+        display_filter: first.display_filter(),
+        source_file: config.input.display().to_string(),
+        sample_rate: None,
+    };
+    drop(first);
+    check_overwrite(&config.output, &config)?;
+
+    let mut appending = config.append && config.output.exists();
+    let mut last_frame = if appending { stored_last_frame(&config.output, config.session_id.as_deref().unwrap_or_default())? } else { 0 };
+    info!("Following {:?} into {:?} (Ctrl-C to stop)", config.input, config.output);
+    if last_frame > 0 {
+        info!("Continuing after stored frame {}", last_frame);
+    }
+
+    loop {
+        let mut converter = Converter::new(config.clone())?;
+        converter.push_filter(FrameFilter::AfterFrame(last_frame));
+        let mut records = Vec::new();
+        for result in converter.records() {
+            match result {
+                Ok(record) => records.push(record),
+                Err(e) if e.is_tshark_failure() => {
+                    debug!("Capture tail not readable yet, retrying next poll: {}", e);
+                    break;
+                }
+                Err(_) => continue,
+            }
+        }
+
+        if let Some(max_frame) = records.iter().map(|r| r.frame_number).max() {
+            let batch = pipeline.dataframe(records, &config)?;
+            let batch = pipeline.select(batch, &config)?;
+            let count = batch.height();
+            let written = append_batch(&config.output, batch, appending, compression, &run_metadata)?;
+            if config.manifest {
+                write_manifest(&written, &config.output, &run_metadata)?;
+            }
+            appending = true;
+            last_frame = max_frame;
+            info!("Appended {} records through frame {}", count, last_frame);
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Highest frame number already written for this session
+fn stored_last_frame(output: &Path, session_id: &str) -> Result<u32> {
+    let path = output.to_str().ok_or("Output path is not valid UTF-8")?;
+    let stored = LazyFrame::scan_parquet(PlPath::new(path), ScanArgsParquet::default())?
+        .filter(col("session_id").eq(lit(session_id)))
+        .select([col("frame_number").max()])
+        .collect()?;
+    Ok(stored.column("frame_number")?.u32()?.get(0).unwrap_or(0))
+}
+
+/// Write `batch` to `output`, after any rows already there when `append` is set; returns
+/// the rows now in the file
+fn append_batch(
+    output: &Path,
+    batch: DataFrame,
+    append: bool,
+    compression: ParquetCompression,
+    run_metadata: &RunMetadata,
+) -> Result<DataFrame> {
+    let mut df = if append {
+        let path = output.to_str().ok_or("Output path is not valid UTF-8")?;
+        let existing = LazyFrame::scan_parquet(PlPath::new(path), ScanArgsParquet::default())?.collect()?;
+        polars::functions::concat_df_diagonal(&[existing, batch])?
+    } else {
+        batch
+    };
+    let temp_output = {
+        let mut path = output.as_os_str().to_owned();
+        path.push(".tmp");
+        PathBuf::from(path)
+    };
+    ParquetWriter::new(std::fs::File::create(&temp_output)?)
+        .with_compression(compression)
+        .with_key_value_metadata(Some(run_metadata.key_value_metadata()))
+        .finish(&mut df)?;
+    std::fs::rename(&temp_output, output)?;
+    Ok(df)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::packet_with;
+    use crate::{create_dataframe, process_packet};
+
+    fn batch(session: &str, frames: &[u32]) -> DataFrame {
+        let records = frames
+            .iter()
+            .map(|frame| {
+                let packet = packet_with(&[("frame.number", &frame.to_string())], &[("usb.device_address", "16")]);
+                process_packet(&packet, session, false).unwrap()
+            })
+            .collect();
+        create_dataframe(records).unwrap()
+    }
+
+    #[test]
+    fn appends_batches_and_resumes_after_last_frame() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("live.parquet");
+        let run = RunMetadata { tshark_version: None, display_filter: String::new(), source_file: String::new(), sample_rate: None };
+
+        append_batch(&output, batch("live", &[1, 2]), false, ParquetCompression::Uncompressed, &run).unwrap();
+        append_batch(&output, batch("live", &[5]), true, ParquetCompression::Uncompressed, &run).unwrap();
+        append_batch(&output, batch("other", &[9]), true, ParquetCompression::Uncompressed, &run).unwrap();
+        assert_eq!(stored_last_frame(&output, "live").unwrap(), 5);
+        assert_eq!(stored_last_frame(&output, "missing").unwrap(), 0);
+    }
+
+    #[test]
+    fn rejects_incompatible_options() {
+        let config = ConverterConfig::new("capture.16.pcapng").follow(true).format(OutputFormat::Csv);
+        assert!(follow(&config).unwrap_err().to_string().contains("--follow"));
+        let per_capture = ConverterConfig::new("capture.16.pcapng").follow(true).transaction_id(3);
+        assert!(follow(&per_capture).unwrap_err().to_string().contains("--transaction-id"));
+        assert!(follow(&ConverterConfig::new("-").follow(true)).is_err());
+    }
+}
@@ -0,0 +1,62 @@
+//! `--format framestream`: KM003C protocol payloads as a length-prefixed binary stream.
+
+use polars::prelude::*;
+use std::io::Write;
+
+use crate::{ConverterError, Result};
+
+/// Columns a frame is taken from
+const FRAMESTREAM_COLUMNS: [&str; 4] = ["km_packet_type", "payload_hex", "payload_truncated_by_flag", "is_retransmit"];
+
+/// Write each KM003C application payload as `[u32 little-endian length][payload bytes]`, in row order.
+///
+/// Only rows with a parsed KM003C header are emitted, and usbmon double-logs
+/// (`is_retransmit`) are skipped so every message appears once. Payloads cut by
+/// `--max-payload` would desync a reader, so they are an error. Returns the number of frames written.
+pub(crate) fn write_frame_stream(df: &DataFrame, out: &mut impl Write) -> Result<usize> {
+    let missing: Vec<&str> = FRAMESTREAM_COLUMNS.into_iter().filter(|c| df.get_column_index(c).is_none()).collect();
+    if !missing.is_empty() {
+        return Err(format!("--format framestream needs column(s) {} (check --fields / --query)", missing.join(", ")).into());
+    }
+    let rows = df
+        .clone()
+        .lazy()
+        .filter(col("km_packet_type").is_not_null().and(col("is_retransmit").neq(lit(true))))
+        .select([col("payload_hex"), col("payload_truncated_by_flag")])
+        .collect()?;
+    if rows.column("payload_truncated_by_flag")?.bool()?.any() {
+        return Err("--format framestream needs whole payloads; drop --max-payload".into());
+    }
+
+    let mut frames = 0;
+    for payload in rows.column("payload_hex")?.str()?.into_iter().flatten() {
+        let bytes = hex::decode(payload).map_err(|source| ConverterError::HexDecode { payload: payload.to_string(), source })?;
+        out.write_all(&(bytes.len() as u32).to_le_bytes())?;
+        out.write_all(&bytes)?;
+        frames += 1;
+    }
+    Ok(frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefixes_each_protocol_payload_with_its_length() {
+        let df = df![
+            "km_packet_type" => [Some(0x0Cu32), None, Some(0x41), Some(0x41)],
+            "payload_hex" => ["0c0a0200", "12010002", "410a8202", "410a8202"],
+            "payload_truncated_by_flag" => [None::<bool>, None, None, None],
+            "is_retransmit" => [false, false, false, true],
+        ]
+        .unwrap();
+
+        let mut out = Vec::new();
+        assert_eq!(write_frame_stream(&df, &mut out).unwrap(), 2);
+        assert_eq!(out, [4, 0, 0, 0, 0x0c, 0x0a, 0x02, 0x00, 4, 0, 0, 0, 0x41, 0x0a, 0x82, 0x02]);
+
+        let truncated = df.lazy().with_column(lit(true).alias("payload_truncated_by_flag")).collect().unwrap();
+        assert!(write_frame_stream(&truncated, &mut Vec::new()).unwrap_err().to_string().contains("--max-payload"));
+    }
+}
@@ -0,0 +1,98 @@
+//! `--format influx`: decoded ADC rows as InfluxDB line protocol.
+
+use polars::prelude::*;
+use std::io::Write;
+
+use crate::Result;
+
+/// Measurement name of every emitted line
+const MEASUREMENT: &str = "km003c";
+
+/// Columns a line is built from
+const INFLUX_COLUMNS: [&str; 6] = ["session_id", "timestamp", "timestamp_utc", "adc_vbus_v", "adc_ibus_a", "adc_power_w"];
+
+/// Write one `km003c,session=<id> vbus=..,ibus=..,power=.. <ns>` line per decoded ADC row.
+///
+/// Timestamps are wall-clock nanoseconds from `timestamp_utc`, or the capture-relative
+/// time when that is blank (`--anonymize`). Returns the number of lines written.
+pub(crate) fn write_line_protocol(df: &DataFrame, out: &mut impl Write) -> Result<usize> {
+    let missing: Vec<&str> = INFLUX_COLUMNS.into_iter().filter(|c| df.get_column_index(c).is_none()).collect();
+    if !missing.is_empty() {
+        return Err(format!("--format influx needs column(s) {} (check --fields / --query)", missing.join(", ")).into());
+    }
+    let rows = df
+        .clone()
+        .lazy()
+        .filter(col("adc_vbus_v").is_not_null().and(col("adc_ibus_a").is_not_null()).and(col("adc_power_w").is_not_null()))
+        .select([
+            col("session_id").cast(DataType::String),
+            col("timestamp_utc")
+                .cast(DataType::Int64)
+                .fill_null((col("timestamp") * lit(1e6)).round(0, RoundMode::HalfToEven).cast(DataType::Int64))
+                .alias("timestamp_us"),
+            col("adc_vbus_v"),
+            col("adc_ibus_a"),
+            col("adc_power_w"),
+        ])
+        .collect()?;
+
+    let sessions = rows.column("session_id")?.str()?;
+    let timestamps = rows.column("timestamp_us")?.i64()?;
+    let vbus = rows.column("adc_vbus_v")?.f64()?;
+    let ibus = rows.column("adc_ibus_a")?.f64()?;
+    let power = rows.column("adc_power_w")?.f64()?;
+    for i in 0..rows.height() {
+        writeln!(
+            out,
+            "{},session={} vbus={},ibus={},power={} {}",
+            MEASUREMENT,
+            escape_tag(sessions.get(i).unwrap_or_default()),
+            vbus.get(i).unwrap_or_default(),
+            ibus.get(i).unwrap_or_default(),
+            power.get(i).unwrap_or_default(),
+            timestamps.get(i).unwrap_or_default() * 1000,
+        )?;
+    }
+    Ok(rows.height())
+}
+
+/// Backslash-escape the characters line protocol treats specially in tag values
+fn escape_tag(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, ',' | '=' | ' ') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_only_adc_rows_with_nanosecond_timestamps() {
+        let df = df![
+            "session_id" => ["bench a", "bench a", "bench a"],
+            "timestamp" => [0.5, 0.75, 1.25],
+            "timestamp_utc" => [Some(1_700_000_000_000_001i64), Some(1_700_000_000_250_000), None],
+            "adc_vbus_v" => [Some(5.0), None, Some(9.0)],
+            "adc_ibus_a" => [Some(1.5), None, Some(2.0)],
+            "adc_power_w" => [Some(7.5), None, Some(18.0)],
+        ]
+        .unwrap();
+
+        let mut out = Vec::new();
+        assert_eq!(write_line_protocol(&df, &mut out).unwrap(), 2);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "km003c,session=bench\\ a vbus=5,ibus=1.5,power=7.5 1700000000000001000\n\
+             km003c,session=bench\\ a vbus=9,ibus=2,power=18 1250000000\n"
+        );
+
+        let err = write_line_protocol(&df.drop("adc_power_w").unwrap(), &mut Vec::new()).unwrap_err();
+        assert!(err.to_string().contains("adc_power_w"));
+    }
+}
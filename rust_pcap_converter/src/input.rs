@@ -0,0 +1,140 @@
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+
+use log::info;
+use tempfile::NamedTempFile;
+
+use crate::Result;
+
+/// Compression wrappers accepted on `--input`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InputCompression {
+    Gzip,
+    Zstd,
+}
+
+impl InputCompression {
+    fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "gz" => Some(InputCompression::Gzip),
+            "zst" | "zstd" => Some(InputCompression::Zstd),
+            _ => None,
+        }
+    }
+}
+
+/// `--input` value that reads the capture from standard input
+pub(crate) const STDIN_INPUT: &str = "-";
+
+/// A capture file ready to hand to tshark.
+///
+/// Compressed inputs are decompressed into a temporary file that is removed when this
+/// value is dropped; `name` is the file name without the compression extension so
+/// address and session auto-detection see `capture.16.pcapng`. Standard input (`-`)
+/// is streamed to tshark and named `stdin`.
+pub(crate) struct CaptureInput {
+    path: PathBuf,
+    name: String,
+    is_stdin: bool,
+    _temp: Option<NamedTempFile>,
+}
+
+impl CaptureInput {
+    pub(crate) fn open(input: &Path) -> Result<Self> {
+        if input == Path::new(STDIN_INPUT) {
+            return Self::stdin();
+        }
+        let file_name = input.file_name().and_then(|s| s.to_str()).unwrap_or("");
+        let Some(compression) = InputCompression::from_path(input) else {
+            return Ok(Self { path: input.to_path_buf(), name: file_name.to_string(), is_stdin: false, _temp: None });
+        };
+        let name = Path::new(file_name).file_stem().and_then(|s| s.to_str()).unwrap_or("").to_string();
+
+        let reader = BufReader::new(File::open(input)?);
+        let mut temp = tempfile::Builder::new().prefix("pcap_to_parquet_").suffix(".pcapng").tempfile()?;
+        match compression {
+            InputCompression::Gzip => io::copy(&mut flate2::read::MultiGzDecoder::new(reader), &mut temp)?,
+            InputCompression::Zstd => io::copy(&mut zstd::Decoder::with_buffer(reader)?, &mut temp)?,
+        };
+        info!("Decompressed {:?} to {:?}", input, temp.path());
+
+        Ok(Self { path: temp.path().to_path_buf(), name, is_stdin: false, _temp: Some(temp) })
+    }
+
+    /// tshark reads the pipe directly; rtshark insists on a path that exists, so use `/dev/stdin`
+    #[cfg(unix)]
+    fn stdin() -> Result<Self> {
+        Ok(Self { path: PathBuf::from("/dev/stdin"), name: "stdin".to_string(), is_stdin: true, _temp: None })
+    }
+
+    /// No stdin device path here, so spool the stream to a temporary file first
+    #[cfg(not(unix))]
+    fn stdin() -> Result<Self> {
+        let mut temp = tempfile::Builder::new().prefix("pcap_to_parquet_").suffix(".pcapng").tempfile()?;
+        io::copy(&mut io::stdin().lock(), &mut temp)?;
+        Ok(Self { path: temp.path().to_path_buf(), name: "stdin".to_string(), is_stdin: true, _temp: Some(temp) })
+    }
+
+    /// Path tshark should read
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Capture file name with any compression extension removed
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the capture comes from standard input and can only be read once
+    pub(crate) fn is_stdin(&self) -> bool {
+        self.is_stdin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn decompresses_gzip_and_zstd_inputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = b"\x0a\x0d\x0d\x0a fake pcapng";
+
+        let gz_path = dir.path().join("capture.16.pcapng.gz");
+        let mut gz = flate2::write::GzEncoder::new(File::create(&gz_path).unwrap(), flate2::Compression::default());
+        gz.write_all(data).unwrap();
+        gz.finish().unwrap();
+
+        let zst_path = dir.path().join("capture.16.pcapng.zst");
+        std::fs::write(&zst_path, zstd::encode_all(&data[..], 3).unwrap()).unwrap();
+
+        for path in [gz_path, zst_path] {
+            let input = CaptureInput::open(&path).unwrap();
+            assert_eq!(input.name(), "capture.16.pcapng");
+            let mut contents = Vec::new();
+            File::open(input.path()).unwrap().read_to_end(&mut contents).unwrap();
+            assert_eq!(contents, data);
+
+            let temp_path = input.path().to_path_buf();
+            drop(input);
+            assert!(!temp_path.exists());
+        }
+    }
+
+    #[test]
+    fn plain_inputs_are_used_in_place() {
+        let input = CaptureInput::open(Path::new("captures/capture.16.pcapng")).unwrap();
+        assert_eq!(input.path(), Path::new("captures/capture.16.pcapng"));
+        assert_eq!(input.name(), "capture.16.pcapng");
+        assert!(!input.is_stdin());
+    }
+
+    #[test]
+    fn dash_reads_standard_input() {
+        let input = CaptureInput::open(Path::new(STDIN_INPUT)).unwrap();
+        assert!(input.is_stdin());
+        assert_eq!(input.name(), "stdin");
+    }
+}
@@ -0,0 +1,810 @@
+//! Decoding of KM003C application payloads carried on the bulk endpoints.
+//!
+//! Layouts follow `docs/protocol_reference.md` and `docs/features/pd_analysis.md`.
+//! Payloads come from captures that may be truncated or corrupt, so every reader
+//! bounds-checks with `get` and returns `None` instead of indexing past the end.
+
+/// Control commands that drive the session lifecycle
+pub const CMD_CONNECT: u8 = 0x02;
+pub const CMD_DISCONNECT: u8 = 0x03;
+pub const CMD_GET_DATA: u8 = 0x0C;
+pub const CMD_START_GRAPH: u8 = 0x0E;
+pub const CMD_STOP_GRAPH: u8 = 0x0F;
+
+/// PutData response carrying one or more logical packets
+pub const CMD_PUT_DATA: u8 = 0x41;
+
+/// Logical packet attribute for a single 44-byte ADC measurement
+pub const ATT_ADC: u16 = 0x0001;
+
+/// Logical packet attribute for queued ADC samples streamed while graphing
+pub const ATT_ADC_QUEUE: u16 = 0x0002;
+
+/// Full ADC body size, and the offset of its i16 temperature (LSB = 1/128 °C)
+const ADC_LEN: usize = 44;
+const ADC_TEMP_OFFSET: usize = 24;
+
+/// Logical packet attribute for the 180-byte device settings block
+pub const ATT_SETTINGS: u16 = 0x0008;
+
+/// Logical packet attribute for PD status / event streams
+pub const ATT_PD_PACKET: u16 = 0x0010;
+
+/// Settings is two CRC-32 protected blocks: `(data range, checksum offset)`
+const SETTINGS_LEN: usize = 180;
+/// Offset of the u16 sample interval (µs) within a Settings block
+const SETTINGS_SAMPLE_INTERVAL_OFFSET: usize = 0x08;
+const SETTINGS_CRC_BLOCKS: [(std::ops::Range<usize>, usize); 2] = [(0x00..0x5C, 0x5C), (0x60..0xB0, 0xB0)];
+
+/// Names of the attribute bits understood so far (bit index, name)
+const ATTRIBUTE_BIT_NAMES: [(u8, &str); 5] = [(0, "adc"), (1, "adc_queue"), (3, "settings"), (4, "pd_packet"), (9, "unknown512")];
+
+/// Width of the control-header attribute field
+pub const ATTRIBUTE_BITS: u8 = 15;
+
+/// Every main-header packet type in `docs/protocol_reference.md`'s command table
+const KNOWN_PACKET_TYPES: [u8; 12] = [0x02, 0x03, 0x05, 0x06, 0x0C, 0x0E, 0x0F, 0x10, 0x11, 0x41, 0x44, 0x4C];
+
+/// Names accepted by `--only-packet-types`
+const PACKET_TYPE_NAMES: [(&str, PacketTypeFilter); 10] = [
+    ("connect", PacketTypeFilter::Header(CMD_CONNECT)),
+    ("disconnect", PacketTypeFilter::Header(CMD_DISCONNECT)),
+    ("get_data", PacketTypeFilter::Header(CMD_GET_DATA)),
+    ("start_graph", PacketTypeFilter::Header(CMD_START_GRAPH)),
+    ("stop_graph", PacketTypeFilter::Header(CMD_STOP_GRAPH)),
+    ("put_data", PacketTypeFilter::Header(CMD_PUT_DATA)),
+    ("adc", PacketTypeFilter::PutData(ATT_ADC)),
+    ("adc_queue", PacketTypeFilter::PutData(ATT_ADC_QUEUE)),
+    ("settings", PacketTypeFilter::PutData(ATT_SETTINGS)),
+    ("pd", PacketTypeFilter::PutData(ATT_PD_PACKET)),
+];
+
+/// Size of the PD measurement block that prefixes every PdPacket payload
+const PD_PREAMBLE_LEN: usize = 12;
+const PD_EVENT_CONNECTION: u8 = 0x45;
+const PD_EVENT_CONNECT: u8 = 0x21;
+const PD_EVENT_DISCONNECT: u8 = 0x22;
+
+/// USB PD message header: data message type Request, carrying one RDO
+const PD_MSG_TYPE_REQUEST: u16 = 0x02;
+/// USB PD message header: data message type BIST (test modes, not power objects)
+const PD_MSG_TYPE_BIST: u8 = 0x03;
+/// USB PD message header bit 15: the message type is from the extended message table
+const PD_HEADER_EXTENDED: u16 = 1 << 15;
+
+/// Sink-side CC voltage thresholds (mV) for Rp detection, per the Type-C spec vRd ranges
+const CC_RD_CONNECT_MV: u16 = 200;
+const CC_RD_USB_MAX_MV: u16 = 660;
+const CC_RD_1A5_MAX_MV: u16 = 1230;
+
+/// 4-byte main header shared by control and data packets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub packet_type: u8,
+    pub transaction_id: u8,
+    /// Attribute for control packets, `obj_count_words` for PutData
+    pub attribute: u16,
+    /// Bit 7 of the first byte: an extended header follows the main header
+    pub extend: bool,
+}
+
+pub fn parse_header(payload: &[u8]) -> Option<Header> {
+    let word = u32::from_le_bytes(payload.get(..4)?.try_into().ok()?);
+    let packet_type = (word & 0x7F) as u8;
+    let transaction_id = (word >> 8) as u8;
+    let attribute = if packet_type == CMD_PUT_DATA {
+        ((word >> 22) & 0x3FF) as u16
+    } else {
+        ((word >> 17) & 0x7FFF) as u16
+    };
+    Some(Header { packet_type, transaction_id, attribute, extend: word & 0x80 != 0 })
+}
+
+/// Bytes after the 4-byte main header that the header itself declares.
+///
+/// PutData counts in 32-bit words (`obj_count_words`, 10 bits), so bursts past the
+/// 1023-byte reach of an extended header's `size` still read correctly: non-empty
+/// packets declare `(count + 2) * 4` bytes. Other packets with the extend bit declare
+/// their extended header plus its byte `size`. Plain control packets declare nothing.
+pub fn declared_payload_len(payload: &[u8]) -> Option<usize> {
+    let header = parse_header(payload)?;
+    if header.packet_type == CMD_PUT_DATA {
+        let words = header.attribute as usize;
+        return Some(if words == 0 { 0 } else { (words + 2) * 4 });
+    }
+    if !header.extend {
+        return None;
+    }
+    let ext = u32::from_le_bytes(payload.get(4..8)?.try_into().ok()?);
+    Some(4 + ((ext >> 22) & 0x3FF) as usize)
+}
+
+/// Bit-to-name table for control-packet attribute masks (`--attribute-map` extends it)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttributeNames {
+    names: std::collections::BTreeMap<u8, String>,
+}
+
+impl Default for AttributeNames {
+    /// The built-in names from [`ATTRIBUTE_BIT_NAMES`]
+    fn default() -> Self {
+        Self { names: ATTRIBUTE_BIT_NAMES.iter().map(|(bit, name)| (*bit, name.to_string())).collect() }
+    }
+}
+
+impl AttributeNames {
+    /// Override or add names for individual bits
+    pub fn merge(mut self, overrides: impl IntoIterator<Item = (u8, String)>) -> Self {
+        self.names.extend(overrides);
+        self
+    }
+
+    /// `|`-joined names of the set bits, lowest first; unnamed bits read `bit<N>`
+    pub fn decode_attribute(&self, attribute: u16) -> Option<String> {
+        let names: Vec<String> = (0..ATTRIBUTE_BITS)
+            .filter(|bit| attribute & (1 << bit) != 0)
+            .map(|bit| self.names.get(&bit).cloned().unwrap_or_else(|| format!("bit{}", bit)))
+            .collect();
+        Some(names.join("|")).filter(|n| !n.is_empty())
+    }
+}
+
+/// Protocol lifecycle stage of a capture, driven by main-header packet types.
+///
+/// Connect (and StartGraph) open a handshake, the first GetData/PutData exchange after
+/// it starts streaming, and StopGraph/Disconnect begin teardown until the next Connect.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SessionPhase {
+    #[default]
+    Handshake,
+    Streaming,
+    Teardown,
+}
+
+impl SessionPhase {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SessionPhase::Handshake => "handshake",
+            SessionPhase::Streaming => "streaming",
+            SessionPhase::Teardown => "teardown",
+        }
+    }
+
+    /// Phase after seeing a packet of `packet_type`; the packet itself belongs to it
+    pub fn advance(self, packet_type: u8) -> Self {
+        match packet_type {
+            CMD_CONNECT | CMD_START_GRAPH => SessionPhase::Handshake,
+            CMD_STOP_GRAPH | CMD_DISCONNECT => SessionPhase::Teardown,
+            CMD_GET_DATA | CMD_PUT_DATA if self == SessionPhase::Handshake => SessionPhase::Streaming,
+            _ => self,
+        }
+    }
+}
+
+/// What an `--only-packet-types` name selects
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketTypeFilter {
+    /// Packets whose main-header type is this command
+    Header(u8),
+    /// PutData responses carrying a logical packet with this attribute
+    PutData(u16),
+}
+
+impl PacketTypeFilter {
+    /// Look up a name such as `adc` or `get_data`
+    pub fn from_name(name: &str) -> Option<Self> {
+        PACKET_TYPE_NAMES.iter().find(|(n, _)| *n == name).map(|(_, filter)| *filter)
+    }
+
+    /// Every accepted name, in table order
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        PACKET_TYPE_NAMES.iter().map(|(name, _)| *name)
+    }
+}
+
+/// Split a PutData payload into `(attribute, body)` logical packets
+pub fn logical_packets(payload: &[u8]) -> Vec<(u16, &[u8])> {
+    let mut packets = Vec::new();
+    if parse_header(payload).map(|h| h.packet_type) != Some(CMD_PUT_DATA) {
+        return packets;
+    }
+    let mut offset = 4;
+    while let Some(ext) = payload.get(offset..offset + 4) {
+        let word = u32::from_le_bytes(ext.try_into().unwrap_or_default());
+        let attribute = (word & 0x7FFF) as u16;
+        let next = word & 0x8000 != 0;
+        let size = ((word >> 22) & 0x3FF) as usize;
+        let start = offset + 4;
+        let end = (start + size).min(payload.len());
+        packets.push((attribute, payload.get(start..end).unwrap_or_default()));
+        if !next {
+            break;
+        }
+        offset = end;
+    }
+    packets
+}
+
+/// Whether a bulk payload follows the protocol grammar (`validate` subcommand).
+///
+/// The header must carry a known packet type, and a non-empty PutData must be exactly
+/// tiled by its logical packets: no truncated body and no bytes after the last one.
+pub fn is_well_formed(payload: &[u8]) -> bool {
+    let Some(header) = parse_header(payload) else {
+        return false;
+    };
+    if !KNOWN_PACKET_TYPES.contains(&header.packet_type) {
+        return false;
+    }
+    if header.packet_type != CMD_PUT_DATA || payload.len() == 4 {
+        return true;
+    }
+    let mut offset = 4;
+    while let Some(ext) = payload.get(offset..offset + 4) {
+        let word = u32::from_le_bytes(ext.try_into().unwrap_or_default());
+        offset += 4 + ((word >> 22) & 0x3FF) as usize;
+        if word & 0x8000 == 0 {
+            return offset == payload.len();
+        }
+    }
+    false
+}
+
+/// Total logical-packet body length a GetData for `mask` should be answered with.
+///
+/// Only ADC and Settings bodies have a fixed size; a mask with any other bit (queued
+/// samples, PD streams, unknown attributes) has no expected length.
+pub fn expected_body_len(mask: u16) -> Option<usize> {
+    let fixed = [(ATT_ADC, ADC_LEN), (ATT_SETTINGS, SETTINGS_LEN)];
+    let known = fixed.iter().fold(0, |known, (attribute, _)| known | attribute);
+    if mask == 0 || mask & !known != 0 {
+        return None;
+    }
+    Some(fixed.iter().filter(|(attribute, _)| mask & attribute != 0).map(|(_, len)| len).sum())
+}
+
+/// `name:length` of every logical packet in a PutData payload, in order, e.g.
+/// `adc:44,pd_packet:28`. Unnamed attributes read `bit<N>` as in `km_attribute_name`,
+/// so groups no decoder understands still show up.
+pub fn describe_logical_packets(payload: &[u8]) -> Option<String> {
+    let names = AttributeNames::default();
+    let groups: Vec<String> = logical_packets(payload)
+        .into_iter()
+        .map(|(attribute, body)| {
+            let name = names.decode_attribute(attribute).unwrap_or_else(|| format!("{:#06x}", attribute));
+            format!("{}:{}", name, body.len())
+        })
+        .collect();
+    Some(groups.join(",")).filter(|g| !g.is_empty())
+}
+
+/// Summed body length of every logical packet in a PutData payload
+pub fn logical_body_len(payload: &[u8]) -> Option<usize> {
+    logical_packets(payload).into_iter().map(|(_, body)| body.len()).reduce(|a, b| a + b)
+}
+
+/// Attributes of every logical packet in a PutData payload, OR-ed together
+pub fn logical_attributes(payload: &[u8]) -> Option<u16> {
+    logical_packets(payload).into_iter().map(|(attribute, _)| attribute).reduce(|a, b| a | b)
+}
+
+/// Validate the CRC-32 checksums of any Settings block in a PutData payload.
+///
+/// Returns `None` when the payload carries nothing checksummed (every packet type other
+/// than Settings), `Some(false)` if any block's stored CRC does not match.
+pub fn verify_checksum(payload: &[u8]) -> Option<bool> {
+    logical_packets(payload)
+        .into_iter()
+        .filter(|(attribute, body)| *attribute == ATT_SETTINGS && body.len() == SETTINGS_LEN)
+        .map(|(_, body)| {
+            SETTINGS_CRC_BLOCKS.iter().all(|(range, crc_at)| {
+                let stored = body.get(*crc_at..crc_at + 4).and_then(|b| b.try_into().ok()).map(u32::from_le_bytes);
+                body.get(range.clone()).map(crc32fast::hash).is_some_and(|crc| Some(crc) == stored)
+            })
+        })
+        .reduce(|a, b| a && b)
+}
+
+/// Configured sample rate in Hz, from the sample interval of a Settings block in a
+/// PutData payload; `None` without a Settings block or with a zero interval
+pub fn decode_settings_sample_rate(payload: &[u8]) -> Option<f64> {
+    let (_, body) = logical_packets(payload)
+        .into_iter()
+        .find(|(attribute, body)| *attribute == ATT_SETTINGS && body.len() == SETTINGS_LEN)?;
+    let offset = SETTINGS_SAMPLE_INTERVAL_OFFSET;
+    let interval_us = u16::from_le_bytes(body.get(offset..offset + 2)?.try_into().ok()?);
+    (interval_us > 0).then(|| 1e6 / interval_us as f64)
+}
+
+/// Main VBUS measurement from an ADC logical packet, in SI units
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdcSample {
+    pub vbus_v: f64,
+    pub ibus_a: f64,
+}
+
+impl AdcSample {
+    pub fn power_w(&self) -> f64 {
+        self.vbus_v * self.ibus_a
+    }
+}
+
+/// Decode the first complete ADC logical packet of a PutData payload.
+///
+/// Shorter ADC-attribute packets (temperature-only frames) are not samples and are
+/// left to [`decode_temperature`].
+pub fn decode_adc(payload: &[u8]) -> Option<AdcSample> {
+    let (_, body) = logical_packets(payload)
+        .into_iter()
+        .find(|(attribute, body)| *attribute == ATT_ADC && body.len() == ADC_LEN)?;
+    let vbus_uv = i32::from_le_bytes(body.get(0..4)?.try_into().ok()?);
+    let ibus_ua = i32::from_le_bytes(body.get(4..8)?.try_into().ok()?);
+    Some(AdcSample {
+        vbus_v: vbus_uv as f64 / 1e6,
+        ibus_a: ibus_ua as f64 / 1e6,
+    })
+}
+
+/// Internal temperature in °C from the first ADC-attribute packet long enough to carry it
+pub fn decode_temperature(payload: &[u8]) -> Option<f64> {
+    let (_, body) = logical_packets(payload)
+        .into_iter()
+        .find(|(attribute, body)| *attribute == ATT_ADC && body.len() >= ADC_TEMP_OFFSET + 2)?;
+    let raw = i16::from_le_bytes(body.get(ADC_TEMP_OFFSET..ADC_TEMP_OFFSET + 2)?.try_into().ok()?);
+    Some(raw as f64 / 128.0)
+}
+
+/// Type-C CC line state decoded from a PD connection event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CcStatus {
+    pub attached: bool,
+    /// `"CC1"` or `"CC2"`; `None` when neither line sees Rp
+    pub orientation: Option<&'static str>,
+    /// Source Rp advertisement: `"default"`, `"1.5A"` or `"3.0A"`
+    pub advertised_current: Option<&'static str>,
+}
+
+/// Decode CC status from the last connect/disconnect event in a PdPacket payload
+pub fn decode_cc_event(payload: &[u8]) -> Option<CcStatus> {
+    let (_, body) = logical_packets(payload)
+        .into_iter()
+        .find(|(attribute, _)| *attribute == ATT_PD_PACKET)?;
+    let preamble = body.get(..PD_PREAMBLE_LEN)?;
+    let attached = last_connection_event(body.get(PD_PREAMBLE_LEN..)?)?;
+
+    let cc1 = u16::from_le_bytes(preamble.get(8..10)?.try_into().ok()?);
+    let cc2 = u16::from_le_bytes(preamble.get(10..12)?.try_into().ok()?);
+    let (orientation, active_mv) = match (cc1 >= CC_RD_CONNECT_MV, cc2 >= CC_RD_CONNECT_MV) {
+        (true, false) => (Some("CC1"), Some(cc1)),
+        (false, true) => (Some("CC2"), Some(cc2)),
+        _ => (None, None),
+    };
+    let advertised_current = active_mv.map(|mv| match mv {
+        mv if mv < CC_RD_USB_MAX_MV => "default",
+        mv if mv < CC_RD_1A5_MAX_MV => "1.5A",
+        _ => "3.0A",
+    });
+
+    Some(CcStatus { attached, orientation, advertised_current })
+}
+
+/// One record of the PD event stream that follows the PdPacket preamble
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PdEvent<'a> {
+    /// Connect/disconnect record with its event code
+    Connection(Option<u8>),
+    /// Wrapped USB PD message: 2-byte header plus data objects, as sent on the wire
+    Wire(&'a [u8]),
+}
+
+/// Walk the PD event stream, stopping at the first unrecognised record
+fn pd_events(events: &[u8]) -> Vec<PdEvent<'_>> {
+    let mut records = Vec::new();
+    let mut i = 0;
+    while let Some(&tag) = events.get(i) {
+        match tag {
+            PD_EVENT_CONNECTION => {
+                records.push(PdEvent::Connection(events.get(i + 5).copied()));
+                i += 6;
+            }
+            size_flag @ 0x80..=0x9F => {
+                // Truncated records keep whatever wire bytes the capture still holds
+                let wire_len = ((size_flag & 0x3F) as usize).saturating_sub(5);
+                let start = (i + 6).min(events.len());
+                let end = (start + wire_len).min(events.len());
+                records.push(PdEvent::Wire(events.get(start..end).unwrap_or_default()));
+                i += 6 + wire_len;
+            }
+            _ => break,
+        }
+    }
+    records
+}
+
+/// Whether the last connection event in the stream was a connect
+fn last_connection_event(events: &[u8]) -> Option<bool> {
+    pd_events(events).into_iter().rev().find_map(|event| match event {
+        PdEvent::Connection(Some(PD_EVENT_CONNECT)) => Some(true),
+        PdEvent::Connection(Some(PD_EVENT_DISCONNECT)) => Some(false),
+        _ => None,
+    })
+}
+
+/// Fields of a PD Request data object (RDO), read with the fixed/variable supply layout
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RequestDataObject {
+    /// 1-based index of the selected Source_Capabilities PDO
+    pub object_position: u8,
+    pub give_back: bool,
+    pub capability_mismatch: bool,
+    pub usb_comm_capable: bool,
+    /// Operating current in amperes (bits 19..10, 10 mA units)
+    pub op_current_a: f64,
+    /// Maximum (or, with GiveBack, minimum) operating current in amperes (bits 9..0)
+    pub max_current_a: f64,
+}
+
+impl RequestDataObject {
+    pub fn from_raw(rdo: u32) -> Self {
+        Self {
+            object_position: (rdo >> 28) as u8 & 0x0F,
+            give_back: rdo & (1 << 27) != 0,
+            capability_mismatch: rdo & (1 << 26) != 0,
+            usb_comm_capable: rdo & (1 << 25) != 0,
+            op_current_a: ((rdo >> 10) & 0x3FF) as f64 * 0.01,
+            max_current_a: (rdo & 0x3FF) as f64 * 0.01,
+        }
+    }
+}
+
+/// Decode the RDO of the last Request message in a PdPacket payload's event stream
+pub fn decode_pd_request(payload: &[u8]) -> Option<RequestDataObject> {
+    let (_, body) = logical_packets(payload)
+        .into_iter()
+        .find(|(attribute, _)| *attribute == ATT_PD_PACKET)?;
+    let events = body.get(PD_PREAMBLE_LEN..)?;
+    pd_events(events).into_iter().rev().find_map(|event| {
+        let PdEvent::Wire(wire) = event else {
+            return None;
+        };
+        let header = u16::from_le_bytes(wire.get(..2)?.try_into().ok()?);
+        let object_count = (header >> 12) & 0x7;
+        // Extended type 0x02 is Status, not Request
+        if header & PD_HEADER_EXTENDED != 0 || header & 0x1F != PD_MSG_TYPE_REQUEST || object_count == 0 {
+            return None;
+        }
+        let rdo = u32::from_le_bytes(wire.get(2..6)?.try_into().ok()?);
+        Some(RequestDataObject::from_raw(rdo))
+    })
+}
+
+/// A USB PD message carried in the PdPacket event stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PdMessage {
+    /// 16-bit PD message header as sent on the wire
+    pub header: u16,
+    /// 16-bit extended message header (chunking and data size), for extended messages only
+    pub extended_header: Option<u16>,
+    /// Data objects that follow the header; empty for control messages, extended messages
+    /// (whose data block is not made of 32-bit objects) and truncated records
+    pub data_objects: Vec<u32>,
+}
+
+impl PdMessage {
+    /// Message type field (header bits 4..0); an extended message type when [`Self::is_extended`]
+    pub fn message_type(&self) -> u8 {
+        (self.header & 0x1F) as u8
+    }
+
+    /// Extended message (header bit 15)
+    pub fn is_extended(&self) -> bool {
+        self.header & PD_HEADER_EXTENDED != 0
+    }
+
+    /// BIST data message: its objects select test modes rather than describe power
+    pub fn is_bist(&self) -> bool {
+        !self.is_extended() && self.message_type() == PD_MSG_TYPE_BIST && (self.header >> 12) & 0x7 != 0
+    }
+}
+
+/// Every wire PD message of a PdPacket payload, in stream order
+pub fn decode_pd_messages(payload: &[u8]) -> Vec<PdMessage> {
+    let Some((_, body)) = logical_packets(payload).into_iter().find(|(attribute, _)| *attribute == ATT_PD_PACKET) else {
+        return Vec::new();
+    };
+    pd_events(body.get(PD_PREAMBLE_LEN..).unwrap_or_default())
+        .into_iter()
+        .filter_map(|event| {
+            let PdEvent::Wire(wire) = event else {
+                return None;
+            };
+            let header = u16::from_le_bytes(wire.get(..2)?.try_into().ok()?);
+            if header & PD_HEADER_EXTENDED != 0 {
+                let extended_header = wire.get(2..4).map(|h| u16::from_le_bytes([h[0], h[1]]));
+                return Some(PdMessage { header, extended_header, data_objects: Vec::new() });
+            }
+            let data_objects = wire
+                .get(2..)
+                .unwrap_or_default()
+                .chunks_exact(4)
+                .take(((header >> 12) & 0x7) as usize)
+                .map(|object| u32::from_le_bytes(object.try_into().unwrap_or_default()))
+                .collect();
+            Some(PdMessage { header, extended_header: None, data_objects })
+        })
+        .collect()
+}
+
+/// Protocol meaning of one bulk payload, for library users who want typed values
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decoded {
+    /// A complete ADC measurement
+    Adc(AdcSample),
+    /// A PD connect/disconnect event
+    Connection(CcStatus),
+    /// The wire PD messages of a PdPacket; one packet usually batches several
+    Pd(Vec<PdMessage>),
+    /// Anything else: commands, settings, empty responses, unparseable payloads
+    Unknown,
+}
+
+/// Decode a payload into the first of [`Decoded`]'s variants it matches, in declaration order
+pub fn decode_payload(payload: &[u8]) -> Decoded {
+    if let Some(sample) = decode_adc(payload) {
+        return Decoded::Adc(sample);
+    }
+    if let Some(status) = decode_cc_event(payload) {
+        return Decoded::Connection(status);
+    }
+    let messages = decode_pd_messages(payload);
+    if !messages.is_empty() {
+        return Decoded::Pd(messages);
+    }
+    Decoded::Unknown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pd_put_data(cc1_mv: u16, cc2_mv: u16, events: &[u8]) -> Vec<u8> {
+        let size = (PD_PREAMBLE_LEN + events.len()) as u32;
+        let mut payload = vec![CMD_PUT_DATA, 0x07, 0x00, 0x00];
+        payload.extend_from_slice(&(ATT_PD_PACKET as u32 | size << 22).to_le_bytes());
+        payload.extend_from_slice(&[0xe5, 0xe8, 0x5b, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        payload.extend_from_slice(&cc1_mv.to_le_bytes());
+        payload.extend_from_slice(&cc2_mv.to_le_bytes());
+        payload.extend_from_slice(events);
+        payload
+    }
+
+    #[test]
+    fn names_attribute_bits_with_overrides() {
+        let names = AttributeNames::default();
+        assert_eq!(names.decode_attribute(0x0011).as_deref(), Some("adc|pd_packet"));
+        assert_eq!(names.decode_attribute(0x0040).as_deref(), Some("bit6"));
+        assert_eq!(names.decode_attribute(0), None);
+
+        let custom = names.merge([(6, "graph_samples".to_string()), (0, "adc_main".to_string())]);
+        assert_eq!(custom.decode_attribute(0x0043).as_deref(), Some("adc_main|adc_queue|graph_samples"));
+    }
+
+    #[test]
+    fn tracks_session_phase_transitions() {
+        // Connect, Accept, StreamingAuth, GetData, PutData, Accept, Disconnect, Accept
+        let mut phase = SessionPhase::default();
+        let phases: Vec<&str> = [0x02, 0x05, 0x4C, 0x0C, 0x41, 0x05, 0x03, 0x05, 0x0C, 0x02]
+            .into_iter()
+            .map(|packet_type| {
+                phase = phase.advance(packet_type);
+                phase.as_str()
+            })
+            .collect();
+        assert_eq!(
+            phases,
+            [
+                "handshake", "handshake", "handshake", "streaming", "streaming", "streaming",
+                "teardown", "teardown", "teardown", "handshake",
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_control_header_attribute() {
+        let header = parse_header(&[0x0C, 0x05, 0x22, 0x00]).unwrap();
+        assert_eq!(header, Header { packet_type: 0x0C, transaction_id: 5, attribute: 0x11, extend: false });
+    }
+
+    #[test]
+    fn reads_declared_length_from_either_header_form() {
+        // 52-byte ADC PutData: obj_count_words = 10 words
+        let adc = hex::decode("410a82020100000b5c0f0000faffffffa00f0000f2ffffff04100000500000007e0d7b7ed40471014201837e0080780025002100").unwrap();
+        assert_eq!(declared_payload_len(&adc), Some(adc.len() - 4));
+
+        // Burst larger than a 10-bit byte size: 400 words
+        let burst = (CMD_PUT_DATA as u32 | 400 << 22).to_le_bytes();
+        assert_eq!(declared_payload_len(&burst), Some(1608));
+
+        // Control packet with the extend bit and a 6-byte extended-header payload
+        let mut extended = vec![0x0C | 0x80, 0x01, 0x02, 0x00];
+        extended.extend_from_slice(&(6u32 << 22).to_le_bytes());
+        assert!(parse_header(&extended).unwrap().extend);
+        assert_eq!(declared_payload_len(&extended), Some(10));
+
+        assert_eq!(declared_payload_len(&[0x0C, 0x05, 0x22, 0x00]), None);
+        assert_eq!(declared_payload_len(&[0x8C, 0x05, 0x22, 0x00]), None);
+    }
+
+    fn settings_put_data(corrupt: bool) -> Vec<u8> {
+        let mut body = vec![0u8; SETTINGS_LEN];
+        body[0x08..0x0A].copy_from_slice(&10_000u16.to_le_bytes());
+        body[0x70..0x77].copy_from_slice(b"POWER-Z");
+        for (range, crc_at) in SETTINGS_CRC_BLOCKS {
+            let crc = crc32fast::hash(&body[range]);
+            body[crc_at..crc_at + 4].copy_from_slice(&crc.to_le_bytes());
+        }
+        if corrupt {
+            body[0x71] ^= 0xFF;
+        }
+        let mut payload = vec![CMD_PUT_DATA, 0x03, 0x00, 0x00];
+        payload.extend_from_slice(&(ATT_SETTINGS as u32 | (SETTINGS_LEN as u32) << 22).to_le_bytes());
+        payload.extend_from_slice(&body);
+        payload
+    }
+
+    #[test]
+    fn verifies_settings_checksums() {
+        assert_eq!(verify_checksum(&settings_put_data(false)), Some(true));
+        assert_eq!(verify_checksum(&settings_put_data(true)), Some(false));
+        assert_eq!(verify_checksum(&pd_put_data(420, 3, &[])), None);
+        assert_eq!(verify_checksum(&[0x0C, 0x03, 0x10, 0x00]), None);
+    }
+
+    #[test]
+    fn decodes_sample_rate_from_settings_interval() {
+        // 10 ms interval
+        assert_eq!(decode_settings_sample_rate(&settings_put_data(false)), Some(100.0));
+        assert_eq!(decode_settings_sample_rate(&pd_put_data(420, 3, &[])), None);
+    }
+
+    #[test]
+    fn decodes_adc_measurement() {
+        let mut payload = vec![CMD_PUT_DATA, 0x02, 0x00, 0x00];
+        payload.extend_from_slice(&(ATT_ADC as u32 | 44 << 22).to_le_bytes());
+        payload.extend_from_slice(&5_012_000i32.to_le_bytes());
+        payload.extend_from_slice(&(-1_500_000i32).to_le_bytes());
+        payload.resize(8 + 44, 0);
+        let adc = decode_adc(&payload).unwrap();
+        assert_eq!(adc, AdcSample { vbus_v: 5.012, ibus_a: -1.5 });
+        assert!((adc.power_w() + 7.518).abs() < 1e-9);
+        assert_eq!(decode_adc(&pd_put_data(0, 0, &[])), None);
+    }
+
+    #[test]
+    fn temperature_only_packet_is_not_an_adc_sample() {
+        let mut body = vec![0xAA; ADC_TEMP_OFFSET];
+        body.extend_from_slice(&(3200i16).to_le_bytes());
+        let mut payload = vec![CMD_PUT_DATA, 0x02, 0x00, 0x00];
+        payload.extend_from_slice(&(ATT_ADC as u32 | (body.len() as u32) << 22).to_le_bytes());
+        payload.extend_from_slice(&body);
+        assert_eq!(decode_adc(&payload), None);
+        assert_eq!(decode_temperature(&payload), Some(25.0));
+    }
+
+    #[test]
+    fn decodes_connect_event() {
+        let payload = pd_put_data(1654, 3, &[0x45, 0xe2, 0xe8, 0x5b, 0x00, 0x21]);
+        let status = decode_cc_event(&payload).unwrap();
+        assert!(status.attached);
+        assert_eq!(status.orientation, Some("CC1"));
+        assert_eq!(status.advertised_current, Some("3.0A"));
+    }
+
+    #[test]
+    fn decodes_disconnect_without_rp() {
+        let payload = pd_put_data(4, 3, &[0x45, 0x10, 0x00, 0x00, 0x00, 0x22]);
+        let status = decode_cc_event(&payload).unwrap();
+        assert!(!status.attached);
+        assert_eq!(status.orientation, None);
+        assert_eq!(status.advertised_current, None);
+    }
+
+    #[test]
+    fn decodes_request_data_object() {
+        // GoodCRC (control, no objects) then Request: position 2, 3.0 A op / 3.0 A max, USB comm capable
+        let rdo: u32 = 2 << 28 | 1 << 25 | 300 << 10 | 300;
+        let mut events = vec![0x87, 0, 0, 0, 0, 0, 0x41, 0x01];
+        events.extend_from_slice(&[0x8B, 0, 0, 0, 0, 0, 0x82, 0x10]);
+        events.extend_from_slice(&rdo.to_le_bytes());
+        let request = decode_pd_request(&pd_put_data(1654, 3, &events)).unwrap();
+        assert_eq!(request.object_position, 2);
+        assert!(request.usb_comm_capable && !request.give_back && !request.capability_mismatch);
+        assert!((request.op_current_a - 3.0).abs() < 1e-9 && (request.max_current_a - 3.0).abs() < 1e-9);
+
+        assert_eq!(decode_pd_request(&pd_put_data(1654, 3, &[0x87, 0, 0, 0, 0, 0, 0x41, 0x01])), None);
+    }
+
+    #[test]
+    fn decodes_payloads_into_typed_variants() {
+        let rdo: u32 = 2 << 28 | 300 << 10 | 300;
+        let mut events = vec![0x87, 0, 0, 0, 0, 0, 0x41, 0x01];
+        events.extend_from_slice(&[0x8B, 0, 0, 0, 0, 0, 0x82, 0x10]);
+        events.extend_from_slice(&rdo.to_le_bytes());
+        let Decoded::Pd(messages) = decode_payload(&pd_put_data(1654, 3, &events)) else {
+            panic!("expected PD messages");
+        };
+        assert_eq!(messages.len(), 2);
+        assert_eq!((messages[0].message_type(), messages[0].data_objects.len()), (0x01, 0));
+        assert_eq!((messages[1].message_type(), messages[1].data_objects.clone()), (PD_MSG_TYPE_REQUEST as u8, vec![rdo]));
+
+        assert!(!messages[1].is_extended() && !messages[1].is_bist());
+
+        let connect = decode_payload(&pd_put_data(1654, 3, &[0x45, 0x10, 0x00, 0x00, 0x00, 0x21]));
+        assert!(matches!(connect, Decoded::Connection(CcStatus { attached: true, .. })));
+        assert_eq!(decode_payload(&[0x0C, 0x05, 0x22, 0x00]), Decoded::Unknown);
+    }
+
+    #[test]
+    fn keeps_extended_and_bist_messages_out_of_power_objects() {
+        // Extended Status (type 0x02, one chunk of 7 data bytes) followed by BIST Carrier Mode
+        let mut events = vec![0x90, 0, 0, 0, 0, 0, 0x82, 0xB2, 0x07, 0x80];
+        events.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7]);
+        events.extend_from_slice(&[0x8B, 0, 0, 0, 0, 0, 0x43, 0x10]);
+        events.extend_from_slice(&(5u32 << 28).to_le_bytes());
+        let payload = pd_put_data(1654, 3, &events);
+
+        let messages = decode_pd_messages(&payload);
+        assert!(messages[0].is_extended() && !messages[0].is_bist());
+        assert_eq!((messages[0].extended_header, messages[0].data_objects.len()), (Some(0x8007), 0));
+        assert!(messages[1].is_bist() && !messages[1].is_extended());
+        assert_eq!(decode_pd_request(&payload), None);
+    }
+
+    #[test]
+    fn status_only_payload_is_not_an_event() {
+        assert_eq!(decode_cc_event(&pd_put_data(420, 3, &[])), None);
+        assert_eq!(decode_cc_event(&[0x0C, 0x05, 0x22, 0x00]), None);
+    }
+
+    #[test]
+    fn well_formed_requires_known_type_and_exact_put_data_tiling() {
+        assert!(is_well_formed(&[0x0C, 0x05, 0x22, 0x00]));
+        assert!(is_well_formed(&[CMD_PUT_DATA, 0x05, 0x00, 0x00]));
+        assert!(is_well_formed(&put_data_with(ATT_ADC, 2, vec![0xAA, 0xBB])));
+        assert!(!is_well_formed(&put_data_with(ATT_ADC, 4, vec![0xAA, 0xBB])));
+        assert!(!is_well_formed(&put_data_with(ATT_ADC, 1, vec![0xAA, 0xBB])));
+        assert!(!is_well_formed(&[0x7F, 0x05, 0x00, 0x00]));
+        assert!(!is_well_formed(&[0x0C, 0x05]));
+    }
+
+    /// Arbitrary bytes behind a PutData header and one extended header for `attribute`
+    fn put_data_with(attribute: u16, size: u32, tail: Vec<u8>) -> Vec<u8> {
+        let mut payload = vec![CMD_PUT_DATA, 0x01, 0x00, 0x00];
+        payload.extend_from_slice(&(attribute as u32 | (size & 0x3FF) << 22).to_le_bytes());
+        payload.extend(tail);
+        payload
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn decoders_never_panic_on_garbage(
+            raw in proptest::collection::vec(proptest::num::u8::ANY, 0..512),
+            attribute in proptest::sample::select(vec![ATT_ADC, ATT_SETTINGS, ATT_PD_PACKET, 0x7FFF]),
+            size in 0u32..0x400,
+            tail in proptest::collection::vec(proptest::num::u8::ANY, 0..256),
+        ) {
+            for payload in [raw, put_data_with(attribute, size, tail)] {
+                let _ = parse_header(&payload);
+                let _ = logical_packets(&payload);
+                let _ = is_well_formed(&payload);
+                let _ = verify_checksum(&payload);
+                let _ = decode_adc(&payload);
+                let _ = decode_temperature(&payload);
+                let _ = decode_cc_event(&payload);
+                let _ = decode_pd_request(&payload);
+                let _ = decode_payload(&payload);
+                let _ = crate::decoder::DecoderRegistry::default().decode(&payload);
+            }
+        }
+    }
+}
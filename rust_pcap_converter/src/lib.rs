@@ -0,0 +1,58 @@
+//! Convert USB pcapng captures into Parquet datasets for KM003C protocol analysis.
+//!
+//! The `pcap_to_parquet` binary is a thin wrapper around [`convert`]; library
+//! users build a [`ConverterConfig`] and call it directly, or stream records
+//! through [`Converter::records`].
+
+mod checkpoint;
+mod compare;
+pub mod config;
+mod convert;
+mod converter;
+pub mod decoder;
+#[cfg(feature = "duckdb")]
+mod duckdb_sink;
+mod energy;
+mod error;
+mod exchange;
+pub mod experimental;
+mod features;
+mod field_map;
+mod fingerprint;
+mod framestream;
+mod follow;
+mod influx;
+mod input;
+pub mod km003c;
+mod metadata;
+mod native;
+mod normalize;
+mod record;
+mod resample;
+mod schema;
+pub mod source;
+mod stats;
+#[cfg(test)]
+mod test_support;
+mod timeline;
+pub mod urb;
+mod validate;
+#[cfg(feature = "wasm")]
+mod wasm_decoder;
+
+pub use compare::{compare_captures, Comparison, Divergence, Message};
+pub use config::ConverterConfig;
+pub use convert::{convert, convert_batch, convert_bytes, convert_with_observer};
+pub use error::ConverterError;
+pub use exchange::exchange_table;
+pub use features::{feature_matrix, FEATURE_PADDING};
+pub use fingerprint::{dataframe_fingerprint, print_parquet_fingerprint};
+pub use converter::{list_devices, log_progress, ConvertEvent, Converter, DeviceSummary};
+pub use metadata::{print_parquet_metadata, read_parquet_metadata};
+pub use record::{anonymize_records, create_dataframe, decode, enrich_device_names, mark_transaction_gaps, process_packet, split_iso_segments, UsbPacketRecord};
+pub use resample::resample_adc;
+pub use schema::print_columns_info;
+pub use stats::{print_parquet_statistics, print_statistics, throughput_stats};
+pub use validate::{validate_capture, Validation};
+
+pub type Result<T> = std::result::Result<T, ConverterError>;
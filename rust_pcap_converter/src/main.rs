@@ -1,619 +1,536 @@
-use clap::Parser;
-use polars::prelude::*;
-use polars_utils::plpath::PlPath;
-use rtshark::{Packet as RtSharkPacket, RTSharkBuilder};
-use serde::{Deserialize, Serialize};
+use clap::{Parser, Subcommand};
+use pcap_to_parquet::config::{AppendDedupKey, Compression, ConverterConfig, DedupKey, FrameRange, OutputFormat, ParserKind, PartitionKey, Preview};
+use pcap_to_parquet::experimental::ExperimentalField;
+use log::LevelFilter;
+use pcap_to_parquet::Result;
+use std::io::Write;
 use std::path::PathBuf;
-
-type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
-
-/// Clean tshark field values that contain HTML entities and surrounding quotes
-fn clean_tshark_field(value: &str) -> String {
-    // Decode HTML entities
-    let decoded = value
-        .replace("&#x27;", "'")  // Single quote
-        .replace("&lt;", "<")    // Less than
-        .replace("&gt;", ">")    // Greater than
-        .replace("&amp;", "&")   // Ampersand
-        .replace("&quot;", "\""); // Double quote
-    
-    // Remove surrounding single quotes if present
-    if decoded.starts_with('\'') && decoded.ends_with('\'') && decoded.len() >= 2 {
-        decoded[1..decoded.len()-1].to_string()
-    } else {
-        decoded.to_string()
-    }
-}
+use std::process::ExitCode;
 
 #[derive(Parser, Debug)]
-#[command(author, version, about = "Convert pcap files to Parquet format with USB payload data")]
+#[command(
+    author,
+    version,
+    about = "Convert pcap files to Parquet format with USB payload data",
+    subcommand_negates_reqs = true
+)]
 struct Cli {
-    /// Input pcapng file to process
-    #[arg(short, long)]
-    input: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Input pcapng file to process (.gz and .zst archives are decompressed transparently; `-` reads stdin and needs --device-address).
+    /// Repeat to convert several captures, named by --output-dir / --output-template
+    #[arg(short, long, required_unless_present = "columns_info")]
+    input: Vec<PathBuf>,
 
-    /// Output parquet file
+    /// Print every output column with its dtype, what populates it and a description, then exit
+    #[arg(long)]
+    columns_info: bool,
+
+    /// List the USB device addresses in the capture with packet counts, then exit
+    #[arg(long)]
+    list_devices: bool,
+
+    /// Output file
     #[arg(short, long, default_value = "usb_packets.parquet")]
     output: PathBuf,
 
-    /// Device address filter (auto-detected from filename if not provided)
+    /// Write each input's output into this directory, named by --output-template (default {stem}.<format>)
+    #[arg(long)]
+    output_dir: Option<PathBuf>,
+
+    /// Output file name with {stem}, {addr} and {date} placeholders, e.g. "{stem}_{date}.parquet"
+    #[arg(long)]
+    output_template: Option<String>,
+
+    /// Output file format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Parquet)]
+    format: OutputFormat,
+
+    /// Write a Hive-style dataset under the output directory: <output>/session_id=<id>/data.parquet,
+    /// or <output>/km_packet_type=<type>/<session>.parquet with undecoded rows under km_packet_type=unknown
+    #[arg(long, value_enum)]
+    partition_by: Option<PartitionKey>,
+
+    /// Create the output's parent directories if they do not exist
+    #[arg(long)]
+    mkdir: bool,
+
+    /// Insert rows into this DuckDB database file instead of writing --output (requires the `duckdb` feature)
+    #[arg(long)]
+    duckdb: Option<PathBuf>,
+
+    /// DuckDB table to create or, with --append, insert into
+    #[arg(long, default_value = "usb_packets", requires = "duckdb")]
+    table: String,
+
+    /// How to read the capture: tshark, or the built-in usbmon pcapng/pcap parser, which is
+    /// faster but leaves class and descriptor fields empty (falls back to tshark for --filter
+    /// and --tshark-arg)
+    #[arg(long, value_enum, default_value_t = ParserKind::Tshark)]
+    parser: ParserKind,
+
+    /// tshark binary (or its directory) to run instead of the one on PATH
+    #[arg(long)]
+    tshark_path: Option<PathBuf>,
+
+    /// Extra tshark argument, `-o <pref>:<value>` or `-d <decode-as rule>` (repeatable)
+    #[arg(long, allow_hyphen_values = true)]
+    tshark_arg: Vec<String>,
+
+    /// tshark display filter that replaces the generated one (device address, --payload-only);
+    /// the device address is still auto-detected for the session ID and output naming
+    #[arg(long, value_name = "DISPLAY_FILTER")]
+    filter: Option<String>,
+
+    /// TOML or JSON file mapping attribute bit numbers to names, merged over the built-in table
+    #[arg(long)]
+    attribute_map: Option<PathBuf>,
+
+    /// TOML or JSON file listing the exact output columns and dtypes ([[columns]] name/dtype, dtypes
+    /// as shown by --columns-info); missing columns are added as nulls and unlisted ones dropped
+    #[arg(long)]
+    schema: Option<PathBuf>,
+
+    /// WebAssembly module exporting `decode`, whose JSON output becomes `wasm_*` columns
+    /// (requires the `wasm` feature)
+    #[arg(long, value_name = "PATH")]
+    wasm_decoder: Option<PathBuf>,
+
+    /// Device address filter (auto-detected from filename, else the busiest address in the capture)
     #[arg(short, long)]
     device_address: Option<u8>,
 
+    /// usbmon bus to convert, when the same device address exists on several buses
+    #[arg(long)]
+    bus_id: Option<u8>,
+
     /// Session ID for this capture (auto-detected from filename if not provided)
     #[arg(long)]
     session_id: Option<String>,
 
+    /// Build the session ID from a template with {stem}, {addr} and {date}, e.g. device{addr}_{stem}
+    #[arg(long, conflicts_with = "session_id")]
+    session_template: Option<String>,
+
     /// Append to existing parquet file instead of overwriting
     #[arg(long)]
     append: bool,
 
-    /// Only capture packets with payload data (exclude control/setup packets)
+    /// Replace the output file, its side tables or the DuckDB database if they already exist (refused by default)
+    #[arg(long, visible_alias = "force", conflicts_with = "append", overrides_with = "no_overwrite")]
+    overwrite: bool,
+
+    /// Refuse to replace an existing output file (the default; undoes an earlier --overwrite)
+    #[arg(long, overrides_with = "overwrite")]
+    no_overwrite: bool,
+
+    /// With --append, only convert frames newer than those already stored for this session
+    #[arg(long, requires = "append")]
+    since: bool,
+
+    /// With --append, what counts as already stored (comma-separated checks, any match skips the
+    /// capture): session = same session ID on the same bus (cheap column filter), hash = any
+    /// packet_hash already stored (same URB and payload; keeps all stored hashes in memory),
+    /// urb_id = any URB id with the same bus, type and URB timestamp (ignores the payload;
+    /// same memory cost), none = always append
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "session,hash", requires = "append")]
+    dedup_key: Vec<AppendDedupKey>,
+
+    /// Only capture packets with payload data (exclude control/setup packets; without it every row is tagged in packet_category)
     #[arg(long)]
     payload_only: bool,
 
-    /// Verbose output
-    #[arg(short, long)]
-    verbose: bool,
-}
+    /// Attach product names (device_product_name) looked up from device descriptor VID/PID
+    #[arg(long)]
+    device_name: bool,
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct UsbPacketRecord {
-    session_id: String,
-    frame_number: u32,
-    timestamp: f64,
-    timestamp_absolute: String,
-    direction: String,
-    device_address: u8,
-    bus_id: u8,
-    endpoint_address: String,
-    endpoint_number: u8,
-    transfer_type: String,
-    urb_type: String,
-    urb_status: String,
-    data_length: u32,
-    urb_length: u32,
-    payload_hex: String,
-    // Additional USB metadata
-    setup_flag: String,
-    data_flag: String,
-    interval: u32,
-    start_frame: u32,
-    // Frame-level metadata
-    frame_length: u32,
-    frame_protocols: String,
-    source_file: String,
-    // USB Control packet fields (for setup packets)
-    bmrequest_type: Option<String>,
-    brequest: Option<String>,
-    brequest_name: Option<String>,
-    wvalue: Option<u32>,
-    windex: Option<u32>,
-    wlength: Option<u32>,
-    descriptor_type: Option<String>,
-    descriptor_index: Option<u32>,
-    language_id: Option<u32>,
-    // USB Transfer flags (detailed USB metadata)
-    transfer_flags: Option<String>,
-    copy_of_transfer_flags: Option<String>,
-    // Additional USB identifiers and timing
-    urb_id: String,
-    usb_src: String,
-    usb_dst: String,
-    usb_addr: String,
-    urb_ts_sec: u64,
-    urb_ts_usec: u32,
-    added_datetime: String,
-}
+    /// Blank host-identifying fields, replace URB ids/addresses with pseudonyms and rebase timestamps to zero for public sharing
+    #[arg(long)]
+    anonymize: bool,
 
-fn main() -> Result<()> {
-    let mut args = Cli::parse();
-
-    // Auto-detect device address from filename if not provided
-    let device_address = if let Some(addr) = args.device_address {
-        addr
-    } else {
-        let filename = args.input.file_name().and_then(|s| s.to_str()).unwrap_or("");
-        // Look for pattern like "filename.16.pcapng" where 16 is the device address
-        if let Some(dot_pos) = filename.rfind('.') {
-            let before_ext = &filename[..dot_pos];
-            if let Some(second_dot_pos) = before_ext.rfind('.') {
-                let potential_id = &before_ext[second_dot_pos + 1..];
-                if let Ok(id) = potential_id.parse::<u8>() {
-                    println!("Auto-detected device address from filename: {}", id);
-                    args.device_address = Some(id);
-                    id
-                } else {
-                    return Err("Could not auto-detect device address from filename. Please provide --device-address".into());
-                }
-            } else {
-                return Err("Could not auto-detect device address from filename. Please provide --device-address".into());
-            }
-        } else {
-            return Err("Could not auto-detect device address from filename. Please provide --device-address".into());
-        }
-    };
+    /// Merge each submit URB and its completion into one row with submit_ts/complete_ts
+    #[arg(long)]
+    coalesce_urbs: bool,
 
-    // Auto-detect session ID from filename if not provided
-    let session_id = if let Some(id) = &args.session_id {
-        id.clone()
-    } else {
-        let filename = args.input.file_name().and_then(|s| s.to_str()).unwrap_or("");
-        if let Some(dot_pos) = filename.rfind('.') {
-            let before_ext = &filename[..dot_pos];
-            before_ext.to_string()
-        } else {
-            filename.to_string()
-        }
-    };
+    /// Include the full frame bytes (usbmon header + data) as frame_raw_hex
+    #[arg(long)]
+    include_raw: bool,
 
-    println!("Processing file: {:?}", args.input);
-    println!("Output file: {:?}", args.output);
-    println!("Device address: {}", device_address);
-    println!("Session ID: {}", session_id);
-    if args.payload_only {
-        println!("Mode: payload-only (excluding control/setup packets)");
-    } else {
-        println!("Mode: complete capture (all USB packets to device)");
-    }
+    /// Truncate payload_hex to the first N bytes (decoded columns still use the full payload)
+    #[arg(long, value_name = "N")]
+    max_payload: Option<usize>,
 
-    // Build tshark filter with minimal essential filtering
-    let mut filter_parts = vec![
-        format!("usb.device_address == {}", device_address)
-    ];
-    
-    // Add capdata filter only if payload-only mode is requested
-    if args.payload_only {
-        filter_parts.push("usb.capdata".to_string());
-    }
-    
-    let display_filter = filter_parts.join(" && ");
+    /// Round the decoded adc_* columns to this many decimal places (default: full precision)
+    #[arg(long, value_name = "DECIMALS")]
+    adc_precision: Option<u32>,
 
-    if args.verbose {
-        println!("Display filter: {}", display_filter);
-    }
+    /// Unstable research aid: decode NAME=OFFSET:TYPE[:le|be][:SCALE] from each payload into a
+    /// Float64 column NAME (TYPE: u8..u64, i8..i64, f32, f64); repeatable
+    #[arg(long, value_name = "SPEC", hide = true)]
+    experimental_field: Vec<ExperimentalField>,
+
+    /// Detect KM003C transaction id gaps per direction (dropped URBs) and emit transaction_gap
+    #[arg(long)]
+    check_transactions: bool,
+
+    /// Warn about regions where usbmon dropped events (URBs whose submit or completion is missing)
+    #[arg(long)]
+    check_drops: bool,
 
-    let file_path = args.input.to_str().ok_or("File path is not valid UTF-8")?;
+    /// Sort rows by capture time before writing; with --append the whole combined file is
+    /// re-sorted, which needs memory for the full dataset plus a sorted copy
+    #[arg(long)]
+    sort_on_write: bool,
 
-    let mut rtshark = RTSharkBuilder::builder()
-        .input_path(file_path)
-        .display_filter(&display_filter)
-        .spawn()?;
+    /// Offset each session's timestamp by the end of the sessions captured before it (ordered
+    /// by epoch time), so appended captures share one monotonic timeline; the session's own
+    /// time is kept in timestamp_session
+    #[arg(long)]
+    continuous_time: bool,
 
-    let mut records = Vec::new();
-    let mut packet_count = 0;
+    /// Write <output>.manifest.json next to each output file with its source file, sessions,
+    /// device addresses, record count, time range, tool version and columns
+    #[arg(long)]
+    manifest: bool,
 
-    println!("Reading packets...");
-    while let Some(packet) = rtshark.read()? {
-        packet_count += 1;
+    /// Skip the summary statistics after converting; their group-bys are slow on large captures
+    #[arg(long)]
+    no_stats: bool,
 
-        if packet_count % 100 == 0 {
-            println!("Processed {} packets...", packet_count);
-        }
+    /// Write <output>_packets/_adc/_pd.parquet tables sharing session_id and frame_number
+    /// instead of one wide table
+    #[arg(long)]
+    normalize: bool,
 
-        if let Ok(record) = process_packet(packet, &session_id, args.verbose) {
-            records.push(record);
-        }
-    }
+    /// Drop duplicate rows within this capture, keyed on frame number (default) or packet hash
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "frame-number")]
+    dedup: Option<DedupKey>,
 
-    println!(
-        "Processed {} packets, extracted {} USB data packets",
-        packet_count,
-        records.len()
-    );
+    /// Comma-separated columns to emit (frame_number and payload_hex are always kept)
+    #[arg(long, value_delimiter = ',')]
+    fields: Option<Vec<String>>,
 
-    if records.is_empty() {
-        println!("No USB data packets found. Check your filter settings.");
-        return Ok(());
-    }
+    /// Comma-separated KM003C packet types to keep after decoding: header types (connect, get_data, put_data, ...)
+    /// or PutData contents (adc, adc_queue, settings, pd)
+    #[arg(long, value_delimiter = ',', value_name = "TYPES")]
+    only_packet_types: Option<Vec<String>>,
 
-    // Convert to Polars DataFrame
-    let new_df = create_dataframe(records)?;
-    
-    // Handle file merging/appending
-    let final_df = if args.append && args.output.exists() {
-        println!("Loading existing data from {:?}", args.output);
-        let existing_df = LazyFrame::scan_parquet(PlPath::new(args.output.to_str().unwrap()), ScanArgsParquet::default())?
-            .collect()?;
-        
-        // Check for duplicate session_id
-        let existing_sessions: Vec<String> = existing_df
-            .column("session_id")?
-            .unique()?
-            .str()?
-            .into_no_null_iter()
-            .map(|s| s.to_string())
-            .collect();
-        
-        if existing_sessions.contains(&session_id) {
-            println!("⚠️  Session ID '{}' already exists in {:?}. Skipping to prevent duplicates.", session_id, args.output);
-            println!("✅ No new data added. Dataset remains unchanged.");
-            return Ok(());
-        }
-        
-        // Additional check: detect potential duplicate data by URB IDs
-        // (in case same file processed with different session ID)
-        if new_df.height() > 0 && existing_df.height() > 0 {
-            // Get sample URB IDs from both datasets
-            let new_urb_ids: Vec<String> = new_df.column("urb_id")?.str()?.into_no_null_iter().take(5).map(|s| s.to_string()).collect();
-            let existing_urb_ids: Vec<String> = existing_df.column("urb_id")?.str()?.into_no_null_iter().take(100).map(|s| s.to_string()).collect();
-            
-            // Check if any new URB IDs already exist
-            let duplicates = new_urb_ids.iter().filter(|&id| existing_urb_ids.contains(id)).count();
-            if duplicates >= 2 {
-                println!("⚠️  Detected potential duplicate data (same URB IDs). Skipping to prevent duplicates.");
-                println!("✅ No new data added. Dataset remains unchanged.");
-                return Ok(());
-            }
-        }
-        
-        // Combine datasets using vstack
-        let combined_df = existing_df.vstack(&new_df)?;
-        
-        println!("Combined {} existing + {} new = {} total records", 
-                existing_df.height(), new_df.height(), combined_df.height());
-        
-        combined_df
-    } else {
-        if args.output.exists() && !args.append {
-            println!("Overwriting existing file: {:?}", args.output);
-        }
-        new_df
-    };
-    
-    // Save to Parquet
-    println!("Saving to Parquet file: {:?}", args.output);
-    let mut file = std::fs::File::create(&args.output)?;
-    ParquetWriter::new(&mut file).finish(&mut final_df.clone())?;
+    /// Keep only rows with this URB status errno, e.g. 0 or -32 (repeatable)
+    #[arg(long, value_name = "STATUS", allow_hyphen_values = true)]
+    urb_status: Vec<String>,
 
-    println!("Successfully saved {} records to {:?}", final_df.height(), args.output);
+    /// Keep only rows whose decoded KM003C header has this transaction id, in both directions
+    #[arg(long, value_name = "ID")]
+    transaction_id: Option<u8>,
 
-    // // Print some statistics (with error handling)
-    // if let Err(e) = print_statistics(&final_df) {
-    //     println!("⚠️  Statistics display error (data is fine): {}", e);
-    //     println!("✅ Dataset saved successfully with {} records", final_df.height());
-    // }
+    /// Print the first or last N rows of the final table (head:N or tail:N), then write as usual
+    #[arg(long, value_name = "head:N|tail:N")]
+    preview: Option<Preview>,
 
-    Ok(())
-}
+    /// Skip an inclusive frame-number range such as 1200-1350, e.g. a corrupt segment; repeatable
+    #[arg(long, value_name = "A-B")]
+    exclude_frames: Vec<FrameRange>,
 
-fn process_packet(packet: RtSharkPacket, session_id: &str, verbose: bool) -> Result<UsbPacketRecord> {
-    // Extract frame-level information
-    let frame_layer = packet.layer_name("frame").ok_or("Missing frame layer")?;
-    
-    let frame_num = frame_layer
-        .metadata("frame.number")
-        .and_then(|n| n.value().parse().ok())
-        .unwrap_or(0);
-
-    let timestamp = frame_layer
-        .metadata("frame.time_relative")
-        .and_then(|n| n.value().parse().ok())
-        .unwrap_or(0.0);
-
-    let timestamp_absolute = frame_layer
-        .metadata("frame.time")
-        .map(|t| t.value().to_string())
-        .unwrap_or_else(|| "Unknown".to_string());
-
-    let frame_length: u32 = frame_layer
-        .metadata("frame.len")
-        .and_then(|l| l.value().parse().ok())
-        .unwrap_or(0);
-
-    let frame_protocols = frame_layer
-        .metadata("frame.protocols")
-        .map(|p| p.value().to_string())
-        .unwrap_or_else(|| "Unknown".to_string());
-
-    // Extract USB layer information
-    let usb_layer = packet.layer_name("usb").ok_or("Missing USB layer")?;
-    
-    let direction = match usb_layer.metadata("usb.endpoint_address.direction").map(|d| d.value()) {
-        Some("0") => "H->D".to_string(),
-        Some("1") => "D->H".to_string(),
-        _ => "Unknown".to_string(),
-    };
+    /// Keep only failed transfers (non-zero URB status, ignoring -115 on pending submits)
+    #[arg(long)]
+    errors_only: bool,
 
-    let device_address: u8 = usb_layer
-        .metadata("usb.device_address")
-        .and_then(|d| d.value().parse().ok())
-        .unwrap_or(0);
-
-    let bus_id: u8 = usb_layer
-        .metadata("usb.bus_id")
-        .and_then(|b| b.value().parse().ok())
-        .unwrap_or(0);
-
-    let endpoint_address = usb_layer
-        .metadata("usb.endpoint_address")
-        .map(|e| e.value().to_string())
-        .unwrap_or_else(|| "Unknown".to_string());
-
-    let endpoint_number: u8 = usb_layer
-        .metadata("usb.endpoint_address.number")
-        .and_then(|n| n.value().parse().ok())
-        .unwrap_or(0);
-
-    let transfer_type = usb_layer
-        .metadata("usb.transfer_type")
-        .map(|t| t.value().to_string())
-        .unwrap_or_else(|| "Unknown".to_string());
-
-    let urb_type = usb_layer
-        .metadata("usb.urb_type")
-        .map(|u| clean_tshark_field(u.value()))
-        .unwrap_or_else(|| "Unknown".to_string());
-
-    let urb_status = usb_layer
-        .metadata("usb.urb_status")
-        .map(|s| s.value().to_string())
-        .unwrap_or_else(|| "Unknown".to_string());
-
-    let data_length: u32 = usb_layer
-        .metadata("usb.data_len")
-        .and_then(|d| d.value().parse().ok())
-        .unwrap_or(0);
-
-    let urb_length: u32 = usb_layer
-        .metadata("usb.urb_len")
-        .and_then(|l| l.value().parse().ok())
-        .unwrap_or(0);
-
-    let setup_flag = usb_layer
-        .metadata("usb.setup_flag")
-        .map(|s| clean_tshark_field(s.value()))
-        .unwrap_or_else(|| "Unknown".to_string());
-
-    let data_flag = usb_layer
-        .metadata("usb.data_flag")
-        .map(|d| clean_tshark_field(d.value()))
-        .unwrap_or_else(|| "Unknown".to_string());
-
-    let interval: u32 = usb_layer
-        .metadata("usb.interval")
-        .and_then(|i| i.value().parse().ok())
-        .unwrap_or(0);
-
-    let start_frame: u32 = usb_layer
-        .metadata("usb.start_frame")
-        .and_then(|s| s.value().parse().ok())
-        .unwrap_or(0);
-
-    // Extract hex payload (might be empty for control packets)
-    let payload_hex = usb_layer.metadata("usb.capdata")
-        .map(|p| p.value().to_string())
-        .unwrap_or_else(|| String::new());
-
-    // Clean up hex string (remove colons)
-    let clean_hex = payload_hex.replace(':', "");
-
-    // Convert hex to bytes (handle empty payloads)
-    let payload_bytes = if clean_hex.is_empty() {
-        Vec::new()
-    } else {
-        hex::decode(&clean_hex)
-            .map_err(|e| format!("Failed to decode hex payload '{}': {}", clean_hex, e))?
-    };
+    /// Polars SQL statement run against the converted rows (table `packets`); its result is written
+    #[arg(long)]
+    query: Option<String>,
+
+    /// Stop after reading this many packets
+    #[arg(long)]
+    limit: Option<usize>,
+
+    /// Keep 1 in N matching packets (the 1st, N+1th, ...) for a quick overview of a large capture
+    #[arg(long, value_name = "N")]
+    sample_rate: Option<usize>,
+
+    /// Also write mean ADC voltage/current/power in bins of this many milliseconds to <output>_resampled_<N>ms.parquet
+    #[arg(long, value_name = "INTERVAL_MS")]
+    resample: Option<u32>,
+
+    /// Also write each GetData request paired with its PutData response to <output>_exchanges.parquet
+    #[arg(long)]
+    exchanges: bool,
+
+    /// Add a cumulative_wh column: ADC power integrated over time (trapezoidal) within each session
+    #[arg(long)]
+    energy: bool,
+
+    /// Also write an all-integer feature matrix (packet type, payload length, first N payload bytes
+    /// padded with -1) to <output>_features.parquet
+    #[arg(long, value_name = "BYTES")]
+    features: Option<usize>,
+
+    /// Write per-endpoint bytes, duration, average and peak 1 s throughput to this JSON file
+    #[arg(long)]
+    throughput_json: Option<PathBuf>,
+
+    /// Checkpoint progress to <output>.partial/ every N records so a crashed run can be resumed
+    #[arg(long)]
+    batch_size: Option<usize>,
+
+    /// Resume an interrupted conversion, skipping frames already saved in the checkpoint
+    #[arg(long)]
+    resume: bool,
+
+    /// Keep converting a capture that is still being written, appending new frames until interrupted
+    #[arg(long)]
+    follow: bool,
+
+    /// Parquet compression codec
+    #[arg(long, value_enum, default_value_t = Compression::Zstd)]
+    compression: Compression,
 
-    // Extract USB Control packet fields (only present in control transfers)
-    let bmrequest_type = usb_layer.metadata("usb.bmRequestType").map(|b| b.value().to_string());
-    let brequest = usb_layer.metadata("usb.setup.bRequest").map(|b| b.value().to_string());
-    let brequest_name = usb_layer.metadata("usb.setup.bRequest.name").map(|b| b.value().to_string());
-    let wvalue = usb_layer.metadata("usb.setup.wValue").and_then(|w| w.value().parse().ok());
-    let windex = usb_layer.metadata("usb.setup.wIndex").and_then(|w| w.value().parse().ok());
-    let wlength = usb_layer.metadata("usb.setup.wLength").and_then(|w| w.value().parse().ok());
-    let descriptor_type = usb_layer.metadata("usb.bDescriptorType").map(|d| d.value().to_string());
-    let descriptor_index = usb_layer.metadata("usb.setup.wValue.descriptor_index").and_then(|d| d.value().parse().ok());
-    let language_id = usb_layer.metadata("usb.setup.wValue.language_id").and_then(|l| l.value().parse().ok());
-    
-    // Extract USB transfer flags
-    let transfer_flags = usb_layer.metadata("usb.transfer_flags").map(|t| t.value().to_string());
-    let copy_of_transfer_flags = usb_layer.metadata("usb.copy_of_transfer_flags").map(|c| c.value().to_string());
-    
-    // Extract additional USB identifiers and timing
-    let urb_id = usb_layer.metadata("usb.urb_id").map(|u| u.value().to_string()).unwrap_or_else(|| "Unknown".to_string());
-    let usb_src = usb_layer.metadata("usb.src").map(|s| s.value().to_string()).unwrap_or_else(|| "Unknown".to_string());
-    let usb_dst = usb_layer.metadata("usb.dst").map(|d| d.value().to_string()).unwrap_or_else(|| "Unknown".to_string());
-    let usb_addr = usb_layer.metadata("usb.addr").map(|a| a.value().to_string()).unwrap_or_else(|| "Unknown".to_string());
-    let urb_ts_sec = usb_layer.metadata("usb.urb_ts_sec").and_then(|t| t.value().parse().ok()).unwrap_or(0);
-    let urb_ts_usec = usb_layer.metadata("usb.urb_ts_usec").and_then(|t| t.value().parse().ok()).unwrap_or(0);
-
-    if verbose {
-        println!(
-            "Frame {}: {} bytes {} @ {:.6}s [{}:{}]",
-            frame_num, payload_bytes.len(), direction, timestamp, bus_id, endpoint_number
-        );
+    /// Compression level (zstd: 1-22, gzip: 0-10; defaults to a codec-specific level)
+    #[arg(long)]
+    compression_level: Option<i32>,
+
+    /// Verbose output (debug-level logging)
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// Only log errors; statistics and progress messages are suppressed
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Diff the KM003C message sequences of two captures and report the first divergence
+    Compare {
+        /// Reference capture
+        left: PathBuf,
+        /// Capture compared against it (device address and tshark options apply to both)
+        right: PathBuf,
+    },
+    /// Print statistics for an already converted Parquet file
+    Stats {
+        /// Parquet file written by this tool
+        parquet: PathBuf,
+    },
+    /// Check that the capture's bulk payloads follow the KM003C protocol grammar
+    Validate {
+        /// Capture to check (device address and tshark options apply)
+        capture: PathBuf,
+        /// Minimum percentage of recognized payloads; below it the command exits non-zero
+        #[arg(long, default_value_t = 100.0)]
+        threshold: f64,
+    },
+    /// Print the provenance (tool and tshark versions, display filter, source file) stored in a Parquet file
+    Metadata {
+        /// Parquet file written by this tool
+        parquet: PathBuf,
+    },
+    /// Print a hash of a converted Parquet file's rows, ignoring the conversion time and
+    /// wall-clock timestamps, to check that two conversions hold the same data
+    Fingerprint {
+        /// Parquet file written by this tool
+        parquet: PathBuf,
+    },
+}
+
+impl From<Cli> for ConverterConfig {
+    fn from(args: Cli) -> Self {
+        let mut config = ConverterConfig::new(args.input.first().cloned().unwrap_or_default())
+            .output(args.output)
+            .format(args.format)
+            .append(args.append)
+            .dedup_keys(args.dedup_key)
+            .overwrite(args.overwrite && !args.no_overwrite)
+            .since(args.since)
+            .payload_only(args.payload_only)
+            .device_name(args.device_name)
+            .anonymize(args.anonymize)
+            .coalesce_urbs(args.coalesce_urbs)
+            .include_raw(args.include_raw)
+            .check_transactions(args.check_transactions)
+            .check_drops(args.check_drops)
+            .errors_only(args.errors_only)
+            .exchanges(args.exchanges)
+            .energy(args.energy)
+            .continuous_time(args.continuous_time)
+            .manifest(args.manifest)
+            .no_stats(args.no_stats)
+            .sort_on_write(args.sort_on_write)
+            .normalize(args.normalize)
+            .resume(args.resume)
+            .follow(args.follow)
+            .mkdir(args.mkdir)
+            .table(args.table)
+            .parser(args.parser)
+            .compression(args.compression)
+            .verbose(args.verbose);
+        if let Some(address) = args.device_address {
+            config = config.device_address(address);
+        }
+        if let Some(bus_id) = args.bus_id {
+            config = config.bus_id(bus_id);
+        }
+        if let Some(session_id) = args.session_id {
+            config = config.session_id(session_id);
+        }
+        if let Some(template) = args.session_template {
+            config = config.session_template(template);
+        }
+        if let Some(dir) = args.output_dir {
+            config = config.output_dir(dir);
+        }
+        if let Some(template) = args.output_template {
+            config = config.output_template(template);
+        }
+        if let Some(key) = args.dedup {
+            config = config.dedup(key);
+        }
+        if let Some(path) = args.tshark_path {
+            config = config.tshark_path(path);
+        }
+        for arg in args.tshark_arg {
+            config = config.tshark_arg(arg);
+        }
+        if let Some(filter) = args.filter {
+            config = config.display_filter(filter);
+        }
+        if let Some(path) = args.attribute_map {
+            config = config.attribute_map(path);
+        }
+        if let Some(path) = args.schema {
+            config = config.schema(path);
+        }
+        if let Some(path) = args.wasm_decoder {
+            config = config.wasm_decoder(path);
+        }
+        if let Some(path) = args.duckdb {
+            config = config.duckdb(path);
+        }
+        if let Some(key) = args.partition_by {
+            config = config.partition_by(key);
+        }
+        if let Some(fields) = args.fields {
+            config = config.fields(fields);
+        }
+        if let Some(types) = args.only_packet_types {
+            config = config.only_packet_types(types);
+        }
+        if let Some(preview) = args.preview {
+            config = config.preview(preview);
+        }
+        for range in args.exclude_frames {
+            config = config.exclude_frames(range);
+        }
+        for status in args.urb_status {
+            config = config.urb_status(status);
+        }
+        if let Some(id) = args.transaction_id {
+            config = config.transaction_id(id);
+        }
+        if let Some(query) = args.query {
+            config = config.query(query);
+        }
+        if let Some(bytes) = args.max_payload {
+            config = config.max_payload(bytes);
+        }
+        if let Some(decimals) = args.adc_precision {
+            config = config.adc_precision(decimals);
+        }
+        for field in args.experimental_field {
+            config = config.experimental_field(field);
+        }
+        if let Some(limit) = args.limit {
+            config = config.limit(limit);
+        }
+        if let Some(n) = args.sample_rate {
+            config = config.sample_rate(n);
+        }
+        if let Some(bytes) = args.features {
+            config = config.features(bytes);
+        }
+        if let Some(interval_ms) = args.resample {
+            config = config.resample(interval_ms);
+        }
+        if let Some(path) = args.throughput_json {
+            config = config.throughput_json(path);
+        }
+        if let Some(size) = args.batch_size {
+            config = config.batch_size(size);
+        }
+        if let Some(level) = args.compression_level {
+            config = config.compression_level(level);
+        }
+        config
     }
+}
 
-    let record = UsbPacketRecord {
-        session_id: session_id.to_string(),
-        frame_number: frame_num,
-        timestamp,
-        timestamp_absolute,
-        direction,
-        device_address,
-        bus_id,
-        endpoint_address,
-        endpoint_number,
-        transfer_type,
-        urb_type,
-        urb_status,
-        data_length,
-        urb_length,
-        payload_hex: clean_hex.clone(),
-        setup_flag,
-        data_flag,
-        interval,
-        start_frame,
-        frame_length,
-        frame_protocols,
-        source_file: session_id.to_string(), // Use session_id as source file identifier
-        bmrequest_type,
-        brequest,
-        brequest_name,
-        wvalue,
-        windex,
-        wlength,
-        descriptor_type,
-        descriptor_index,
-        language_id,
-        transfer_flags,
-        copy_of_transfer_flags,
-        urb_id,
-        usb_src,
-        usb_dst,
-        usb_addr,
-        urb_ts_sec,
-        urb_ts_usec,
-        added_datetime: chrono::Utc::now().to_rfc3339(),
+/// Log this crate's messages at the level picked by `--quiet`/`--verbose`; `RUST_LOG` can
+/// refine it. Messages are printed bare, as the tool's console output.
+fn init_logging(cli: &Cli) {
+    let level = match (cli.quiet, cli.verbose) {
+        (true, _) => LevelFilter::Error,
+        (_, true) => LevelFilter::Debug,
+        _ => LevelFilter::Info,
     };
-
-    Ok(record)
+    env_logger::Builder::new()
+        .filter_level(LevelFilter::Warn)
+        .filter_module("pcap_to_parquet", level)
+        .parse_default_env()
+        .format(|buf, record| writeln!(buf, "{}", record.args()))
+        .init();
 }
 
-fn create_dataframe(records: Vec<UsbPacketRecord>) -> Result<DataFrame> {
-    let session_ids: Vec<String> = records.iter().map(|r| r.session_id.clone()).collect();
-    let frame_numbers: Vec<u32> = records.iter().map(|r| r.frame_number).collect();
-    let timestamps: Vec<f64> = records.iter().map(|r| r.timestamp).collect();
-    let timestamp_absolutes: Vec<String> = records.iter().map(|r| r.timestamp_absolute.clone()).collect();
-    let directions: Vec<String> = records.iter().map(|r| r.direction.clone()).collect();
-    let device_addresses: Vec<u32> = records.iter().map(|r| r.device_address as u32).collect();
-    let bus_ids: Vec<u32> = records.iter().map(|r| r.bus_id as u32).collect();
-    let endpoint_addresses: Vec<String> = records.iter().map(|r| r.endpoint_address.clone()).collect();
-    let endpoint_numbers: Vec<u32> = records.iter().map(|r| r.endpoint_number as u32).collect();
-    let transfer_types: Vec<String> = records.iter().map(|r| r.transfer_type.clone()).collect();
-    let urb_types: Vec<String> = records.iter().map(|r| r.urb_type.clone()).collect();
-    let urb_statuses: Vec<String> = records.iter().map(|r| r.urb_status.clone()).collect();
-    let data_lengths: Vec<u32> = records.iter().map(|r| r.data_length).collect();
-    let urb_lengths: Vec<u32> = records.iter().map(|r| r.urb_length).collect();
-    let payload_hexs: Vec<String> = records.iter().map(|r| r.payload_hex.clone()).collect();
-    let setup_flags: Vec<String> = records.iter().map(|r| r.setup_flag.clone()).collect();
-    let data_flags: Vec<String> = records.iter().map(|r| r.data_flag.clone()).collect();
-    let intervals: Vec<u32> = records.iter().map(|r| r.interval).collect();
-    let start_frames: Vec<u32> = records.iter().map(|r| r.start_frame).collect();
-    let frame_lengths: Vec<u32> = records.iter().map(|r| r.frame_length).collect();
-    let frame_protocols: Vec<String> = records.iter().map(|r| r.frame_protocols.clone()).collect();
-    let source_files: Vec<String> = records.iter().map(|r| r.source_file.clone()).collect();
-    let bmrequest_types: Vec<Option<String>> = records.iter().map(|r| r.bmrequest_type.clone()).collect();
-    let brequests: Vec<Option<String>> = records.iter().map(|r| r.brequest.clone()).collect();
-    let brequest_names: Vec<Option<String>> = records.iter().map(|r| r.brequest_name.clone()).collect();
-    let wvalues: Vec<Option<u32>> = records.iter().map(|r| r.wvalue).collect();
-    let windexes: Vec<Option<u32>> = records.iter().map(|r| r.windex).collect();
-    let wlengths: Vec<Option<u32>> = records.iter().map(|r| r.wlength).collect();
-    let descriptor_types: Vec<Option<String>> = records.iter().map(|r| r.descriptor_type.clone()).collect();
-    let descriptor_indexes: Vec<Option<u32>> = records.iter().map(|r| r.descriptor_index).collect();
-    let language_ids: Vec<Option<u32>> = records.iter().map(|r| r.language_id).collect();
-    let transfer_flags_vec: Vec<Option<String>> = records.iter().map(|r| r.transfer_flags.clone()).collect();
-    let copy_of_transfer_flags_vec: Vec<Option<String>> = records.iter().map(|r| r.copy_of_transfer_flags.clone()).collect();
-    let urb_ids: Vec<String> = records.iter().map(|r| r.urb_id.clone()).collect();
-    let usb_srcs: Vec<String> = records.iter().map(|r| r.usb_src.clone()).collect();
-    let usb_dsts: Vec<String> = records.iter().map(|r| r.usb_dst.clone()).collect();
-    let usb_addrs: Vec<String> = records.iter().map(|r| r.usb_addr.clone()).collect();
-    let urb_ts_secs: Vec<u64> = records.iter().map(|r| r.urb_ts_sec).collect();
-    let urb_ts_usecs: Vec<u32> = records.iter().map(|r| r.urb_ts_usec).collect();
-    let added_datetimes: Vec<String> = records.iter().map(|r| r.added_datetime.clone()).collect();
-
-    let df = df! [
-        "session_id" => session_ids,
-        "frame_number" => frame_numbers,
-        "timestamp" => timestamps,
-        "timestamp_absolute" => timestamp_absolutes,
-        "direction" => directions,
-        "device_address" => device_addresses,
-        "bus_id" => bus_ids,
-        "endpoint_address" => endpoint_addresses,
-        "endpoint_number" => endpoint_numbers,
-        "transfer_type" => transfer_types,
-        "urb_type" => urb_types,
-        "urb_status" => urb_statuses,
-        "data_length" => data_lengths,
-        "urb_length" => urb_lengths,
-        "payload_hex" => payload_hexs,
-        "setup_flag" => setup_flags,
-        "data_flag" => data_flags,
-        "interval" => intervals,
-        "start_frame" => start_frames,
-        "frame_length" => frame_lengths,
-        "frame_protocols" => frame_protocols,
-        "source_file" => source_files,
-        "bmrequest_type" => bmrequest_types,
-        "brequest" => brequests,
-        "brequest_name" => brequest_names,
-        "wvalue" => wvalues,
-        "windex" => windexes,
-        "wlength" => wlengths,
-        "descriptor_type" => descriptor_types,
-        "descriptor_index" => descriptor_indexes,
-        "language_id" => language_ids,
-        "transfer_flags" => transfer_flags_vec,
-        "copy_of_transfer_flags" => copy_of_transfer_flags_vec,
-        "urb_id" => urb_ids,
-        "usb_src" => usb_srcs,
-        "usb_dst" => usb_dsts,
-        "usb_addr" => usb_addrs,
-        "urb_ts_sec" => urb_ts_secs,
-        "urb_ts_usec" => urb_ts_usecs,
-        "added_datetime" => added_datetimes,
-    ]?;
-
-    Ok(df)
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    init_logging(&cli);
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
 }
 
-// fn print_statistics(df: &DataFrame) -> Result<()> {
-//     println!("\n=== Statistics ===");
-//     println!("Total records: {}", df.height());
-//     println!("Columns: {:?}", df.get_column_names());
-    
-//     // Use lazy evaluation for statistics
-//     let lazy_df = df.clone().lazy();
-    
-//     // Basic counts using group_by
-//     let direction_stats = lazy_df
-//         .clone()
-//         .group_by([col("direction")])
-//         .agg([len().alias("count")])
-//         .sort(["count"], SortMultipleOptions::default().with_order_descending(true))
-//         .collect()?;
-    
-//     println!("\nDirection distribution:");
-//     println!("{}", direction_stats);
-    
-//     let device_stats = lazy_df
-//         .clone()
-//         .group_by([col("device_address")])
-//         .agg([len().alias("count")])
-//         .sort(["count"], SortMultipleOptions::default().with_order_descending(true))
-//         .collect()?;
-    
-//     println!("\nDevice address distribution:");
-//     println!("{}", device_stats);
-    
-//     // Data length statistics
-//     let length_stats = lazy_df
-//         .clone()
-//         .select([
-//             col("data_length").mean().alias("avg_length"),
-//             col("data_length").min().alias("min_length"),
-//             col("data_length").max().alias("max_length"),
-//         ])
-//         .collect()?;
-    
-//     println!("\nPayload length statistics:");
-//     println!("{}", length_stats);
-    
-//     // Time range statistics
-//     let time_stats = lazy_df
-//         .clone()
-//         .select([
-//             col("timestamp").min().alias("start_time"),
-//             col("timestamp").max().alias("end_time"),
-//             (col("timestamp").max() - col("timestamp").min()).alias("duration"),
-//         ])
-//         .collect()?;
-    
-//     println!("\nTime range:");
-//     println!("{}", time_stats);
-
-//     Ok(())
-// }
\ No newline at end of file
+fn run(mut cli: Cli) -> Result<()> {
+    if cli.columns_info {
+        return pcap_to_parquet::print_columns_info();
+    }
+    let command = cli.command.take();
+    let list_devices = cli.list_devices;
+    let inputs = cli.input.clone();
+    let config = ConverterConfig::from(cli);
+    match command {
+        Some(Command::Compare { left, right }) => {
+            let comparison = pcap_to_parquet::compare_captures(&config, &left, &right)?;
+            println!("{}", comparison);
+            return Ok(());
+        }
+        Some(Command::Stats { parquet }) => return pcap_to_parquet::print_parquet_statistics(&parquet),
+        Some(Command::Validate { capture, threshold }) => {
+            let validation = pcap_to_parquet::validate_capture(&config, &capture)?;
+            println!("{}", validation);
+            if validation.recognized_percent() < threshold {
+                return Err(format!("{:.2}% recognized is below the --threshold of {}%", validation.recognized_percent(), threshold).into());
+            }
+            return Ok(());
+        }
+        Some(Command::Metadata { parquet }) => return pcap_to_parquet::print_parquet_metadata(&parquet),
+        Some(Command::Fingerprint { parquet }) => return pcap_to_parquet::print_parquet_fingerprint(&parquet),
+        None => {}
+    }
+    if list_devices {
+        let devices = pcap_to_parquet::list_devices(&config)?;
+        println!("{:>7}  {:>9}  {:<9}  Product", "Address", "Packets", "VID:PID");
+        for device in devices {
+            let ids = match (device.id_vendor, device.id_product) {
+                (Some(vid), Some(pid)) => format!("{:04x}:{:04x}", vid, pid),
+                _ => "-".to_string(),
+            };
+            println!("{:>7}  {:>9}  {:<9}  {}", device.address, device.packets, ids, device.product_name().unwrap_or("-"));
+        }
+        return Ok(());
+    }
+
+    pcap_to_parquet::convert_batch(&config, &inputs)
+}
@@ -0,0 +1,201 @@
+//! Run provenance stored in the Parquet key-value footer rather than as columns.
+
+use polars::prelude::*;
+use serde::Serialize;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use crate::Result;
+
+/// Namespace for the footer keys this tool writes
+const KEY_PREFIX: &str = "pcap_to_parquet.";
+
+/// How a Parquet file was produced
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RunMetadata {
+    pub(crate) tshark_version: Option<String>,
+    pub(crate) display_filter: String,
+    pub(crate) source_file: String,
+    /// `--sample-rate`: one row kept per this many matching packets
+    pub(crate) sample_rate: Option<usize>,
+}
+
+impl RunMetadata {
+    fn pairs(&self) -> Vec<(String, String)> {
+        [
+            ("tool_version", env!("CARGO_PKG_VERSION").to_string()),
+            ("tshark_version", self.tshark_version.clone().unwrap_or_else(|| "unknown".to_string())),
+            ("display_filter", self.display_filter.clone()),
+            ("source_file", self.source_file.clone()),
+        ]
+        .into_iter()
+        .chain(self.sample_rate.map(|rate| ("sample_rate", rate.to_string())))
+        .map(|(key, value)| (format!("{}{}", KEY_PREFIX, key), value))
+        .collect()
+    }
+
+    pub(crate) fn key_value_metadata(&self) -> KeyValueMetadata {
+        KeyValueMetadata::from_static(self.pairs())
+    }
+}
+
+/// `source_file` value for an input path; `--anonymize` keeps only the file name so
+/// home directories and user names never reach the footer or manifest
+pub(crate) fn source_label(input: &Path, anonymize: bool) -> String {
+    match input.file_name() {
+        Some(name) if anonymize => name.to_string_lossy().into_owned(),
+        _ => input.display().to_string(),
+    }
+}
+
+/// Sidecar description of one written file (`--manifest`)
+#[derive(Debug, Serialize)]
+struct Manifest {
+    file: String,
+    source_file: String,
+    tool_version: &'static str,
+    record_count: usize,
+    session_ids: Vec<String>,
+    device_addresses: Vec<u32>,
+    /// Min and max `timestamp`, absent for an empty file
+    time_range: Option<(f64, f64)>,
+    columns: Vec<ManifestColumn>,
+}
+
+#[derive(Debug, Serialize)]
+struct ManifestColumn {
+    name: String,
+    dtype: String,
+}
+
+/// `<output>.manifest.json` next to a written file
+pub(crate) fn manifest_path(output: &Path) -> PathBuf {
+    let mut path = output.as_os_str().to_owned();
+    path.push(".manifest.json");
+    PathBuf::from(path)
+}
+
+/// Describe `df` as written to `output`; columns dropped by `--fields` are left out of the summary
+pub(crate) fn write_manifest(df: &DataFrame, output: &Path, run_metadata: &RunMetadata) -> Result<()> {
+    let session_ids = match df.column("session_id") {
+        Ok(column) => column.unique_stable()?.cast(&DataType::String)?.str()?.into_iter().flatten().map(str::to_string).collect(),
+        Err(_) => Vec::new(),
+    };
+    let mut device_addresses: Vec<u32> = match df.column("device_address") {
+        Ok(column) => column.unique()?.cast(&DataType::UInt32)?.u32()?.into_iter().flatten().collect(),
+        Err(_) => Vec::new(),
+    };
+    device_addresses.sort_unstable();
+    let time_range = match df.column("timestamp") {
+        Ok(column) => {
+            let timestamps = column.f64()?;
+            timestamps.min().zip(timestamps.max())
+        }
+        Err(_) => None,
+    };
+    let manifest = Manifest {
+        file: output.display().to_string(),
+        source_file: run_metadata.source_file.clone(),
+        tool_version: env!("CARGO_PKG_VERSION"),
+        record_count: df.height(),
+        session_ids,
+        device_addresses,
+        time_range,
+        columns: df
+            .get_columns()
+            .iter()
+            .map(|c| ManifestColumn { name: c.name().to_string(), dtype: c.dtype().to_string() })
+            .collect(),
+    };
+    std::fs::write(manifest_path(output), serde_json::to_string_pretty(&manifest)?)?;
+    Ok(())
+}
+
+/// This tool's footer entries of a Parquet file, prefix stripped, in file order
+pub fn read_parquet_metadata(path: &Path) -> Result<Vec<(String, String)>> {
+    let mut reader = ParquetReader::new(File::open(path)?);
+    let metadata = reader.get_metadata()?;
+    Ok(metadata
+        .key_value_metadata()
+        .iter()
+        .flatten()
+        .filter_map(|kv| Some((kv.key.strip_prefix(KEY_PREFIX)?.to_string(), kv.value.clone().unwrap_or_default())))
+        .collect())
+}
+
+/// Print the run metadata of a converted file (`metadata` subcommand)
+pub fn print_parquet_metadata(path: &Path) -> Result<()> {
+    let entries = read_parquet_metadata(path)?;
+    if entries.is_empty() {
+        println!("{:?} has no pcap_to_parquet metadata (written by an older version or another tool)", path);
+    }
+    for (key, value) in entries {
+        println!("{:<15} {}", key, value);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_parquet_footer() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.parquet");
+        let run = RunMetadata {
+            tshark_version: Some("TShark (Wireshark) 4.2.2".to_string()),
+            display_filter: "usb.device_address == 16".to_string(),
+            source_file: "captures/orig_adc.16.pcapng".to_string(),
+            sample_rate: Some(10),
+        };
+        let mut df = df!("frame_number" => [1u32, 2]).unwrap();
+        ParquetWriter::new(File::create(&path).unwrap())
+            .with_key_value_metadata(Some(run.key_value_metadata()))
+            .finish(&mut df)
+            .unwrap();
+
+        let entries = read_parquet_metadata(&path).unwrap();
+        assert_eq!(entries[0], ("tool_version".to_string(), env!("CARGO_PKG_VERSION").to_string()));
+        assert_eq!(&entries[1..], &[
+            ("tshark_version".to_string(), "TShark (Wireshark) 4.2.2".to_string()),
+            ("display_filter".to_string(), "usb.device_address == 16".to_string()),
+            ("source_file".to_string(), "captures/orig_adc.16.pcapng".to_string()),
+            ("sample_rate".to_string(), "10".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn anonymized_source_keeps_only_the_file_name() {
+        let input = Path::new("/home/alice/captures/orig_adc.pcapng");
+        assert_eq!(source_label(input, false), "/home/alice/captures/orig_adc.pcapng");
+        assert_eq!(source_label(input, true), "orig_adc.pcapng");
+    }
+
+    #[test]
+    fn manifest_describes_the_written_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out.parquet");
+        let run = RunMetadata {
+            tshark_version: None,
+            display_filter: String::new(),
+            source_file: "orig_adc.pcapng".to_string(),
+            sample_rate: None,
+        };
+        let df = df!(
+            "session_id" => ["b", "a", "b"],
+            "device_address" => [16u32, 16, 5],
+            "timestamp" => [0.5, 0.0, 2.25],
+        )
+        .unwrap();
+        write_manifest(&df, &output, &run).unwrap();
+
+        let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(manifest_path(&output)).unwrap()).unwrap();
+        assert_eq!(json["source_file"], "orig_adc.pcapng");
+        assert_eq!(json["record_count"], 3);
+        assert_eq!(json["session_ids"], serde_json::json!(["b", "a"]));
+        assert_eq!(json["device_addresses"], serde_json::json!([5, 16]));
+        assert_eq!(json["time_range"], serde_json::json!([0.0, 2.25]));
+        assert_eq!(json["columns"][2], serde_json::json!({"name": "timestamp", "dtype": "f64"}));
+    }
+}
@@ -0,0 +1,514 @@
+//! `--parser native`: read usbmon pcapng and pcap captures without tshark.
+//!
+//! The capture is memory-mapped and only the usbmon header and captured data are
+//! decoded, into the field names and display formats tshark uses, so
+//! [`process_packet`](crate::process_packet) treats both parsers alike. Class
+//! dissection (HID reports, descriptor fields, request names, ISO segments) still
+//! needs tshark; of the control decoding only the setup packet and the device
+//! descriptor's VID/PID are covered.
+
+use memmap2::Mmap;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+
+use crate::record::parse_device_descriptor_ids;
+use crate::source::PacketSource;
+use crate::Result;
+
+/// `LINKTYPE_USB_LINUX`: usbmon with the 48-byte header
+const LINKTYPE_USB_LINUX: u16 = 189;
+
+/// `LINKTYPE_USB_LINUX_MMAPPED`: usbmon with the 64-byte header
+const LINKTYPE_USB_LINUX_MMAPPED: u16 = 220;
+
+const PCAPNG_SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 1;
+const PCAPNG_OBSOLETE_PACKET: u32 = 2;
+const PCAPNG_SIMPLE_PACKET: u32 = 3;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+const PCAP_MAGIC_MICROS: u32 = 0xA1B2_C3D4;
+const PCAP_MAGIC_NANOS: u32 = 0xA1B2_3C4D;
+
+/// usbmon transfer type of control transfers
+const XFER_CONTROL: u8 = 2;
+
+/// Capture bytes: mapped for files, read into memory for pipes
+enum CaptureBytes {
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+}
+
+impl std::ops::Deref for CaptureBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            CaptureBytes::Mapped(map) => map,
+            CaptureBytes::Owned(bytes) => bytes,
+        }
+    }
+}
+
+/// One captured frame
+struct Frame<'a> {
+    linktype: u16,
+    /// Capture time in nanoseconds since the Unix epoch
+    timestamp_ns: u128,
+    original_len: u32,
+    data: &'a [u8],
+    comment: Option<String>,
+}
+
+/// Link type and timestamp resolution of a pcapng interface
+#[derive(Clone, Copy)]
+struct Interface {
+    linktype: u16,
+    units_per_second: u128,
+}
+
+/// Container layout, discovered from the leading magic number
+enum Layout {
+    /// Interfaces of the current section, indexed by interface ID
+    Pcapng { big_endian: bool, interfaces: Vec<Interface> },
+    Pcap { big_endian: bool, linktype: u16, units_per_second: u128 },
+}
+
+/// The usbmon header fields the converter reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct UsbmonHeader {
+    pub urb_id: u64,
+    pub urb_type: u8,
+    pub transfer_type: u8,
+    pub endpoint: u8,
+    pub device: u8,
+    pub bus: u16,
+    pub setup_flag: u8,
+    pub data_flag: u8,
+    pub ts_sec: i64,
+    pub ts_usec: i32,
+    pub status: i32,
+    pub urb_len: u32,
+    pub data_len: u32,
+    pub setup: [u8; 8],
+    /// Only in the 64-byte (mmapped) header
+    pub interval: Option<i32>,
+    pub start_frame: Option<i32>,
+    pub transfer_flags: Option<u32>,
+    /// ISO descriptors between the header and the data
+    pub iso_descriptors: u32,
+}
+
+impl UsbmonHeader {
+    /// Parse the usbmon header at the start of a frame, returning it and its length.
+    /// usbmon writes the header in the capturing host's byte order, little-endian in practice.
+    pub(crate) fn parse(frame: &[u8], mmapped: bool) -> Option<(Self, usize)> {
+        let header_len = if mmapped { 64 } else { 48 };
+        let header = frame.get(..header_len)?;
+        let u32_at = |offset: usize| u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap());
+        let i32_at = |offset: usize| i32::from_le_bytes(header[offset..offset + 4].try_into().unwrap());
+        let parsed = Self {
+            urb_id: u64::from_le_bytes(header[0..8].try_into().unwrap()),
+            urb_type: header[8],
+            transfer_type: header[9],
+            endpoint: header[10],
+            device: header[11],
+            bus: u16::from_le_bytes([header[12], header[13]]),
+            setup_flag: header[14],
+            data_flag: header[15],
+            ts_sec: i64::from_le_bytes(header[16..24].try_into().unwrap()),
+            ts_usec: i32_at(24),
+            status: i32_at(28),
+            urb_len: u32_at(32),
+            data_len: u32_at(36),
+            setup: header[40..48].try_into().unwrap(),
+            interval: mmapped.then(|| i32_at(48)),
+            start_frame: mmapped.then(|| i32_at(52)),
+            transfer_flags: mmapped.then(|| u32_at(56)),
+            iso_descriptors: if mmapped { u32_at(60) } else { 0 },
+        };
+        Some((parsed, header_len))
+    }
+}
+
+/// A frame decoded into tshark-named `frame` and `usb` layers
+pub(crate) struct NativePacket {
+    pub frame_number: u32,
+    pub time_relative: f64,
+    /// `None` for frames that are not usbmon
+    pub usb: Option<UsbmonHeader>,
+    /// Whether the frame carries data the converter treats as a payload (`usb.capdata`)
+    pub has_payload: bool,
+    layers: HashMap<String, HashMap<String, String>>,
+    raw: Vec<u8>,
+}
+
+impl PacketSource for NativePacket {
+    type Layer = HashMap<String, String>;
+
+    fn layer(&self, name: &str) -> Option<&Self::Layer> {
+        self.layers.get(name)
+    }
+
+    fn raw_bytes(&self) -> Option<Vec<u8>> {
+        Some(self.raw.clone())
+    }
+}
+
+/// Read position within the capture bytes
+struct Cursor {
+    layout: Layout,
+    offset: usize,
+    frame_number: u32,
+}
+
+/// A memory-mapped capture read one frame at a time
+pub(crate) struct NativeCapture {
+    bytes: CaptureBytes,
+    cursor: Cursor,
+    first_timestamp_ns: Option<u128>,
+}
+
+impl NativeCapture {
+    /// Map the capture and check its magic number
+    pub(crate) fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path)?;
+        let bytes = if file.metadata()?.is_file() {
+            // SAFETY: the map is only read, and a capture truncated underneath us fails
+            // the block length checks rather than being read out of bounds
+            CaptureBytes::Mapped(unsafe { Mmap::map(&file)? })
+        } else {
+            let mut bytes = Vec::new();
+            std::io::Read::read_to_end(&mut &file, &mut bytes)?;
+            CaptureBytes::Owned(bytes)
+        };
+        let not_a_capture = || format!("{:?} is not a pcap or pcapng capture; use --parser tshark", path);
+        let magic = bytes.get(..4).ok_or_else(not_a_capture)?;
+        let magic_le = u32::from_le_bytes(magic.try_into().unwrap());
+        let magic_be = u32::from_be_bytes(magic.try_into().unwrap());
+        let (layout, offset) = if magic_le == PCAPNG_SECTION_HEADER {
+            (Layout::Pcapng { big_endian: false, interfaces: Vec::new() }, 0)
+        } else if let Some((big_endian, nanos)) = [(magic_le, false), (magic_be, true)].into_iter().find_map(|(magic, big_endian)| match magic {
+            PCAP_MAGIC_MICROS => Some((big_endian, false)),
+            PCAP_MAGIC_NANOS => Some((big_endian, true)),
+            _ => None,
+        }) {
+            let linktype = read_u32(&bytes, 20, big_endian).ok_or_else(not_a_capture)?;
+            let units_per_second = if nanos { 1_000_000_000 } else { 1_000_000 };
+            (Layout::Pcap { big_endian, linktype: linktype as u16, units_per_second }, 24)
+        } else {
+            return Err(not_a_capture().into());
+        };
+        Ok(Self { bytes, cursor: Cursor { layout, offset, frame_number: 0 }, first_timestamp_ns: None })
+    }
+
+    /// Next frame, numbered like tshark's `frame.number`
+    pub(crate) fn next_packet(&mut self) -> Result<Option<NativePacket>> {
+        let Some(frame) = self.cursor.next_frame(&self.bytes)? else {
+            return Ok(None);
+        };
+        let first = *self.first_timestamp_ns.get_or_insert(frame.timestamp_ns);
+        let time_relative = frame.timestamp_ns.saturating_sub(first) as f64 / 1e9;
+        Ok(Some(native_packet(self.cursor.frame_number, time_relative, &frame)))
+    }
+}
+
+impl Cursor {
+    fn next_frame<'a>(&mut self, bytes: &'a [u8]) -> Result<Option<Frame<'a>>> {
+        loop {
+            if self.offset >= bytes.len() {
+                return Ok(None);
+            }
+            let truncated = |offset: usize| format!("Capture truncated at byte {}", offset);
+            match &mut self.layout {
+                Layout::Pcap { big_endian, linktype, units_per_second } => {
+                    let at = self.offset;
+                    let field = |index: usize| read_u32(bytes, at + index * 4, *big_endian).ok_or_else(|| truncated(at));
+                    let (seconds, fraction, captured, original) = (field(0)?, field(1)?, field(2)? as usize, field(3)?);
+                    let data = bytes.get(at + 16..at + 16 + captured).ok_or_else(|| truncated(at))?;
+                    self.offset = at + 16 + captured;
+                    self.frame_number += 1;
+                    let timestamp_ns = seconds as u128 * 1_000_000_000 + fraction as u128 * 1_000_000_000 / *units_per_second;
+                    return Ok(Some(Frame { linktype: *linktype, timestamp_ns, original_len: original, data, comment: None }));
+                }
+                Layout::Pcapng { big_endian, interfaces } => {
+                    let at = self.offset;
+                    let block_type = read_u32(bytes, at, *big_endian).ok_or_else(|| truncated(at))?;
+                    if block_type == PCAPNG_SECTION_HEADER {
+                        // The byte-order magic decides how this section, its own length included, is read
+                        *big_endian = match read_u32(bytes, at + 8, false).ok_or_else(|| truncated(at))? {
+                            PCAPNG_BYTE_ORDER_MAGIC => false,
+                            magic if magic.swap_bytes() == PCAPNG_BYTE_ORDER_MAGIC => true,
+                            _ => return Err(format!("Bad pcapng byte-order magic at byte {}", at).into()),
+                        };
+                        interfaces.clear();
+                    }
+                    let block_len = read_u32(bytes, at + 4, *big_endian).ok_or_else(|| truncated(at))? as usize;
+                    if block_len < 12 || !block_len.is_multiple_of(4) {
+                        return Err(format!("Bad pcapng block length {} at byte {}", block_len, at).into());
+                    }
+                    let body = bytes.get(at + 8..at + block_len - 4).ok_or_else(|| truncated(at))?;
+                    self.offset = at + block_len;
+                    let big_endian = *big_endian;
+                    match block_type {
+                        PCAPNG_INTERFACE_DESCRIPTION => {
+                            let linktype = read_u16(body, 0, big_endian).ok_or_else(|| truncated(at))?;
+                            let resolution = options(body.get(8..).unwrap_or_default(), big_endian)
+                                .find(|(code, _)| *code == 9)
+                                .and_then(|(_, value)| value.first().copied());
+                            interfaces.push(Interface { linktype, units_per_second: units_per_second(resolution) });
+                        }
+                        PCAPNG_ENHANCED_PACKET => {
+                            let field = |index: usize| read_u32(body, index * 4, big_endian).ok_or_else(|| truncated(at));
+                            let interface = interfaces
+                                .get(field(0)? as usize)
+                                .copied()
+                                .ok_or_else(|| format!("Packet at byte {} names an undeclared interface", at))?;
+                            let ticks = (field(1)? as u128) << 32 | field(2)? as u128;
+                            let (captured, original) = (field(3)? as usize, field(4)?);
+                            let data = body.get(20..20 + captured).ok_or_else(|| truncated(at))?;
+                            let comment = options(body.get(20 + captured.next_multiple_of(4)..).unwrap_or_default(), big_endian)
+                                .find(|(code, _)| *code == 1)
+                                .map(|(_, value)| String::from_utf8_lossy(value).into_owned());
+                            self.frame_number += 1;
+                            let timestamp_ns = ticks * 1_000_000_000 / interface.units_per_second;
+                            return Ok(Some(Frame { linktype: interface.linktype, timestamp_ns, original_len: original, data, comment }));
+                        }
+                        // Rare block kinds without (usable) timestamps still count as frames for tshark
+                        PCAPNG_OBSOLETE_PACKET | PCAPNG_SIMPLE_PACKET => {
+                            log::debug!("Skipping pcapng block type {} at byte {}", block_type, at);
+                            self.frame_number += 1;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn read_u16(bytes: &[u8], offset: usize, big_endian: bool) -> Option<u16> {
+    let raw: [u8; 2] = bytes.get(offset..offset + 2)?.try_into().ok()?;
+    Some(if big_endian { u16::from_be_bytes(raw) } else { u16::from_le_bytes(raw) })
+}
+
+fn read_u32(bytes: &[u8], offset: usize, big_endian: bool) -> Option<u32> {
+    let raw: [u8; 4] = bytes.get(offset..offset + 4)?.try_into().ok()?;
+    Some(if big_endian { u32::from_be_bytes(raw) } else { u32::from_le_bytes(raw) })
+}
+
+/// pcapng options as (code, value) up to `opt_endofopt` or the end of the block
+fn options(mut bytes: &[u8], big_endian: bool) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        let code = read_u16(bytes, 0, big_endian)?;
+        let len = read_u16(bytes, 2, big_endian)? as usize;
+        if code == 0 {
+            return None;
+        }
+        let value = bytes.get(4..4 + len)?;
+        bytes = bytes.get(4 + len.next_multiple_of(4)..).unwrap_or_default();
+        Some((code, value))
+    })
+}
+
+/// Timestamp units per second from `if_tsresol`: a power of ten, or of two when bit 7 is set
+fn units_per_second(resolution: Option<u8>) -> u128 {
+    match resolution {
+        None => 1_000_000,
+        Some(r) if r & 0x80 != 0 => 1u128 << (r & 0x7F).min(64),
+        Some(r) => 10u128.pow(u32::from(r).min(30)),
+    }
+}
+
+fn layer(fields: Vec<(&str, String)>) -> HashMap<String, String> {
+    fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect()
+}
+
+/// Lay out a frame the way tshark's PDML would
+fn native_packet(frame_number: u32, time_relative: f64, frame: &Frame) -> NativePacket {
+    let seconds = (frame.timestamp_ns / 1_000_000_000) as i64;
+    let nanos = (frame.timestamp_ns % 1_000_000_000) as u32;
+    let mut frame_layer = layer(vec![
+        ("frame.number", frame_number.to_string()),
+        ("frame.time_relative", format!("{:.9}", time_relative)),
+        ("frame.time_epoch", format!("{}.{:09}", seconds, nanos)),
+        ("frame.len", frame.original_len.to_string()),
+    ]);
+    if let Some(time) = chrono::DateTime::from_timestamp(seconds, nanos) {
+        frame_layer.insert("frame.time".into(), time.format("%b %e, %Y %H:%M:%S%.9f UTC").to_string());
+    }
+    if let Some(comment) = &frame.comment {
+        frame_layer.insert("frame.comment".into(), comment.clone());
+    }
+
+    let mut packet = NativePacket {
+        frame_number,
+        time_relative,
+        usb: None,
+        has_payload: false,
+        layers: HashMap::new(),
+        raw: frame.data.to_vec(),
+    };
+    let mmapped = match frame.linktype {
+        LINKTYPE_USB_LINUX_MMAPPED => true,
+        LINKTYPE_USB_LINUX => false,
+        _ => {
+            packet.layers.insert("frame".into(), frame_layer);
+            return packet;
+        }
+    };
+    let Some((header, header_len)) = UsbmonHeader::parse(frame.data, mmapped) else {
+        packet.layers.insert("frame".into(), frame_layer);
+        return packet;
+    };
+    frame_layer.insert("frame.protocols".into(), "usb".into());
+
+    let mut usb = layer(vec![
+        ("usb.urb_id", format!("0x{:016x}", header.urb_id)),
+        ("usb.urb_type", format!("'{}'", header.urb_type as char)),
+        ("usb.transfer_type", format!("0x{:02x}", header.transfer_type)),
+        ("usb.endpoint_address", format!("0x{:02x}", header.endpoint)),
+        ("usb.endpoint_address.direction", (header.endpoint >> 7).to_string()),
+        ("usb.endpoint_address.number", (header.endpoint & 0x0F).to_string()),
+        ("usb.device_address", header.device.to_string()),
+        ("usb.bus_id", header.bus.to_string()),
+        ("usb.setup_flag", header.setup_flag.to_string()),
+        ("usb.data_flag", header.data_flag.to_string()),
+        ("usb.urb_ts_sec", header.ts_sec.to_string()),
+        ("usb.urb_ts_usec", header.ts_usec.to_string()),
+        ("usb.urb_status", header.status.to_string()),
+        ("usb.urb_len", header.urb_len.to_string()),
+        ("usb.data_len", header.data_len.to_string()),
+    ]);
+    // tshark names the endpoint `bus.device.endpoint`; submissions go host -> device
+    let endpoint = format!("{}.{}.{}", header.bus, header.device, header.endpoint & 0x0F);
+    let (src, dst) = if header.urb_type == b'S' { ("host".to_string(), endpoint.clone()) } else { (endpoint.clone(), "host".to_string()) };
+    usb.insert("usb.src".into(), src);
+    usb.insert("usb.dst".into(), dst);
+    usb.insert("usb.addr".into(), endpoint);
+    if let (Some(interval), Some(start_frame), Some(flags)) = (header.interval, header.start_frame, header.transfer_flags) {
+        usb.insert("usb.interval".into(), interval.to_string());
+        usb.insert("usb.start_frame".into(), start_frame.to_string());
+        usb.insert("usb.transfer_flags".into(), format!("0x{:08x}", flags));
+    }
+    if header.setup_flag == 0 {
+        let word = |i: usize| u16::from_le_bytes([header.setup[i], header.setup[i + 1]]);
+        usb.insert("usb.bmRequestType".into(), format!("0x{:02x}", header.setup[0]));
+        usb.insert("usb.setup.bRequest".into(), header.setup[1].to_string());
+        usb.insert("usb.setup.wValue".into(), word(2).to_string());
+        usb.insert("usb.setup.wIndex".into(), word(4).to_string());
+        usb.insert("usb.setup.wLength".into(), word(6).to_string());
+    }
+
+    let data_start = header_len + header.iso_descriptors as usize * 16;
+    let data = frame.data.get(data_start..).unwrap_or_default();
+    let data = &data[..data.len().min(header.data_len as usize)];
+    match header.transfer_type {
+        // tshark's control dissector claims the data stage; only the device descriptor is decoded here
+        XFER_CONTROL => {
+            if let Some((vid, pid)) = parse_device_descriptor_ids(data) {
+                usb.insert("usb.bDescriptorType".into(), "0x01".into());
+                usb.insert("usb.idVendor".into(), format!("0x{:04x}", vid));
+                usb.insert("usb.idProduct".into(), format!("0x{:04x}", pid));
+            }
+        }
+        _ if !data.is_empty() => {
+            let hex: Vec<String> = data.iter().map(|b| format!("{:02x}", b)).collect();
+            usb.insert("usb.capdata".into(), hex.join(":"));
+            packet.has_payload = true;
+        }
+        _ => {}
+    }
+
+    packet.usb = Some(header);
+    packet.layers.insert("frame".into(), frame_layer);
+    packet.layers.insert("usb".into(), usb);
+    packet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn block(block_type: u32, mut body: Vec<u8>) -> Vec<u8> {
+        body.resize(body.len().next_multiple_of(4), 0);
+        let len = (body.len() + 12) as u32;
+        [&block_type.to_le_bytes()[..], &len.to_le_bytes(), &body, &len.to_le_bytes()].concat()
+    }
+
+    fn usbmon(urb_type: u8, transfer_type: u8, endpoint: u8, setup: Option<[u8; 8]>, data: &[u8]) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend(0xABCDu64.to_le_bytes());
+        header.extend([urb_type, transfer_type, endpoint, 7]);
+        header.extend(2u16.to_le_bytes());
+        header.extend([if setup.is_some() { 0 } else { b'-' }, if data.is_empty() { b'<' } else { 0 }]);
+        header.extend(1_700_000_000i64.to_le_bytes());
+        header.extend(250i32.to_le_bytes());
+        header.extend((-115i32).to_le_bytes());
+        header.extend((data.len() as u32).to_le_bytes());
+        header.extend((data.len() as u32).to_le_bytes());
+        header.extend(setup.unwrap_or_default());
+        header.extend([0u8; 16]);
+        [header, data.to_vec()].concat()
+    }
+
+    /// Section, one nanosecond-resolution usbmon interface, then the frames at 1 ms steps
+    fn capture(frames: &[Vec<u8>]) -> tempfile::NamedTempFile {
+        let mut bytes = block(PCAPNG_SECTION_HEADER, [&PCAPNG_BYTE_ORDER_MAGIC.to_le_bytes()[..], &[1, 0, 0, 0], &[0xFF; 8]].concat());
+        let tsresol = [9u16.to_le_bytes(), 1u16.to_le_bytes()].concat();
+        bytes.extend(block(PCAPNG_INTERFACE_DESCRIPTION, [&LINKTYPE_USB_LINUX_MMAPPED.to_le_bytes()[..], &[0; 6], &tsresol, &[9, 0, 0, 0], &[0; 4]].concat()));
+        for (index, frame) in frames.iter().enumerate() {
+            let ticks = 5_000_000_000u64 + index as u64 * 1_000_000;
+            let mut body = [0u32, (ticks >> 32) as u32, ticks as u32, frame.len() as u32, frame.len() as u32].map(u32::to_le_bytes).concat();
+            body.extend(frame);
+            bytes.extend(block(PCAPNG_ENHANCED_PACKET, body));
+        }
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&bytes).unwrap();
+        file
+    }
+
+    #[test]
+    fn decodes_usbmon_header_into_tshark_fields() {
+        let file = capture(&[
+            usbmon(b'C', 3, 0x81, None, &[0x41, 0x0A]),
+            usbmon(b'S', 2, 0x80, Some([0x80, 6, 0, 1, 0, 0, 18, 0]), &[]),
+        ]);
+        let mut capture = NativeCapture::open(file.path()).unwrap();
+
+        let bulk = capture.next_packet().unwrap().unwrap();
+        assert_eq!(bulk.frame_number, 1);
+        assert!(bulk.has_payload);
+        let usb = bulk.layer("usb").unwrap();
+        assert_eq!(usb["usb.capdata"], "41:0a");
+        assert_eq!(usb["usb.endpoint_address"], "0x81");
+        assert_eq!(usb["usb.endpoint_address.direction"], "1");
+        assert_eq!(usb["usb.urb_id"], "0x000000000000abcd");
+        assert_eq!(usb["usb.src"], "2.7.1");
+        assert_eq!(bulk.layer("frame").unwrap()["frame.time_epoch"], "5.000000000");
+
+        let setup = capture.next_packet().unwrap().unwrap();
+        assert_eq!(setup.time_relative, 0.001);
+        let record = crate::process_packet(&setup, "s", false).unwrap();
+        assert_eq!(record.transfer_type, "0x02");
+        assert_eq!(record.urb_status, "-115");
+        assert_eq!(record.bmrequest_type.as_deref(), Some("0x80"));
+        assert_eq!(record.wvalue, Some(0x0100));
+        assert!(capture.next_packet().unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_non_captures_and_reports_truncation() {
+        let mut junk = tempfile::NamedTempFile::new().unwrap();
+        junk.write_all(b"not a capture").unwrap();
+        assert!(NativeCapture::open(junk.path()).is_err());
+
+        let file = capture(&[usbmon(b'C', 3, 0x81, None, &[1, 2, 3])]);
+        let bytes = std::fs::read(file.path()).unwrap();
+        let mut cut = tempfile::NamedTempFile::new().unwrap();
+        cut.write_all(&bytes[..bytes.len() - 8]).unwrap();
+        assert!(NativeCapture::open(cut.path()).unwrap().next_packet().is_err());
+    }
+}
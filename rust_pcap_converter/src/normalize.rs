@@ -0,0 +1,107 @@
+//! `--normalize`: split the wide table into packet, ADC and PD tables keyed by frame.
+
+use log::info;
+use polars::prelude::*;
+use std::path::{Path, PathBuf};
+
+use crate::metadata::{write_manifest, RunMetadata};
+use crate::Result;
+
+/// Columns repeated in every table so child rows join back to their packet
+const KEY_COLUMNS: [&str; 2] = ["session_id", "frame_number"];
+
+/// Decoded columns of the ADC table; rows are the packets with a VBUS or temperature reading
+const ADC_COLUMNS: [&str; 5] = ["adc_vbus_v", "adc_ibus_a", "adc_power_w", "adc_temp_c", "adc_packet_kind"];
+
+/// Decoded columns of the PD table; rows are the packets with any of them set
+const PD_COLUMNS: [&str; 11] = [
+    "cc_orientation",
+    "cc_attached",
+    "cc_advertised_current",
+    "pd_rdo_object_position",
+    "pd_rdo_op_current",
+    "pd_rdo_max_current",
+    "pd_rdo_give_back",
+    "pd_rdo_capability_mismatch",
+    "pd_rdo_usb_comm_capable",
+    "pd_is_extended",
+    "pd_is_bist",
+];
+
+/// File suffixes of the tables written by `--normalize`, in write order
+pub(crate) const TABLE_SUFFIXES: [&str; 3] = ["packets", "adc", "pd"];
+
+/// The `(file suffix, table)` pairs written by `--normalize`
+pub(crate) fn normalized_tables(df: &DataFrame) -> Result<Vec<(&'static str, DataFrame)>> {
+    let decoded: Vec<&str> = ADC_COLUMNS.iter().chain(PD_COLUMNS.iter()).copied().collect();
+    let packets = df.drop_many(decoded);
+    let child = |columns: &[&str], has_row: Expr| -> Result<DataFrame> {
+        let selected: Vec<Expr> = KEY_COLUMNS.iter().chain(columns).map(|c| col(*c)).collect();
+        Ok(df.clone().lazy().filter(has_row).select(selected).collect()?)
+    };
+    let adc = child(&ADC_COLUMNS, col("adc_vbus_v").is_not_null().or(col("adc_temp_c").is_not_null()))?;
+    let pd_row = PD_COLUMNS.iter().map(|c| col(*c).is_not_null()).reduce(|a, b| a.or(b)).unwrap_or(lit(false));
+    let pd = child(&PD_COLUMNS, pd_row)?;
+    Ok(TABLE_SUFFIXES.into_iter().zip([packets, adc, pd]).collect())
+}
+
+/// `<dir>/<stem>_<suffix>.parquet` next to the configured output
+pub(crate) fn normalized_path(output: &Path, suffix: &str) -> PathBuf {
+    let stem = output.file_stem().and_then(|s| s.to_str()).unwrap_or("usb_packets");
+    output.with_file_name(format!("{}_{}.parquet", stem, suffix))
+}
+
+/// Write every normalized table next to `output`, each through a temporary file
+pub(crate) fn write_normalized(
+    df: &DataFrame,
+    output: &Path,
+    compression: ParquetCompression,
+    run_metadata: &RunMetadata,
+    manifest: bool,
+) -> Result<()> {
+    for (suffix, mut table) in normalized_tables(df)? {
+        let path = normalized_path(output, suffix);
+        let temp_path = path.with_extension("parquet.tmp");
+        ParquetWriter::new(std::fs::File::create(&temp_path)?)
+            .with_compression(compression)
+            .with_key_value_metadata(Some(run_metadata.key_value_metadata()))
+            .finish(&mut table)?;
+        std::fs::rename(&temp_path, &path)?;
+        if manifest {
+            write_manifest(&table, &path, run_metadata)?;
+        }
+        info!("Saved {} {} rows to {:?}", table.height(), suffix, path);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_dataframe;
+    use crate::test_support::{adc_put_data, bulk};
+    use std::collections::HashMap;
+
+    #[test]
+    fn splits_decoded_columns_into_keyed_tables() {
+        // Temperature-only ADC packet: 26-byte body, 25 °C at offset 24
+        let temperature = format!("41:0b:82:02:01:00:80:06:{}:80:0c", ["00"; 24].join(":"));
+        let records = vec![bulk(1, "0", "0c:0a:02:00"), bulk(2, "1", &adc_put_data(0x0a, 0, 0)), bulk(3, "1", &temperature)];
+        let df = create_dataframe(records).unwrap();
+
+        let tables: HashMap<&str, DataFrame> = normalized_tables(&df).unwrap().into_iter().collect();
+        assert_eq!(tables["packets"].height(), 3);
+        assert!(tables["packets"].get_column_index("adc_vbus_v").is_none());
+        assert!(tables["packets"].get_column_index("payload_hex").is_some());
+
+        let adc = &tables["adc"];
+        assert_eq!(adc.get_column_names_str(), ["session_id", "frame_number", "adc_vbus_v", "adc_ibus_a", "adc_power_w", "adc_temp_c", "adc_packet_kind"]);
+        let frames: Vec<u32> = adc.column("frame_number").unwrap().u32().unwrap().into_no_null_iter().collect();
+        assert_eq!(frames, [2, 3]);
+        assert_eq!(adc.column("adc_packet_kind").unwrap().str().unwrap().get(1), Some("temperature"));
+        assert_eq!(adc.column("adc_temp_c").unwrap().f64().unwrap().get(1), Some(25.0));
+        assert_eq!(tables["pd"].height(), 0);
+
+        assert_eq!(normalized_path(Path::new("out/run.parquet"), "adc"), Path::new("out/run_adc.parquet"));
+    }
+}
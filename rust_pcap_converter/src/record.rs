@@ -0,0 +1,1246 @@
+use log::debug;
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::decoder::{DecodedFields, DecoderRegistry};
+use crate::field_map::FieldMap;
+use crate::km003c;
+use crate::schema::COLUMNS;
+use crate::source::{MetadataSource, PacketSource};
+use crate::{ConverterError, Result};
+
+/// Clean tshark field values that contain HTML entities and surrounding quotes
+fn clean_tshark_field(value: &str) -> String {
+    // Decode HTML entities
+    let decoded = value
+        .replace("&#x27;", "'")  // Single quote
+        .replace("&lt;", "<")    // Less than
+        .replace("&gt;", ">")    // Greater than
+        .replace("&amp;", "&")   // Ampersand
+        .replace("&quot;", "\""); // Double quote
+    
+    // Remove surrounding single quotes if present
+    if decoded.starts_with('\'') && decoded.ends_with('\'') && decoded.len() >= 2 {
+        decoded[1..decoded.len()-1].to_string()
+    } else {
+        decoded.to_string()
+    }
+}
+
+/// Canonical `transfer_type` category: tshark's two-digit hex code (`0x00`..`0x03`)
+fn normalize_transfer_type(raw: &str) -> String {
+    let value = clean_tshark_field(raw).trim().to_ascii_lowercase();
+    let code = match value.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16).ok(),
+        None => value.parse::<u8>().ok(),
+    };
+    let code = code.filter(|c| *c <= 3).or_else(|| {
+        ["iso", "interrupt", "control", "bulk"]
+            .iter()
+            .position(|name| value.contains(name))
+            .map(|c| c as u8)
+    });
+    match code {
+        Some(code) => format!("0x{:02x}", code),
+        None => "Unknown".to_string(),
+    }
+}
+
+/// Canonical `urb_type` category: `S` (submit), `C` (complete) or `E` (error)
+fn normalize_urb_type(raw: &str) -> String {
+    let value = clean_tshark_field(raw).trim().to_ascii_uppercase();
+    let value = value.strip_prefix("URB_").unwrap_or(&value);
+    match value.chars().next() {
+        Some(c @ ('S' | 'C' | 'E')) => c.to_string(),
+        _ => "Unknown".to_string(),
+    }
+}
+
+/// Canonical `urb_status` category: the status as a plain decimal errno (e.g. `0`, `-115`)
+pub(crate) fn normalize_urb_status(raw: &str) -> String {
+    let value = clean_tshark_field(raw);
+    let value = value.trim();
+    // tshark may render the status with its name, e.g. "-EINPROGRESS (-115)"
+    let number = value
+        .rsplit_once('(')
+        .and_then(|(_, rest)| rest.strip_suffix(')'))
+        .unwrap_or(value);
+    match number.trim().parse::<i32>() {
+        Ok(status) => status.to_string(),
+        Err(_) => "Unknown".to_string(),
+    }
+}
+
+/// Split a setup packet's bmRequestType bitfield into (direction, type, recipient)
+fn decode_bmrequest_type(raw: &str) -> Option<(&'static str, &'static str, &'static str)> {
+    let bits = u8::try_from(parse_tshark_u16(raw)?).ok()?;
+    let direction = if bits & 0x80 != 0 { "D->H" } else { "H->D" };
+    let req_type = match (bits >> 5) & 0x03 {
+        0 => "standard",
+        1 => "class",
+        2 => "vendor",
+        _ => "reserved",
+    };
+    let recipient = match bits & 0x1F {
+        0 => "device",
+        1 => "interface",
+        2 => "endpoint",
+        3 => "other",
+        _ => "reserved",
+    };
+    Some((direction, req_type, recipient))
+}
+
+/// Known KM003C vendor control requests: (bmRequestType, bRequest, wValue or any, name),
+/// from `docs/usb_transport.md`
+const VENDOR_COMMANDS: &[(u8, u8, Option<u16>, &str)] = &[(0xC2, 0x32, None, "read_blob_0x32")];
+
+/// Name a vendor control request from its setup fields; `None` for unknown combinations
+fn vendor_command(bmrequest_type: &str, brequest: &str, wvalue: Option<u32>) -> Option<&'static str> {
+    let request_type = u8::try_from(parse_tshark_u16(bmrequest_type)?).ok()?;
+    let request = u8::try_from(parse_tshark_u16(brequest)?).ok()?;
+    VENDOR_COMMANDS
+        .iter()
+        .find(|(t, r, v, _)| *t == request_type && *r == request && v.is_none_or(|v| wvalue == Some(u32::from(v))))
+        .map(|(_, _, _, name)| *name)
+}
+
+/// Known USB VID/PID pairs and their product names
+const KNOWN_DEVICES: &[(u16, u16, &str)] = &[
+    (0x5FC9, 0x0061, "ChargerLAB POWER-Z KM002C"),
+    (0x5FC9, 0x0063, "ChargerLAB POWER-Z KM003C"),
+];
+
+pub(crate) fn known_device_name(id_vendor: u16, id_product: u16) -> Option<&'static str> {
+    KNOWN_DEVICES
+        .iter()
+        .find(|(vid, pid, _)| *vid == id_vendor && *pid == id_product)
+        .map(|(_, _, name)| *name)
+}
+
+/// Parse a tshark numeric field shown either as hex ("0x5fc9") or decimal
+pub(crate) fn parse_tshark_u16(value: &str) -> Option<u16> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+/// Parse tshark's `frame.time_epoch` ("1700000000.123456789") into whole microseconds
+fn parse_epoch_micros(value: &str) -> Option<i64> {
+    let (secs, frac) = value.trim().split_once('.').unwrap_or((value.trim(), ""));
+    if !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let micros: String = frac.chars().chain(std::iter::repeat('0')).take(6).collect();
+    secs.parse::<i64>().ok()?.checked_mul(1_000_000)?.checked_add(micros.parse().ok()?)
+}
+
+/// Extract idVendor/idProduct from a standard 18-byte device descriptor body
+pub(crate) fn parse_device_descriptor_ids(payload: &[u8]) -> Option<(u16, u16)> {
+    // bLength = 18, bDescriptorType = DEVICE (0x01)
+    if payload.len() < 18 || payload[0] != 0x12 || payload[1] != 0x01 {
+        return None;
+    }
+    let id_vendor = u16::from_le_bytes([payload[8], payload[9]]);
+    let id_product = u16::from_le_bytes([payload[10], payload[11]]);
+    Some((id_vendor, id_product))
+}
+
+/// Fill device_product_name on every record of a device whose descriptor was seen in the capture
+pub fn enrich_device_names(records: &mut [UsbPacketRecord]) {
+    let mut names = HashMap::new();
+    for record in records.iter() {
+        if let (Some(vid), Some(pid)) = (record.id_vendor, record.id_product) {
+            if let Some(name) = known_device_name(vid, pid) {
+                names.insert((record.bus_id, record.device_address), name);
+            }
+        }
+    }
+    for record in records.iter_mut() {
+        record.device_product_name = names
+            .get(&(record.bus_id, record.device_address))
+            .map(|name| name.to_string());
+    }
+}
+
+/// Strip host-identifying fields so a dataset can be shared publicly.
+///
+/// Wall-clock strings, `timestamp_utc`, capture comments and `frame_raw_hex` (whose usbmon
+/// header carries the URB id and wall-clock time) are blanked, kernel URB ids and usbmon addresses are replaced
+/// by sequential pseudonyms in order of first appearance (distinct values stay distinct, but
+/// nothing of the original survives), and both `timestamp` and the URB timestamps are rebased
+/// so the first record starts at zero. `packet_hash` is recomputed from the rewritten fields so
+/// it cannot link rows back to a plain conversion. Payloads and decoded protocol fields are left untouched.
+pub fn anonymize_records(records: &mut [UsbPacketRecord]) {
+    let first_timestamp = records.iter().map(|r| r.timestamp).fold(f64::INFINITY, f64::min);
+    let first_urb_us = records.iter().map(urb_timestamp_us).min().unwrap_or(0);
+    let mut urb_ids = Pseudonyms::new("urb");
+    // usb.src, usb.dst and usb.addr name the same endpoints, so they share one mapping
+    let mut addresses = Pseudonyms::new("addr");
+
+    for record in records.iter_mut() {
+        record.timestamp -= first_timestamp;
+        let urb_us = urb_timestamp_us(record) - first_urb_us;
+        record.urb_ts_sec = urb_us / 1_000_000;
+        record.urb_ts_usec = (urb_us % 1_000_000) as u32;
+
+        record.timestamp_absolute = String::new();
+        record.timestamp_utc = None;
+        record.added_datetime = String::new();
+        record.frame_comment = None;
+        record.frame_raw_hex = None;
+        record.urb_id = urb_ids.get(&record.urb_id);
+        record.usb_src = addresses.get(&record.usb_src);
+        record.usb_dst = addresses.get(&record.usb_dst);
+        record.usb_addr = addresses.get(&record.usb_addr);
+
+        let hash = packet_hash(
+            record.bus_id,
+            &record.urb_id,
+            &record.urb_type,
+            record.urb_ts_sec,
+            record.urb_ts_usec,
+            &record.endpoint_address,
+            &record.payload_hex,
+        );
+        record.packet_hash = match record.iso_segment_index {
+            Some(index) => fnv1a_hex(&[&hash, &index.to_string(), &record.payload_hex]),
+            None => hash,
+        };
+    }
+}
+
+/// Replaces values with `<prefix><n>`, numbering distinct values in order of first appearance
+struct Pseudonyms {
+    prefix: &'static str,
+    assigned: HashMap<String, String>,
+}
+
+impl Pseudonyms {
+    fn new(prefix: &'static str) -> Self {
+        Self { prefix, assigned: HashMap::new() }
+    }
+
+    fn get(&mut self, value: &str) -> String {
+        let next = self.assigned.len() + 1;
+        self.assigned
+            .entry(value.to_string())
+            .or_insert_with(|| format!("{}{}", self.prefix, next))
+            .clone()
+    }
+}
+
+fn urb_timestamp_us(record: &UsbPacketRecord) -> u64 {
+    record.urb_ts_sec * 1_000_000 + record.urb_ts_usec as u64
+}
+
+/// Stable content hash of a packet, independent of session and conversion time.
+/// Uses FNV-1a so the value is identical across runs, platforms and toolchains.
+fn packet_hash(
+    bus_id: u8,
+    urb_id: &str,
+    urb_type: &str,
+    urb_ts_sec: u64,
+    urb_ts_usec: u32,
+    endpoint_address: &str,
+    payload_hex: &str,
+) -> String {
+    let ts_sec = urb_ts_sec.to_string();
+    let ts_usec = urb_ts_usec.to_string();
+    fnv1a_hex(&[&bus_id.to_string(), urb_id, urb_type, &ts_sec, &ts_usec, endpoint_address, payload_hex])
+}
+
+/// FNV-1a over NUL-separated parts, formatted as 16 hex digits
+pub(crate) fn fnv1a_hex(parts: &[&str]) -> String {
+    const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    let mut hash = FNV_OFFSET;
+    for part in parts {
+        for byte in part.bytes().chain(std::iter::once(0)) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+    format!("{:016x}", hash)
+}
+
+/// Normalize a tshark bytes field to plain hex and decode it
+fn decode_hex_payload(raw: &str) -> Result<(String, Vec<u8>)> {
+    // Some tshark versions join several values with spaces or commas, optionally 0x-prefixed
+    let clean_hex: String = raw
+        .split(|c: char| c == ':' || c == ',' || c.is_whitespace())
+        .map(|block| block.strip_prefix("0x").or_else(|| block.strip_prefix("0X")).unwrap_or(block))
+        .collect();
+
+    // Convert hex to bytes (handle empty payloads)
+    let bytes = if clean_hex.is_empty() {
+        Vec::new()
+    } else {
+        hex::decode(&clean_hex).map_err(|source| ConverterError::HexDecode { payload: clean_hex.clone(), source })?
+    };
+    Ok((clean_hex, bytes))
+}
+
+/// tshark's `usb.transfer_type` value for isochronous transfers
+const TRANSFER_TYPE_ISOCHRONOUS: &str = "0x00";
+
+/// tshark's `usb.transfer_type` value for bulk transfers, which carry the KM003C protocol
+pub(crate) const TRANSFER_TYPE_BULK: &str = "0x03";
+
+/// tshark's `usb.transfer_type` value for control transfers
+const TRANSFER_TYPE_CONTROL: &str = "0x02";
+
+/// tshark's `usb.transfer_type` value for interrupt transfers
+const TRANSFER_TYPE_INTERRUPT: &str = "0x01";
+
+/// Payload of an interrupt transfer whose data a class dissector (HID, CDC) claimed, so tshark
+/// emits no `usb.capdata`: the raw HID report, or else the last `data_len` bytes of the frame
+fn interrupt_payload_hex<P: PacketSource>(packet: &P, data_len: u32) -> Option<String> {
+    if let Some(report) = packet.layer("usbhid").and_then(|hid| hid.get("usbhid.data")) {
+        return Some(report.to_string());
+    }
+    let frame = packet.raw_bytes().filter(|_| data_len > 0)?;
+    Some(hex::encode(frame.get(frame.len().checked_sub(data_len as usize)?..)?))
+}
+
+/// CDC ACM SERIAL_STATE notification header: bmRequestType 0xA1, bNotification 0x20
+const CDC_SERIAL_STATE: [u8; 2] = [0xA1, 0x20];
+
+/// UART state bitmap (DCD, DSR, break, ring, framing/parity/overrun) of a CDC SERIAL_STATE
+/// notification pushed on the interrupt IN endpoint; `None` for any other payload
+fn decode_interrupt_status(payload: &[u8]) -> Option<u32> {
+    if payload.get(..2)? != CDC_SERIAL_STATE || payload.get(6..8)? != [2, 0] {
+        return None;
+    }
+    Some(u16::from_le_bytes(payload.get(8..10)?.try_into().ok()?).into())
+}
+
+/// `packet_category`: `setup` for control setup packets, `control` for other control
+/// traffic with data, `status` for anything without payload (submits, completions,
+/// status stages) and `data` for payload-carrying bulk/interrupt/ISO transfers
+fn packet_category(transfer_type: &str, has_setup: bool, payload_len: usize) -> &'static str {
+    match (transfer_type == TRANSFER_TYPE_CONTROL, has_setup, payload_len) {
+        (true, true, _) => "setup",
+        (_, _, 0) => "status",
+        (true, false, _) => "control",
+        (false, _, _) => "data",
+    }
+}
+
+/// Flag KM003C transaction id jumps per direction and return the frame numbers where gaps occur.
+///
+/// Ids are an 8-bit rolling counter, so each header-bearing row should carry the previous
+/// id plus one (mod 256); a repeated id is not treated as a gap. Rows without a decoded
+/// header keep `transaction_gap = None`.
+pub fn mark_transaction_gaps(records: &mut [UsbPacketRecord]) -> Vec<u32> {
+    let mut last_ids: HashMap<String, u8> = HashMap::new();
+    let mut gap_frames = Vec::new();
+    for record in records.iter_mut() {
+        let Some(id) = record.km_transaction_id else {
+            continue;
+        };
+        let gap = last_ids
+            .insert(record.direction.clone(), id)
+            .is_some_and(|last| id != last && id != last.wrapping_add(1));
+        if gap {
+            gap_frames.push(record.frame_number);
+        }
+        record.transaction_gap = Some(gap);
+    }
+    gap_frames
+}
+
+/// Split an isochronous URB into one record per ISO packet descriptor.
+///
+/// Each `usb.iso.data` segment becomes its own row carrying the segment index, status
+/// and length; all other fields are copied from the URB record. Non-ISO packets, and
+/// ISO packets without data segments, are returned unchanged as a single record.
+pub fn split_iso_segments<P: PacketSource>(packet: &P, record: UsbPacketRecord) -> Result<Vec<UsbPacketRecord>> {
+    let usb_layer = match packet.layer("usb") {
+        Some(layer) if record.transfer_type == TRANSFER_TYPE_ISOCHRONOUS => layer,
+        _ => return Ok(vec![record]),
+    };
+
+    let data = usb_layer.get_all("usb.iso.data");
+    if data.is_empty() {
+        return Ok(vec![record]);
+    }
+    let statuses = usb_layer.get_all("usb.iso.iso_status");
+    let lengths = usb_layer.get_all("usb.iso.iso_len");
+
+    let mut segments = Vec::with_capacity(data.len());
+    for (index, raw) in data.iter().enumerate() {
+        let (segment_hex, segment_bytes) = decode_hex_payload(raw)?;
+        let index_str = index.to_string();
+        let mut segment = record.clone();
+        segment.packet_hash = fnv1a_hex(&[&record.packet_hash, &index_str, &segment_hex]);
+        segment.payload_hex = segment_hex;
+        segment.iso_segment_index = Some(index as u32);
+        segment.iso_segment_status = statuses.get(index).map(|s| s.to_string());
+        segment.iso_segment_length = lengths
+            .get(index)
+            .and_then(|l| l.parse().ok())
+            .or(Some(segment_bytes.len() as u32));
+        segments.push(segment);
+    }
+    Ok(segments)
+}
+
+/// One USB packet from the capture, flattened into the output row layout
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsbPacketRecord {
+    pub session_id: String,
+    pub frame_number: u32,
+    pub timestamp: f64,
+    pub timestamp_absolute: String,
+    // Wall-clock capture time in microseconds since the Unix epoch, written as Datetime(us, UTC)
+    pub timestamp_utc: Option<i64>,
+    pub direction: String,
+    pub device_address: u8,
+    pub bus_id: u8,
+    pub endpoint_address: String,
+    pub endpoint_number: u8,
+    // Normalized categories; the tshark strings they came from are kept in the *_raw fields
+    pub transfer_type: String,
+    pub urb_type: String,
+    pub urb_status: String,
+    pub transfer_type_raw: String,
+    pub urb_type_raw: String,
+    pub urb_status_raw: String,
+    pub data_length: u32,
+    pub urb_length: u32,
+    pub payload_hex: String,
+    // Whether --max-payload cut payload_hex short (None without the flag)
+    pub payload_truncated_by_flag: Option<bool>,
+    // Additional USB metadata
+    pub setup_flag: String,
+    pub data_flag: String,
+    /// data, control, setup or status; see `packet_category`
+    pub packet_category: String,
+    pub interval: u32,
+    pub start_frame: u32,
+    // Frame-level metadata
+    pub frame_length: u32,
+    pub frame_protocols: String,
+    pub frame_comment: Option<String>,
+    pub source_file: String,
+    // USB Control packet fields (for setup packets)
+    pub bmrequest_type: Option<String>,
+    pub brequest: Option<String>,
+    pub brequest_name: Option<String>,
+    /// Name of a known KM003C vendor control request
+    pub vendor_command: Option<String>,
+    pub wvalue: Option<u32>,
+    pub windex: Option<u32>,
+    pub wlength: Option<u32>,
+    pub descriptor_type: Option<String>,
+    pub descriptor_index: Option<u32>,
+    pub language_id: Option<u32>,
+    // Decoded bmRequestType bitfield
+    pub setup_direction: Option<String>,
+    pub setup_req_type: Option<String>,
+    pub setup_recipient: Option<String>,
+    // Device descriptor identification (only present on GET_DESCRIPTOR responses)
+    pub id_vendor: Option<u16>,
+    pub id_product: Option<u16>,
+    pub device_product_name: Option<String>,
+    // USB Transfer flags (detailed USB metadata)
+    pub transfer_flags: Option<String>,
+    pub copy_of_transfer_flags: Option<String>,
+    // Additional USB identifiers and timing
+    pub urb_id: String,
+    pub usb_src: String,
+    pub usb_dst: String,
+    pub usb_addr: String,
+    pub urb_ts_sec: u64,
+    pub urb_ts_usec: u32,
+    pub packet_hash: String,
+    /// Same URB identity and payload as a row just before it: usbmon logged it twice
+    pub is_retransmit: bool,
+    // Isochronous packet descriptor (one row per segment for ISO transfers)
+    pub iso_segment_index: Option<u32>,
+    pub iso_segment_status: Option<String>,
+    pub iso_segment_length: Option<u32>,
+    // Reassembled frame bytes (only populated with --include-raw)
+    pub frame_raw_hex: Option<String>,
+    // Submit/complete pairing (only populated with --coalesce-urbs)
+    pub submit_ts: Option<f64>,
+    pub complete_ts: Option<f64>,
+    pub urb_coalesced: Option<bool>,
+    /// UART state bitmap from a CDC SERIAL_STATE notification (interrupt transfers only)
+    pub status_flags: Option<u32>,
+    // KM003C main header (bulk payloads only)
+    pub km_packet_type: Option<u8>,
+    pub km_transaction_id: Option<u8>,
+    pub km_attribute: Option<u16>,
+    /// Length after the main header declared by the PutData word count or an extended header
+    pub km_declared_len: Option<u32>,
+    /// Bytes actually captured after the main header
+    pub km_body_len: Option<u32>,
+    /// Whether the body is exactly the declared length; null when nothing is declared
+    pub km_length_ok: Option<bool>,
+    /// Names of the attribute bits set in a control packet, or in a PutData's logical packets
+    pub km_attribute_name: Option<String>,
+    /// Attributes of the logical packets in a PutData payload, OR-ed together
+    pub km_logical_attributes: Option<u16>,
+    /// Summed body length of a PutData's logical packets, measured before `--max-payload`
+    pub km_logical_body_len: Option<u32>,
+    // Lifecycle stage from header packet-type transitions (set by Converter::records)
+    pub session_phase: Option<String>,
+    // Transaction id continuity (only populated with --check-transactions)
+    pub transaction_gap: Option<bool>,
+    // CRC check of checksummed KM003C blocks (Settings); None when nothing is checksummed
+    pub checksum_valid: Option<bool>,
+    // Main ADC measurement (only populated for KM003C ADC responses)
+    pub adc_vbus_v: Option<f64>,
+    pub adc_ibus_a: Option<f64>,
+    pub adc_power_w: Option<f64>,
+    pub adc_temp_c: Option<f64>,
+    // "adc" for a full sample, "temperature" for a temperature-only packet
+    pub adc_packet_kind: Option<String>,
+    // Type-C CC state (only populated for KM003C connection events)
+    pub cc_orientation: Option<String>,
+    pub cc_attached: Option<bool>,
+    pub cc_advertised_current: Option<String>,
+    // Last PD Request RDO in a PdPacket event stream (currents in A)
+    pub pd_rdo_object_position: Option<u8>,
+    pub pd_rdo_op_current: Option<f64>,
+    pub pd_rdo_max_current: Option<f64>,
+    pub pd_rdo_give_back: Option<bool>,
+    pub pd_rdo_capability_mismatch: Option<bool>,
+    pub pd_rdo_usb_comm_capable: Option<bool>,
+    // Any PD message in the stream is extended / BIST, i.e. carries no power data objects
+    pub pd_is_extended: Option<bool>,
+    pub pd_is_bist: Option<bool>,
+    // Device configuration from Settings responses
+    pub device_sample_rate_hz: Option<f64>,
+    // Data groups of a PutData body, `name:length` in order
+    pub km_group_count: Option<u32>,
+    pub km_groups: Option<String>,
+    pub added_datetime: String,
+}
+
+impl UsbPacketRecord {
+    /// Cut `payload_hex` to its first `max_bytes` bytes (`--max-payload`).
+    ///
+    /// Runs after decoding, so header and measurement columns reflect the full payload;
+    /// `data_length` keeps the captured length.
+    pub fn truncate_payload(&mut self, max_bytes: usize) {
+        let truncated = self.payload_hex.len() > max_bytes * 2;
+        if truncated {
+            self.payload_hex.truncate(max_bytes * 2);
+        }
+        self.payload_truncated_by_flag = Some(truncated);
+    }
+}
+
+/// Typed view of a record's KM003C payload; [`Decoded::Unknown`] for non-protocol packets.
+///
+/// Decodes `payload_hex` again, so a payload cut by `--max-payload` may no longer match.
+pub fn decode(record: &UsbPacketRecord) -> km003c::Decoded {
+    if record.km_packet_type.is_none() {
+        return km003c::Decoded::Unknown;
+    }
+    match hex::decode(&record.payload_hex) {
+        Ok(payload) => km003c::decode_payload(&payload),
+        Err(_) => km003c::Decoded::Unknown,
+    }
+}
+
+/// Built-in payload decoders applied to every KM003C bulk payload
+static DECODERS: std::sync::LazyLock<DecoderRegistry> = std::sync::LazyLock::new(DecoderRegistry::default);
+
+/// Convert a dissected tshark packet into a [`UsbPacketRecord`]
+pub fn process_packet<P: PacketSource>(packet: &P, session_id: &str, verbose: bool) -> Result<UsbPacketRecord> {
+    // Extract frame-level information
+    let frame_layer = packet.layer("frame").ok_or(ConverterError::MissingLayer("frame"))?;
+    
+    let frame_num = frame_layer
+        .get("frame.number")
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(0);
+
+    let timestamp = frame_layer
+        .get("frame.time_relative")
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(0.0);
+
+    let timestamp_absolute = frame_layer
+        .get("frame.time")
+        .map(|t| t.to_string())
+        .unwrap_or_else(|| "Unknown".to_string());
+
+    let timestamp_utc = frame_layer.get("frame.time_epoch").and_then(parse_epoch_micros);
+
+    let frame_length: u32 = frame_layer
+        .get("frame.len")
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(0);
+
+    let frame_protocols = frame_layer
+        .get("frame.protocols")
+        .map(|p| p.to_string())
+        .unwrap_or_else(|| "Unknown".to_string());
+
+    // pcapng annotations; older tshark reports them in a separate pkt_comment layer
+    let frame_comment = frame_layer
+        .get("frame.comment")
+        .or_else(|| packet.layer("pkt_comment").and_then(|l| l.get("frame.comment")))
+        .map(|c| c.to_string());
+
+    // Extract USB layer information
+    let usb_layer = FieldMap::new(packet.layer("usb").ok_or(ConverterError::MissingLayer("USB"))?, verbose);
+    
+    let direction = match usb_layer.get("usb.endpoint_address.direction") {
+        Some("0") => "H->D".to_string(),
+        Some("1") => "D->H".to_string(),
+        _ => "Unknown".to_string(),
+    };
+
+    let device_address: u8 = usb_layer
+        .get("usb.device_address")
+        .and_then(|d| d.parse().ok())
+        .unwrap_or(0);
+
+    let bus_id: u8 = usb_layer
+        .get("usb.bus_id")
+        .and_then(|b| b.parse().ok())
+        .unwrap_or(0);
+
+    let endpoint_address = usb_layer
+        .get("usb.endpoint_address")
+        .map(|e| e.to_string())
+        .unwrap_or_else(|| "Unknown".to_string());
+
+    let endpoint_number: u8 = usb_layer
+        .get("usb.endpoint_address.number")
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(0);
+
+    let transfer_type_raw = usb_layer
+        .get("usb.transfer_type")
+        .map(|t| t.to_string())
+        .unwrap_or_else(|| "Unknown".to_string());
+    let transfer_type = normalize_transfer_type(&transfer_type_raw);
+
+    let urb_type_raw = usb_layer
+        .get("usb.urb_type")
+        .map(|t| t.to_string())
+        .unwrap_or_else(|| "Unknown".to_string());
+    let urb_type = normalize_urb_type(&urb_type_raw);
+
+    let urb_status_raw = usb_layer
+        .get("usb.urb_status")
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| "Unknown".to_string());
+    let urb_status = normalize_urb_status(&urb_status_raw);
+
+    let data_length: u32 = usb_layer
+        .get("usb.data_len")
+        .and_then(|d| d.parse().ok())
+        .unwrap_or(0);
+
+    let urb_length: u32 = usb_layer
+        .get("usb.urb_len")
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(0);
+
+    let setup_flag = usb_layer
+        .get("usb.setup_flag")
+        .map(clean_tshark_field)
+        .unwrap_or_else(|| "Unknown".to_string());
+
+    let data_flag = usb_layer
+        .get("usb.data_flag")
+        .map(clean_tshark_field)
+        .unwrap_or_else(|| "Unknown".to_string());
+
+    let interval: u32 = usb_layer
+        .get("usb.interval")
+        .and_then(|i| i.parse().ok())
+        .unwrap_or(0);
+
+    let start_frame: u32 = usb_layer
+        .get("usb.start_frame")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(0);
+
+    // Extract hex payload (might be empty for control packets)
+    let payload_hex = match usb_layer.get("usb.capdata") {
+        Some(capdata) => capdata.to_string(),
+        None if transfer_type == TRANSFER_TYPE_INTERRUPT => interrupt_payload_hex(packet, data_length).unwrap_or_default(),
+        None => String::new(),
+    };
+    let (clean_hex, payload_bytes) = decode_hex_payload(&payload_hex)?;
+    let status_flags = Some(&payload_bytes)
+        .filter(|_| transfer_type == TRANSFER_TYPE_INTERRUPT)
+        .and_then(|bytes| decode_interrupt_status(bytes));
+
+    // Extract USB Control packet fields (only present in control transfers)
+    let bmrequest_type = usb_layer.get("usb.bmRequestType").map(|b| b.to_string());
+    let setup_fields = bmrequest_type.as_deref().and_then(decode_bmrequest_type);
+    let brequest = usb_layer.get("usb.setup.bRequest").map(|b| b.to_string());
+    let brequest_name = usb_layer.get("usb.setup.bRequest.name").map(|b| b.to_string());
+    let wvalue = usb_layer.get("usb.setup.wValue").and_then(|w| w.parse().ok());
+    let windex = usb_layer.get("usb.setup.wIndex").and_then(|w| w.parse().ok());
+    let vendor_command = bmrequest_type
+        .as_deref()
+        .zip(brequest.as_deref())
+        .and_then(|(request_type, request)| vendor_command(request_type, request, wvalue))
+        .map(str::to_string);
+    let wlength = usb_layer.get("usb.setup.wLength").and_then(|w| w.parse().ok());
+    let descriptor_type = usb_layer.get("usb.bDescriptorType").map(|d| d.to_string());
+    let descriptor_index = usb_layer.get("usb.setup.wValue.descriptor_index").and_then(|d| d.parse().ok());
+    let language_id = usb_layer.get("usb.setup.wValue.language_id").and_then(|l| l.parse().ok());
+
+    // Device descriptor VID/PID, from tshark's dissection or the raw descriptor body
+    let (id_vendor, id_product) = match (
+        usb_layer.get("usb.idVendor").and_then(parse_tshark_u16),
+        usb_layer.get("usb.idProduct").and_then(parse_tshark_u16),
+    ) {
+        (Some(vid), Some(pid)) => (Some(vid), Some(pid)),
+        _ => parse_device_descriptor_ids(&payload_bytes).unzip(),
+    };
+    
+    // Extract USB transfer flags
+    let transfer_flags = usb_layer.get("usb.transfer_flags").map(|t| t.to_string());
+    let copy_of_transfer_flags = usb_layer.get("usb.copy_of_transfer_flags").map(|c| c.to_string());
+    
+    // Extract additional USB identifiers and timing
+    let urb_id = usb_layer.get("usb.urb_id").map(|u| u.to_string()).unwrap_or_else(|| "Unknown".to_string());
+    let usb_src = usb_layer.get("usb.src").map(|s| s.to_string()).unwrap_or_else(|| "Unknown".to_string());
+    let usb_dst = usb_layer.get("usb.dst").map(|d| d.to_string()).unwrap_or_else(|| "Unknown".to_string());
+    let usb_addr = usb_layer.get("usb.addr").map(|a| a.to_string()).unwrap_or_else(|| "Unknown".to_string());
+    let urb_ts_sec = usb_layer.get("usb.urb_ts_sec").and_then(|t| t.parse().ok()).unwrap_or(0);
+    let urb_ts_usec = usb_layer.get("usb.urb_ts_usec").and_then(|t| t.parse().ok()).unwrap_or(0);
+
+    let packet_hash = packet_hash(bus_id, &urb_id, &urb_type, urb_ts_sec, urb_ts_usec, &endpoint_address, &clean_hex);
+    let km_header = Some(&payload_bytes)
+        .filter(|_| transfer_type == TRANSFER_TYPE_BULK)
+        .and_then(|bytes| km003c::parse_header(bytes));
+    let km_declared_len = km_header.and_then(|_| km003c::declared_payload_len(&payload_bytes));
+    let km_body_len = km_header.map(|_| payload_bytes.len() - 4);
+    // usbmon marks a present setup packet with flag 0; tshark also dissects its bmRequestType
+    let has_setup = bmrequest_type.is_some() || matches!(setup_flag.as_str(), "0" | "0x00");
+    let packet_category = packet_category(&transfer_type, has_setup, payload_bytes.len()).to_string();
+    let decoded = match km_header {
+        Some(_) => DECODERS.decode(&payload_bytes),
+        None => DecodedFields::default(),
+    };
+
+    if verbose {
+        debug!(
+            "Frame {}: {} bytes {} @ {:.6}s [{}:{}]",
+            frame_num, payload_bytes.len(), direction, timestamp, bus_id, endpoint_number
+        );
+        if decoded.checksum_valid == Some(false) {
+            debug!("Frame {}: checksum mismatch in payload {}", frame_num, clean_hex);
+        }
+    }
+
+    let record = UsbPacketRecord {
+        session_id: session_id.to_string(),
+        frame_number: frame_num,
+        timestamp,
+        timestamp_absolute,
+        timestamp_utc,
+        direction,
+        device_address,
+        bus_id,
+        endpoint_address,
+        endpoint_number,
+        transfer_type,
+        urb_type,
+        urb_status,
+        transfer_type_raw,
+        urb_type_raw,
+        urb_status_raw,
+        data_length,
+        urb_length,
+        payload_hex: clean_hex.clone(),
+        payload_truncated_by_flag: None,
+        setup_flag,
+        data_flag,
+        packet_category,
+        interval,
+        start_frame,
+        frame_length,
+        frame_protocols,
+        frame_comment,
+        source_file: session_id.to_string(), // Use session_id as source file identifier
+        bmrequest_type,
+        brequest,
+        brequest_name,
+        vendor_command,
+        wvalue,
+        windex,
+        wlength,
+        descriptor_type,
+        descriptor_index,
+        language_id,
+        setup_direction: setup_fields.map(|(d, _, _)| d.to_string()),
+        setup_req_type: setup_fields.map(|(_, t, _)| t.to_string()),
+        setup_recipient: setup_fields.map(|(_, _, r)| r.to_string()),
+        id_vendor,
+        id_product,
+        device_product_name: None,
+        transfer_flags,
+        copy_of_transfer_flags,
+        urb_id,
+        usb_src,
+        usb_dst,
+        usb_addr,
+        urb_ts_sec,
+        urb_ts_usec,
+        packet_hash,
+        is_retransmit: false,
+        iso_segment_index: None,
+        iso_segment_status: None,
+        iso_segment_length: None,
+        frame_raw_hex: None,
+        submit_ts: None,
+        complete_ts: None,
+        urb_coalesced: None,
+        status_flags,
+        km_packet_type: km_header.map(|h| h.packet_type),
+        km_transaction_id: km_header.map(|h| h.transaction_id),
+        km_attribute: km_header.map(|h| h.attribute),
+        km_declared_len: km_declared_len.map(|len| len as u32),
+        km_body_len: km_body_len.map(|len| len as u32),
+        km_length_ok: km_declared_len.zip(km_body_len).map(|(declared, body)| declared == body),
+        km_attribute_name: None,
+        km_logical_attributes: km_header.and_then(|_| km003c::logical_attributes(&payload_bytes)),
+        km_logical_body_len: km_header.and_then(|_| km003c::logical_body_len(&payload_bytes)).map(|len| len as u32),
+        session_phase: None,
+        transaction_gap: None,
+        checksum_valid: decoded.checksum_valid,
+        adc_vbus_v: decoded.adc_vbus_v,
+        adc_ibus_a: decoded.adc_ibus_a,
+        adc_power_w: decoded.adc_power_w,
+        adc_temp_c: decoded.adc_temp_c,
+        adc_packet_kind: decoded.adc_packet_kind,
+        cc_orientation: decoded.cc_orientation,
+        cc_attached: decoded.cc_attached,
+        cc_advertised_current: decoded.cc_advertised_current,
+        pd_rdo_object_position: decoded.pd_rdo_object_position,
+        pd_rdo_op_current: decoded.pd_rdo_op_current,
+        pd_rdo_max_current: decoded.pd_rdo_max_current,
+        pd_rdo_give_back: decoded.pd_rdo_give_back,
+        pd_rdo_capability_mismatch: decoded.pd_rdo_capability_mismatch,
+        pd_rdo_usb_comm_capable: decoded.pd_rdo_usb_comm_capable,
+        pd_is_extended: decoded.pd_is_extended,
+        pd_is_bist: decoded.pd_is_bist,
+        device_sample_rate_hz: decoded.device_sample_rate_hz,
+        km_group_count: decoded.km_group_count,
+        km_groups: decoded.km_groups,
+        added_datetime: chrono::Utc::now().to_rfc3339(),
+    };
+
+    Ok(record)
+}
+
+/// Build the output DataFrame from converted records
+pub fn create_dataframe(records: Vec<UsbPacketRecord>) -> Result<DataFrame> {
+    let columns = COLUMNS.iter().map(|column| (column.build)(&records)).collect();
+    let mut df = DataFrame::new(columns)?;
+
+    let timestamp_utc = df
+        .column("timestamp_utc")?
+        .cast(&DataType::Datetime(TimeUnit::Microseconds, Some(TimeZone::UTC)))?;
+    df.with_column(timestamp_utc)?;
+
+    // Store the normalized categories as Categorical for compact files and clean group_bys
+    for name in CATEGORICAL_COLUMNS {
+        let categorical = df.column(name)?.cast(&DataType::from_categories(Categories::global()))?;
+        df.with_column(categorical)?;
+    }
+
+    with_endpoint_deltas(df)
+}
+
+/// Fill `delta_prev_us`: the gap to the previous packet, by timestamp, on the same endpoint and
+/// direction, leaving the first packet of each group null. Row order is kept.
+fn with_endpoint_deltas(df: DataFrame) -> Result<DataFrame> {
+    let delta = (col("timestamp") - col("timestamp").shift(lit(1))) * lit(1e6);
+    let mut df = df
+        .lazy()
+        .with_row_index("__row", None)
+        .sort(["timestamp"], SortMultipleOptions::default().with_maintain_order(true))
+        .with_column(delta.over([col("endpoint_address"), col("direction")]).alias("delta_prev_us"))
+        .sort(["__row"], SortMultipleOptions::default())
+        .collect()?;
+    df.drop_in_place("__row")?;
+    Ok(df)
+}
+
+/// Columns written with the Polars Categorical dtype
+pub(crate) const CATEGORICAL_COLUMNS: [&str; 4] = ["transfer_type", "urb_type", "urb_status", "packet_category"];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{adc_put_data, layer, packet};
+    use std::collections::HashMap;
+
+    #[test]
+    fn maps_endpoint_direction() {
+        let out = process_packet(&packet(&[("usb.endpoint_address.direction", "0")]), "s", false).unwrap();
+        assert_eq!(out.direction, "H->D");
+        let inbound = process_packet(&packet(&[("usb.endpoint_address.direction", "1")]), "s", false).unwrap();
+        assert_eq!(inbound.direction, "D->H");
+        let unknown = process_packet(&packet(&[]), "s", false).unwrap();
+        assert_eq!(unknown.direction, "Unknown");
+    }
+
+    #[test]
+    fn reads_frame_fields() {
+        let record = process_packet(&packet(&[]), "session", false).unwrap();
+        assert_eq!(record.frame_number, 42);
+        assert_eq!(record.timestamp, 1.5);
+        assert_eq!(record.frame_length, 96);
+        assert_eq!(record.session_id, "session");
+        assert_eq!(record.frame_comment, None);
+    }
+
+    #[test]
+    fn parses_epoch_into_utc_datetime() {
+        assert_eq!(parse_epoch_micros("1700000000.123456789"), Some(1_700_000_000_123_456));
+        assert_eq!(parse_epoch_micros("1700000000.5"), Some(1_700_000_000_500_000));
+        assert_eq!(parse_epoch_micros("1700000000"), Some(1_700_000_000_000_000));
+        assert_eq!(parse_epoch_micros("Jan 1"), None);
+
+        let mut dated = packet(&[]);
+        dated.get_mut("frame").unwrap().insert("frame.time_epoch".into(), "1700000000.000001000".into());
+        let df = create_dataframe(vec![process_packet(&dated, "s", false).unwrap()]).unwrap();
+        let column = df.column("timestamp_utc").unwrap();
+        assert_eq!(column.dtype(), &DataType::Datetime(TimeUnit::Microseconds, Some(TimeZone::UTC)));
+        assert_eq!(column.get(0).unwrap().extract::<i64>(), Some(1_700_000_000_000_001));
+    }
+
+    #[test]
+    fn keeps_frame_comments() {
+        let mut annotated = packet(&[]);
+        annotated.get_mut("frame").unwrap().insert("frame.comment".into(), "GetData sent here".into());
+        assert_eq!(process_packet(&annotated, "s", false).unwrap().frame_comment.as_deref(), Some("GetData sent here"));
+
+        let mut legacy = packet(&[]);
+        legacy.insert("pkt_comment".into(), layer(&[("frame.comment", "old tshark")]));
+        assert_eq!(process_packet(&legacy, "s", false).unwrap().frame_comment.as_deref(), Some("old tshark"));
+    }
+
+    #[test]
+    fn empty_payload_for_control_packets() {
+        let record = process_packet(&packet(&[("usb.transfer_type", "0x02")]), "s", false).unwrap();
+        assert_eq!(record.payload_hex, "");
+    }
+
+    #[test]
+    fn strips_colons_from_capdata() {
+        let record = process_packet(&packet(&[("usb.capdata", "0c:01:02:00")]), "s", false).unwrap();
+        assert_eq!(record.payload_hex, "0c010200");
+    }
+
+    #[test]
+    fn strips_spaces_from_capdata() {
+        let record = process_packet(&packet(&[("usb.capdata", "0c01 0200\t41")]), "s", false).unwrap();
+        assert_eq!(record.payload_hex, "0c01020041");
+    }
+
+    #[test]
+    fn strips_commas_from_capdata() {
+        let record = process_packet(&packet(&[("usb.capdata", "0c:01,02:00, 41")]), "s", false).unwrap();
+        assert_eq!(record.payload_hex, "0c01020041");
+    }
+
+    #[test]
+    fn reads_interrupt_payloads_without_capdata_and_decodes_serial_state() {
+        let serial_state = process_packet(&packet(&[("usb.transfer_type", "0x01"), ("usb.capdata", "a1:20:00:00:01:00:02:00:03:00")]), "s", false).unwrap();
+        assert_eq!(serial_state.status_flags, Some(0x03));
+
+        let mut hid = packet(&[("usb.transfer_type", "0x01"), ("usb.data_len", "4")]);
+        hid.insert("usbhid".to_string(), layer(&[("usbhid.data", "0c:05:22:00")]));
+        let hid = process_packet(&hid, "s", false).unwrap();
+        assert_eq!((hid.payload_hex.as_str(), hid.status_flags), ("0c052200", None));
+
+        let bulk = process_packet(&packet(&[("usb.transfer_type", "0x03"), ("usb.capdata", "a1:20:00:00:01:00:02:00:03:00")]), "s", false).unwrap();
+        assert_eq!(bulk.status_flags, None);
+    }
+
+    #[test]
+    fn strips_0x_prefixes_from_capdata() {
+        let record = process_packet(&packet(&[("usb.capdata", "0x0c01,0X0200 0x41")]), "s", false).unwrap();
+        assert_eq!(record.payload_hex, "0c01020041");
+    }
+
+    #[test]
+    fn rejects_invalid_hex_payload() {
+        assert!(process_packet(&packet(&[("usb.capdata", "zz")]), "s", false).is_err());
+    }
+
+    #[test]
+    fn missing_layers_are_errors() {
+        let mut no_usb = packet(&[]);
+        no_usb.remove("usb");
+        let err = process_packet(&no_usb, "s", false).unwrap_err();
+        assert_eq!(err.to_string(), "Missing USB layer");
+
+        let mut no_frame = packet(&[]);
+        no_frame.remove("frame");
+        let err = process_packet(&no_frame, "s", false).unwrap_err();
+        assert_eq!(err.to_string(), "Missing frame layer");
+    }
+
+    /// Layer that keeps repeated fields, like tshark does for ISO descriptors
+    struct FieldList(Vec<(String, String)>);
+
+    impl MetadataSource for FieldList {
+        fn get(&self, key: &str) -> Option<&str> {
+            self.get_all(key).into_iter().next()
+        }
+
+        fn get_all(&self, key: &str) -> Vec<&str> {
+            self.0.iter().filter(|(k, _)| k == key).map(|(_, v)| v.as_str()).collect()
+        }
+    }
+
+    impl PacketSource for HashMap<String, FieldList> {
+        type Layer = FieldList;
+
+        fn layer(&self, name: &str) -> Option<&FieldList> {
+            HashMap::get(self, name)
+        }
+    }
+
+    #[test]
+    fn splits_iso_transfers_into_segments() {
+        let fields = [
+            ("usb.transfer_type", "0x00"),
+            ("usb.iso.iso_status", "0"),
+            ("usb.iso.iso_len", "2"),
+            ("usb.iso.data", "01:02"),
+            ("usb.iso.iso_status", "-18"),
+            ("usb.iso.iso_len", "1"),
+            ("usb.iso.data", "03"),
+        ];
+        let mut iso = HashMap::new();
+        iso.insert("frame".to_string(), FieldList(vec![]));
+        iso.insert(
+            "usb".to_string(),
+            FieldList(fields.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()),
+        );
+
+        let record = process_packet(&iso, "s", false).unwrap();
+        let segments = split_iso_segments(&iso, record).unwrap();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].payload_hex, "0102");
+        assert_eq!(segments[0].iso_segment_index, Some(0));
+        assert_eq!(segments[1].payload_hex, "03");
+        assert_eq!(segments[1].iso_segment_status.as_deref(), Some("-18"));
+        assert_eq!(segments[1].iso_segment_length, Some(1));
+        assert_ne!(segments[0].packet_hash, segments[1].packet_hash);
+    }
+
+    #[test]
+    fn non_iso_packets_are_not_split() {
+        let bulk = packet(&[("usb.transfer_type", "0x03"), ("usb.capdata", "0102")]);
+        let record = process_packet(&bulk, "s", false).unwrap();
+        let records = split_iso_segments(&bulk, record).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].iso_segment_index, None);
+    }
+
+    #[test]
+    fn anonymize_rebases_time_and_hashes_host_fields() {
+        let mut records = vec![
+            process_packet(&packet(&[("usb.urb_id", "0xffff8881"), ("usb.urb_ts_sec", "1700000000"), ("usb.urb_ts_usec", "900000"), ("usb.capdata", "0c")]), "s", false).unwrap(),
+            process_packet(&packet(&[("usb.urb_id", "0xffff8882"), ("usb.urb_ts_sec", "1700000001"), ("usb.urb_ts_usec", "100000"), ("usb.capdata", "41")]), "s", false).unwrap(),
+        ];
+        records[1].timestamp = 1.7;
+        anonymize_records(&mut records);
+
+        assert_eq!(records[0].timestamp, 0.0);
+        assert!((records[1].timestamp - 0.2).abs() < 1e-9);
+        assert_eq!((records[0].urb_ts_sec, records[0].urb_ts_usec), (0, 0));
+        assert_eq!((records[1].urb_ts_sec, records[1].urb_ts_usec), (0, 200_000));
+        assert!(records[0].added_datetime.is_empty());
+        assert_ne!(records[0].urb_id, "0xffff8881");
+        assert_ne!(records[0].urb_id, records[1].urb_id);
+        assert_eq!(records[1].payload_hex, "41");
+    }
+
+    #[test]
+    fn anonymize_uses_pseudonyms_and_recomputes_packet_hash() {
+        let mut records = vec![
+            process_packet(&packet(&[("usb.urb_id", "0xffff8881"), ("usb.src", "host"), ("usb.dst", "1.16.1"), ("usb.capdata", "0c")]), "s", false).unwrap(),
+            process_packet(&packet(&[("usb.urb_id", "0xffff8881"), ("usb.src", "1.16.1"), ("usb.dst", "host"), ("usb.capdata", "41")]), "s", false).unwrap(),
+        ];
+        let plain_hashes: Vec<String> = records.iter().map(|r| r.packet_hash.clone()).collect();
+        anonymize_records(&mut records);
+
+        assert_eq!((records[0].urb_id.as_str(), records[1].urb_id.as_str()), ("urb1", "urb1"));
+        assert_eq!((records[0].usb_src.as_str(), records[0].usb_dst.as_str()), ("addr1", "addr2"));
+        assert_eq!((records[1].usb_src.as_str(), records[1].usb_dst.as_str()), ("addr2", "addr1"));
+        for (record, plain) in records.iter().zip(&plain_hashes) {
+            assert_ne!(&record.packet_hash, plain);
+        }
+        assert_ne!(records[0].packet_hash, records[1].packet_hash);
+    }
+
+    #[test]
+    fn anonymize_drops_raw_frames_and_comments() {
+        let mut record = process_packet(&packet(&[("usb.urb_id", "0xffff8881"), ("usb.capdata", "0c")]), "s", false).unwrap();
+        record.frame_comment = Some("bench laptop".to_string());
+        record.frame_raw_hex = Some("818888ffff".to_string());
+        let mut records = vec![record];
+        anonymize_records(&mut records);
+        assert_eq!((records[0].frame_comment.as_deref(), records[0].frame_raw_hex.as_deref()), (None, None));
+    }
+
+    #[test]
+    fn decodes_km_header_on_bulk_only() {
+        let bulk = process_packet(&packet(&[("usb.transfer_type", "0x03"), ("usb.capdata", "0c:05:22:00")]), "s", false).unwrap();
+        assert_eq!((bulk.km_packet_type, bulk.km_transaction_id, bulk.km_attribute), (Some(0x0C), Some(5), Some(0x11)));
+        let control = process_packet(&packet(&[("usb.transfer_type", "0x02"), ("usb.capdata", "0c:05:22:00")]), "s", false).unwrap();
+        assert_eq!(control.km_transaction_id, None);
+    }
+
+    #[test]
+    fn compares_declared_and_actual_body_length() {
+        let bulk = |capdata: &str| process_packet(&packet(&[("usb.transfer_type", "0x03"), ("usb.capdata", capdata)]), "s", false).unwrap();
+        // PutData declaring 1 word: (1 + 2) * 4 = 12 body bytes
+        let complete = bulk(&format!("41:01:40:00:{}", ["00"; 12].join(":")));
+        assert_eq!((complete.km_declared_len, complete.km_body_len, complete.km_length_ok), (Some(12), Some(12), Some(true)));
+        let truncated = bulk(&format!("41:01:40:00:{}", ["00"; 8].join(":")));
+        assert_eq!((truncated.km_body_len, truncated.km_length_ok), (Some(8), Some(false)));
+        let plain = bulk("0c:05:22:00");
+        assert_eq!((plain.km_declared_len, plain.km_body_len, plain.km_length_ok), (None, Some(0), None));
+        let raw = process_packet(&packet(&[("usb.capdata", "0c:05")]), "s", false).unwrap();
+        assert_eq!((raw.km_body_len, raw.km_length_ok), (None, None));
+    }
+
+    #[test]
+    fn decode_returns_typed_adc_sample() {
+        // 5.0 V, 1.5 A
+        let adc = adc_put_data(0x0a, 5_000_000, 1_500_000);
+        let record = process_packet(&packet(&[("usb.transfer_type", "0x03"), ("usb.capdata", &adc)]), "s", false).unwrap();
+        let km003c::Decoded::Adc(sample) = decode(&record) else {
+            panic!("expected an ADC sample");
+        };
+        assert!((sample.vbus_v - 5.0).abs() < 1e-9 && (sample.ibus_a - 1.5).abs() < 1e-9);
+
+        let control = process_packet(&packet(&[("usb.transfer_type", "0x02"), ("usb.capdata", &adc)]), "s", false).unwrap();
+        assert_eq!(decode(&control), km003c::Decoded::Unknown);
+    }
+
+    #[test]
+    fn tags_packet_categories() {
+        let category = |fields: &[(&str, &str)]| process_packet(&packet(fields), "s", false).unwrap().packet_category;
+        assert_eq!(category(&[("usb.transfer_type", "0x02"), ("usb.setup_flag", "0"), ("usb.bmRequestType", "0x80")]), "setup");
+        assert_eq!(category(&[("usb.transfer_type", "0x02"), ("usb.setup_flag", "45"), ("usb.capdata", "12:01")]), "control");
+        assert_eq!(category(&[("usb.transfer_type", "0x02"), ("usb.setup_flag", "45")]), "status");
+        assert_eq!(category(&[("usb.transfer_type", "0x03")]), "status");
+        assert_eq!(category(&[("usb.transfer_type", "0x03"), ("usb.capdata", "0c:05:22:00")]), "data");
+    }
+
+    #[test]
+    fn truncates_payload_after_decoding() {
+        let mut record = process_packet(&packet(&[("usb.transfer_type", "0x03"), ("usb.capdata", "0c:05:22:00:ff")]), "s", false).unwrap();
+        record.truncate_payload(2);
+        assert_eq!((record.payload_hex.as_str(), record.payload_truncated_by_flag), ("0c05", Some(true)));
+        assert_eq!(record.km_attribute, Some(0x11));
+        let mut short = process_packet(&packet(&[("usb.capdata", "0c:05")]), "s", false).unwrap();
+        short.truncate_payload(2);
+        assert_eq!((short.payload_hex.as_str(), short.payload_truncated_by_flag), ("0c05", Some(false)));
+    }
+
+    #[test]
+    fn flags_transaction_id_gaps_per_direction() {
+        let rows = [("0", "fe"), ("1", "fe"), ("0", "ff"), ("0", "00"), ("1", "ff"), ("0", "03"), ("0", "03")];
+        let mut records: Vec<UsbPacketRecord> = rows
+            .iter()
+            .enumerate()
+            .map(|(i, (dir, id))| {
+                let mut record = process_packet(
+                    &packet(&[
+                        ("usb.transfer_type", "0x03"),
+                        ("usb.endpoint_address.direction", dir),
+                        ("usb.capdata", &format!("41:{}:00:00", id)),
+                    ]),
+                    "s",
+                    false,
+                )
+                .unwrap();
+                record.frame_number = i as u32;
+                record
+            })
+            .collect();
+        assert_eq!(mark_transaction_gaps(&mut records), vec![5]);
+        let gaps: Vec<Option<bool>> = records.iter().map(|r| r.transaction_gap).collect();
+        assert_eq!(gaps, [false, false, false, false, false, true, false].map(Some));
+    }
+
+    #[test]
+    fn normalizes_transfer_and_urb_categories() {
+        assert_eq!(normalize_transfer_type("0x03"), "0x03");
+        assert_eq!(normalize_transfer_type("3"), "0x03");
+        assert_eq!(normalize_transfer_type("URB_BULK"), "0x03");
+        assert_eq!(normalize_transfer_type("URB_ISOCHRONOUS"), "0x00");
+        assert_eq!(normalize_transfer_type("bogus"), "Unknown");
+        assert_eq!(normalize_urb_type("URB_SUBMIT"), "S");
+        assert_eq!(normalize_urb_type("'c'"), "C");
+        assert_eq!(normalize_urb_status("-EINPROGRESS (-115)"), "-115");
+        assert_eq!(normalize_urb_status("Success (0)"), "0");
+        assert_eq!(normalize_urb_status(" 0 "), "0");
+    }
+
+    #[test]
+    fn categories_are_stored_as_categorical_with_raw_columns() {
+        let record = process_packet(&packet(&[("usb.transfer_type", "URB_BULK"), ("usb.urb_type", "&#x27;S&#x27;")]), "s", false).unwrap();
+        assert_eq!(record.transfer_type_raw, "URB_BULK");
+        let df = create_dataframe(vec![record]).unwrap();
+        assert!(df.column("transfer_type").unwrap().dtype().is_categorical());
+        assert_eq!(df.column("transfer_type_raw").unwrap().str().unwrap().get(0), Some("URB_BULK"));
+        assert_eq!(df.column("urb_type_raw").unwrap().str().unwrap().get(0), Some("&#x27;S&#x27;"));
+    }
+
+    #[test]
+    fn computes_per_endpoint_deltas_in_row_order() {
+        let record = |timestamp: f64, endpoint: &str| {
+            let mut record = process_packet(&packet(&[("usb.endpoint_address", endpoint)]), "s", false).unwrap();
+            record.timestamp = timestamp;
+            record
+        };
+        let df = create_dataframe(vec![record(0.003, "0x81"), record(0.001, "0x81"), record(0.002, "0x01"), record(0.0015, "0x81")]).unwrap();
+        let deltas: Vec<Option<f64>> = df.column("delta_prev_us").unwrap().f64().unwrap().iter().map(|d| d.map(f64::round)).collect();
+        assert_eq!(deltas, vec![Some(1500.0), None, None, Some(500.0)]);
+    }
+
+    #[test]
+    fn decodes_bmrequest_type_bitfield() {
+        assert_eq!(decode_bmrequest_type("0x80"), Some(("D->H", "standard", "device")));
+        assert_eq!(decode_bmrequest_type("0x41"), Some(("H->D", "vendor", "interface")));
+        assert_eq!(decode_bmrequest_type("0xa1"), Some(("D->H", "class", "interface")));
+        let setup = process_packet(&packet(&[("usb.bmRequestType", "0xc0")]), "s", false).unwrap();
+        assert_eq!(setup.setup_req_type.as_deref(), Some("vendor"));
+        let data = process_packet(&packet(&[]), "s", false).unwrap();
+        assert_eq!(data.setup_direction, None);
+    }
+
+    #[test]
+    fn names_known_vendor_requests() {
+        let blob = process_packet(&packet(&[("usb.bmRequestType", "0xc2"), ("usb.setup.bRequest", "50"), ("usb.setup.wValue", "0")]), "s", false).unwrap();
+        assert_eq!(blob.vendor_command.as_deref(), Some("read_blob_0x32"));
+        let unknown = process_packet(&packet(&[("usb.bmRequestType", "0xc2"), ("usb.setup.bRequest", "0x33")]), "s", false).unwrap();
+        assert_eq!(unknown.vendor_command, None);
+        assert_eq!(vendor_command("0x40", "0x32", None), None);
+    }
+
+    #[test]
+    fn cleans_quoted_urb_type() {
+        let record = process_packet(&packet(&[("usb.urb_type", "&#x27;S&#x27;")]), "s", false).unwrap();
+        assert_eq!(record.urb_type, "S");
+    }
+}
@@ -0,0 +1,260 @@
+//! Output schema: one table drives both the DataFrame layout and `--columns-info`.
+
+use log::warn;
+use polars::prelude::*;
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::record::{create_dataframe, UsbPacketRecord};
+use crate::Result;
+
+/// One output column: how to build it from records and how to explain it
+pub(crate) struct ColumnSpec {
+    pub(crate) name: &'static str,
+    /// What populates the column: a tshark layer, a payload decoder, or the flag that enables it
+    pub(crate) source: &'static str,
+    pub(crate) description: &'static str,
+    pub(crate) build: fn(&[UsbPacketRecord]) -> Column,
+}
+
+macro_rules! column {
+    ($name:literal, $ty:ty, $source:literal, $description:literal, |$r:ident| $value:expr) => {
+        ColumnSpec {
+            name: $name,
+            source: $source,
+            description: $description,
+            build: |records| {
+                let values: Vec<$ty> = records.iter().map(|$r| $value).collect();
+                Column::new($name.into(), values)
+            },
+        }
+    };
+}
+
+/// Output columns in file order
+pub(crate) static COLUMNS: &[ColumnSpec] = &[
+    column!("session_id", String, "converter", "Capture identifier from --session-id or the input file name", |r| r.session_id.clone()),
+    column!("frame_number", u32, "tshark frame", "pcapng frame number", |r| r.frame_number),
+    column!("timestamp", f64, "tshark frame", "Seconds since the first frame of the capture", |r| r.timestamp),
+    column!("timestamp_absolute", String, "tshark frame", "Wall-clock capture time as tshark prints it", |r| r.timestamp_absolute.clone()),
+    column!("timestamp_utc", Option<i64>, "tshark frame", "Wall-clock capture time from frame.time_epoch", |r| r.timestamp_utc),
+    // Placeholder: create_dataframe fills it in, since it needs the neighbouring rows
+    column!("delta_prev_us", Option<f64>, "converter", "Microseconds since the previous packet on the same endpoint and direction", |_r| None),
+    column!("direction", String, "tshark usb", "H->D (OUT) or D->H (IN)", |r| r.direction.clone()),
+    column!("device_address", u32, "tshark usb", "USB device address on the bus", |r| r.device_address as u32),
+    column!("bus_id", u32, "tshark usb", "usbmon bus number", |r| r.bus_id as u32),
+    column!("endpoint_address", String, "tshark usb", "Endpoint address including the direction bit, e.g. 0x81", |r| r.endpoint_address.clone()),
+    column!("endpoint_number", u32, "tshark usb", "Endpoint number without the direction bit", |r| r.endpoint_number as u32),
+    column!("transfer_type", String, "tshark usb", "Normalized transfer type code: 0x00 ISO, 0x01 interrupt, 0x02 control, 0x03 bulk", |r| r.transfer_type.clone()),
+    column!("urb_type", String, "tshark usb", "Normalized URB type: S submit, C complete, E error", |r| r.urb_type.clone()),
+    column!("urb_status", String, "tshark usb", "URB status as a decimal errno (0 = success)", |r| r.urb_status.clone()),
+    column!("transfer_type_raw", String, "tshark usb", "transfer_type exactly as tshark reported it", |r| r.transfer_type_raw.clone()),
+    column!("urb_type_raw", String, "tshark usb", "urb_type exactly as tshark reported it", |r| r.urb_type_raw.clone()),
+    column!("urb_status_raw", String, "tshark usb", "urb_status exactly as tshark reported it", |r| r.urb_status_raw.clone()),
+    column!("data_length", u32, "tshark usb", "Captured data length in bytes", |r| r.data_length),
+    column!("urb_length", u32, "tshark usb", "URB transfer length in bytes", |r| r.urb_length),
+    column!("payload_hex", String, "tshark usb", "Captured payload (usb.capdata) as lowercase hex", |r| r.payload_hex.clone()),
+    column!("payload_truncated_by_flag", Option<bool>, "--max-payload", "Whether payload_hex was cut to the --max-payload limit", |r| r.payload_truncated_by_flag),
+    column!("setup_flag", String, "tshark usb", "usbmon setup flag", |r| r.setup_flag.clone()),
+    column!("data_flag", String, "tshark usb", "usbmon data flag", |r| r.data_flag.clone()),
+    column!("packet_category", String, "tshark usb", "data, control, setup or status, from transfer type, setup flag and payload", |r| r.packet_category.clone()),
+    column!("interval", u32, "tshark usb", "Polling interval for interrupt/ISO endpoints", |r| r.interval),
+    column!("start_frame", u32, "tshark usb", "Start frame for ISO transfers", |r| r.start_frame),
+    column!("frame_length", u32, "tshark frame", "Frame length in bytes, usbmon header included", |r| r.frame_length),
+    column!("frame_protocols", String, "tshark frame", "Protocol stack tshark dissected", |r| r.frame_protocols.clone()),
+    column!("frame_comment", Option<String>, "tshark frame", "pcapng frame comment (Wireshark annotation)", |r| r.frame_comment.clone()),
+    column!("source_file", String, "converter", "Source capture identifier (same as session_id)", |r| r.source_file.clone()),
+    column!("bmrequest_type", Option<String>, "tshark usb", "Control setup bmRequestType", |r| r.bmrequest_type.clone()),
+    column!("brequest", Option<String>, "tshark usb", "Control setup bRequest code", |r| r.brequest.clone()),
+    column!("brequest_name", Option<String>, "tshark usb", "Control setup bRequest name", |r| r.brequest_name.clone()),
+    column!("vendor_command", Option<String>, "bmRequestType", "Name of a known KM003C vendor control request (bmRequestType, bRequest, wValue)", |r| r.vendor_command.clone()),
+    column!("wvalue", Option<u32>, "tshark usb", "Control setup wValue", |r| r.wvalue),
+    column!("windex", Option<u32>, "tshark usb", "Control setup wIndex", |r| r.windex),
+    column!("wlength", Option<u32>, "tshark usb", "Control setup wLength", |r| r.wlength),
+    column!("descriptor_type", Option<String>, "tshark usb", "Descriptor type of GET_DESCRIPTOR traffic", |r| r.descriptor_type.clone()),
+    column!("descriptor_index", Option<u32>, "tshark usb", "Descriptor index from wValue", |r| r.descriptor_index),
+    column!("language_id", Option<u32>, "tshark usb", "String descriptor language id from wValue", |r| r.language_id),
+    column!("setup_direction", Option<String>, "bmRequestType", "Data stage direction decoded from bmRequestType", |r| r.setup_direction.clone()),
+    column!("setup_req_type", Option<String>, "bmRequestType", "standard, class, vendor or reserved", |r| r.setup_req_type.clone()),
+    column!("setup_recipient", Option<String>, "bmRequestType", "device, interface, endpoint, other or reserved", |r| r.setup_recipient.clone()),
+    column!("id_vendor", Option<u32>, "device descriptor", "idVendor from a device descriptor response", |r| r.id_vendor.map(u32::from)),
+    column!("id_product", Option<u32>, "device descriptor", "idProduct from a device descriptor response", |r| r.id_product.map(u32::from)),
+    column!("device_product_name", Option<String>, "--device-name", "Known product name for the device VID/PID", |r| r.device_product_name.clone()),
+    column!("transfer_flags", Option<String>, "tshark usb", "URB transfer flags", |r| r.transfer_flags.clone()),
+    column!("copy_of_transfer_flags", Option<String>, "tshark usb", "Copy of URB transfer flags", |r| r.copy_of_transfer_flags.clone()),
+    column!("urb_id", String, "tshark usb", "Kernel URB id pairing submit and complete", |r| r.urb_id.clone()),
+    column!("usb_src", String, "tshark usb", "usbmon source address", |r| r.usb_src.clone()),
+    column!("usb_dst", String, "tshark usb", "usbmon destination address", |r| r.usb_dst.clone()),
+    column!("usb_addr", String, "tshark usb", "usbmon address", |r| r.usb_addr.clone()),
+    column!("urb_ts_sec", u64, "tshark usb", "URB timestamp, whole seconds", |r| r.urb_ts_sec),
+    column!("urb_ts_usec", u32, "tshark usb", "URB timestamp, microseconds part", |r| r.urb_ts_usec),
+    column!("packet_hash", String, "converter", "Stable FNV-1a hash of URB identity and payload", |r| r.packet_hash.clone()),
+    column!("is_retransmit", bool, "converter", "Exact repeat (URB id, URB time, payload) of one of the previous few rows, logged twice by usbmon", |r| r.is_retransmit),
+    column!("iso_segment_index", Option<u32>, "tshark usb", "Index of this ISO packet descriptor", |r| r.iso_segment_index),
+    column!("iso_segment_status", Option<String>, "tshark usb", "Status of this ISO packet descriptor", |r| r.iso_segment_status.clone()),
+    column!("iso_segment_length", Option<u32>, "tshark usb", "Length of this ISO packet descriptor", |r| r.iso_segment_length),
+    column!("frame_raw_hex", Option<String>, "--include-raw", "Full frame bytes (usbmon header + data) as hex", |r| r.frame_raw_hex.clone()),
+    column!("submit_ts", Option<f64>, "--coalesce-urbs", "Timestamp of the submit URB", |r| r.submit_ts),
+    column!("complete_ts", Option<f64>, "--coalesce-urbs", "Timestamp of the completion URB", |r| r.complete_ts),
+    column!("urb_coalesced", Option<bool>, "--coalesce-urbs", "Whether this row merges a submit and its completion", |r| r.urb_coalesced),
+    column!("status_flags", Option<u32>, "interrupt status", "UART state bitmap of a CDC SERIAL_STATE notification on the interrupt endpoint (bit 0 DCD, 1 DSR, 2 break, 3 ring, 4 framing, 5 parity, 6 overrun)", |r| r.status_flags),
+    column!("km_packet_type", Option<u32>, "KM003C header", "Main header packet type (bulk payloads)", |r| r.km_packet_type.map(u32::from)),
+    column!("km_transaction_id", Option<u32>, "KM003C header", "Main header rolling transaction id", |r| r.km_transaction_id.map(u32::from)),
+    column!("km_attribute", Option<u32>, "KM003C header", "Attribute (control) or obj_count_words (PutData)", |r| r.km_attribute.map(u32::from)),
+    column!("km_declared_len", Option<u32>, "KM003C header", "Bytes after the main header as declared by the PutData word count or an extended header", |r| r.km_declared_len),
+    column!("km_body_len", Option<u32>, "KM003C header", "Bytes actually captured after the main header", |r| r.km_body_len),
+    column!("km_length_ok", Option<bool>, "KM003C header", "km_body_len equals km_declared_len; false flags truncation or trailing padding", |r| r.km_length_ok),
+    column!("km_attribute_name", Option<String>, "--attribute-map", "Names of the attribute bits of a control packet or of a PutData's logical packets (built-in table plus --attribute-map), unnamed bits as bit<N>", |r| r.km_attribute_name.clone()),
+    column!("km_logical_attributes", Option<u32>, "KM003C payload", "Attributes of the logical packets in a PutData payload, OR-ed together", |r| r.km_logical_attributes.map(u32::from)),
+    column!("km_logical_body_len", Option<u32>, "KM003C payload", "Summed body length of the logical packets in a PutData payload, before --max-payload", |r| r.km_logical_body_len),
+    column!("session_phase", Option<String>, "converter", "handshake, streaming or teardown from packet-type transitions", |r| r.session_phase.clone()),
+    column!("transaction_gap", Option<bool>, "--check-transactions", "Transaction id skipped since the previous packet in this direction", |r| r.transaction_gap),
+    column!("checksum_valid", Option<bool>, "SettingsChecksumDecoder", "CRC-32 of Settings blocks matches; null when nothing is checksummed", |r| r.checksum_valid),
+    column!("adc_vbus_v", Option<f64>, "AdcDecoder", "VBUS voltage in V", |r| r.adc_vbus_v),
+    column!("adc_ibus_a", Option<f64>, "AdcDecoder", "IBUS current in A", |r| r.adc_ibus_a),
+    column!("adc_power_w", Option<f64>, "AdcDecoder", "VBUS power in W", |r| r.adc_power_w),
+    column!("adc_temp_c", Option<f64>, "TemperatureDecoder", "Internal temperature in °C", |r| r.adc_temp_c),
+    column!("adc_packet_kind", Option<String>, "AdcDecoder/TemperatureDecoder", "adc (full sample) or temperature (temperature-only packet)", |r| r.adc_packet_kind.clone()),
+    column!("cc_orientation", Option<String>, "ConnectionDecoder", "CC line seeing Rp: CC1 or CC2", |r| r.cc_orientation.clone()),
+    column!("cc_attached", Option<bool>, "ConnectionDecoder", "Whether the last PD connection event was a connect", |r| r.cc_attached),
+    column!("cc_advertised_current", Option<String>, "ConnectionDecoder", "Source Rp advertisement: default, 1.5A or 3.0A", |r| r.cc_advertised_current.clone()),
+    column!("pd_rdo_object_position", Option<u32>, "PdRequestDecoder", "1-based Source_Capabilities PDO selected by the Request", |r| r.pd_rdo_object_position.map(u32::from)),
+    column!("pd_rdo_op_current", Option<f64>, "PdRequestDecoder", "Requested operating current in A", |r| r.pd_rdo_op_current),
+    column!("pd_rdo_max_current", Option<f64>, "PdRequestDecoder", "Requested maximum (or GiveBack minimum) current in A", |r| r.pd_rdo_max_current),
+    column!("pd_rdo_give_back", Option<bool>, "PdRequestDecoder", "RDO GiveBack flag", |r| r.pd_rdo_give_back),
+    column!("pd_rdo_capability_mismatch", Option<bool>, "PdRequestDecoder", "RDO Capability Mismatch flag", |r| r.pd_rdo_capability_mismatch),
+    column!("pd_rdo_usb_comm_capable", Option<bool>, "PdRequestDecoder", "RDO USB Communications Capable flag", |r| r.pd_rdo_usb_comm_capable),
+    column!("pd_is_extended", Option<bool>, "PdMessageKindDecoder", "PdPacket contains an extended PD message (no data objects decoded)", |r| r.pd_is_extended),
+    column!("pd_is_bist", Option<bool>, "PdMessageKindDecoder", "PdPacket contains a BIST message (test-mode objects, not PDOs)", |r| r.pd_is_bist),
+    column!("device_sample_rate_hz", Option<f64>, "SettingsDecoder", "Configured sample rate in Hz from the Settings block's sample interval", |r| r.device_sample_rate_hz),
+    column!("km_group_count", Option<u32>, "GroupsDecoder", "Number of data groups (logical packets) in a PutData body", |r| r.km_group_count),
+    column!("km_groups", Option<String>, "GroupsDecoder", "Data groups of a PutData body in order as name:length, unnamed attributes as bit<N>", |r| r.km_groups.clone()),
+    column!("added_datetime", String, "converter", "Local time the row was converted", |r| r.added_datetime.clone()),
+];
+
+/// Print every output column with its dtype, source and description (`--columns-info`)
+pub fn print_columns_info() -> Result<()> {
+    let schema = create_dataframe(Vec::new())?.schema().clone();
+    let width = COLUMNS.iter().map(|c| c.name.len()).max().unwrap_or(0);
+    println!("{:<width$}  {:<22}  {:<24}  Description", "Column", "Dtype", "Populated by", width = width);
+    for column in COLUMNS {
+        let dtype = schema.get(column.name).map(|d| d.to_string()).unwrap_or_default();
+        println!(
+            "{:<width$}  {:<22}  {:<24}  {}",
+            column.name,
+            dtype,
+            column.source,
+            column.description,
+            width = width
+        );
+    }
+    Ok(())
+}
+
+/// `--schema` file: the full column list every output is conformed to, in order
+#[derive(Deserialize)]
+struct SchemaFile {
+    columns: Vec<SchemaColumn>,
+}
+
+#[derive(Deserialize)]
+struct SchemaColumn {
+    name: String,
+    /// Dtype as printed by `--columns-info`, e.g. `u32`, `f64`, `str`
+    dtype: String,
+}
+
+/// Dtypes a `--schema` file may name, matched against their printed form
+fn schema_dtypes() -> Vec<DataType> {
+    vec![
+        DataType::Boolean,
+        DataType::UInt8,
+        DataType::UInt16,
+        DataType::UInt32,
+        DataType::UInt64,
+        DataType::Int8,
+        DataType::Int16,
+        DataType::Int32,
+        DataType::Int64,
+        DataType::Float32,
+        DataType::Float64,
+        DataType::String,
+        DataType::from_categories(Categories::global()),
+        DataType::Datetime(TimeUnit::Microseconds, Some(TimeZone::UTC)),
+        DataType::Datetime(TimeUnit::Microseconds, None),
+    ]
+}
+
+/// Read a TOML or JSON `--schema` file of `columns = [{ name, dtype }, ...]`
+pub(crate) fn load_schema_file(path: &Path) -> Result<Vec<(String, DataType)>> {
+    let text = std::fs::read_to_string(path)?;
+    let file: SchemaFile = if path.extension().is_some_and(|e| e == "toml") {
+        toml::from_str(&text).map_err(|e| format!("Invalid --schema {:?}: {}", path, e))?
+    } else {
+        serde_json::from_str(&text).map_err(|e| format!("Invalid --schema {:?}: {}", path, e))?
+    };
+    let dtypes = schema_dtypes();
+    file.columns
+        .into_iter()
+        .map(|column| {
+            let wanted = column.dtype.trim().replace("[us", "[μs");
+            match dtypes.iter().find(|d| d.to_string() == wanted) {
+                Some(dtype) => Ok((column.name, dtype.clone())),
+                None => Err(format!("Unsupported dtype {:?} for column {:?} in --schema {:?}", column.dtype, column.name, path).into()),
+            }
+        })
+        .collect()
+}
+
+/// Reshape `df` to exactly the `--schema` columns: cast present ones, add missing ones as
+/// typed nulls and drop the rest. A value that does not fit its schema dtype is an error.
+pub(crate) fn conform_to_schema(df: DataFrame, schema: &[(String, DataType)]) -> Result<DataFrame> {
+    let extra: Vec<&str> =
+        df.get_column_names().into_iter().map(|c| c.as_str()).filter(|c| !schema.iter().any(|(name, _)| name == c)).collect();
+    if !extra.is_empty() {
+        warn!("⚠️  Dropping columns not in --schema: {}", extra.join(", "));
+    }
+    let columns = schema
+        .iter()
+        .map(|(name, dtype)| match df.column(name) {
+            Ok(column) => column
+                .strict_cast(dtype)
+                .map_err(|e| format!("Column {:?} does not fit --schema dtype {}: {}", name, dtype, e).into()),
+            Err(_) => Ok(Column::full_null(name.as_str().into(), df.height(), dtype)),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(DataFrame::new(columns)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dataframe_follows_the_column_table() {
+        let df = create_dataframe(Vec::new()).unwrap();
+        let names: Vec<&str> = df.get_column_names().iter().map(|n| n.as_str()).collect();
+        let expected: Vec<&str> = COLUMNS.iter().map(|c| c.name).collect();
+        assert_eq!(names, expected);
+        assert!(COLUMNS.iter().all(|c| !c.description.is_empty() && !c.source.is_empty()));
+    }
+
+    #[test]
+    fn conforms_output_to_a_schema_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("schema.toml");
+        let file = "[[columns]]\nname = \"frame_number\"\ndtype = \"u32\"\n\n[[columns]]\nname = \"cumulative_wh\"\ndtype = \"f64\"\n";
+        std::fs::write(&path, file).unwrap();
+        let schema = load_schema_file(&path).unwrap();
+
+        let df = df!["frame_number" => [1u32, 2], "payload_hex" => ["0c", "41"]].unwrap();
+        let conformed = conform_to_schema(df, &schema).unwrap();
+        assert_eq!(conformed.get_column_names(), ["frame_number", "cumulative_wh"]);
+        assert_eq!(conformed.column("cumulative_wh").unwrap().dtype(), &DataType::Float64);
+        assert_eq!(conformed.column("cumulative_wh").unwrap().null_count(), 2);
+
+        let json = dir.path().join("schema.json");
+        std::fs::write(&json, r#"{"columns": [{"name": "timestamp_utc", "dtype": "datetime[us, UTC]"}, {"name": "x", "dtype": "decimal"}]}"#).unwrap();
+        assert!(load_schema_file(&json).unwrap_err().to_string().contains("decimal"));
+    }
+}
@@ -0,0 +1,91 @@
+//! Field access abstraction over dissected packets.
+//!
+//! [`process_packet`](crate::process_packet) reads fields through these traits rather
+//! than rtshark types directly, so it can run against live tshark output or
+//! in-memory metadata maps in tests.
+
+use std::collections::HashMap;
+
+/// Read-only access to the dissected fields of one protocol layer
+pub trait MetadataSource {
+    /// Display value of a field such as `usb.urb_id`, if tshark emitted it
+    fn get(&self, key: &str) -> Option<&str>;
+
+    /// Every value of a field that repeats within the layer, in packet order
+    fn get_all(&self, key: &str) -> Vec<&str> {
+        self.get(key).into_iter().collect()
+    }
+}
+
+/// A packet made of named protocol layers (`frame`, `usb`, ...)
+pub trait PacketSource {
+    type Layer: MetadataSource;
+
+    fn layer(&self, name: &str) -> Option<&Self::Layer>;
+
+    /// Raw frame bytes, if the source retains them
+    fn raw_bytes(&self) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+impl MetadataSource for rtshark::Layer {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.metadata(key).map(|m| m.value())
+    }
+
+    fn get_all(&self, key: &str) -> Vec<&str> {
+        self.iter().filter(|m| m.name() == key).map(|m| m.value()).collect()
+    }
+}
+
+impl PacketSource for rtshark::Packet {
+    type Layer = rtshark::Layer;
+
+    fn layer(&self, name: &str) -> Option<&Self::Layer> {
+        self.layer_name(name)
+    }
+
+    /// Reassemble the frame from each field's raw value and position.
+    ///
+    /// PDML carries the wire bytes of every dissected field (including the usbmon
+    /// header), so placing them at their offsets recovers the frame. Bytes not
+    /// covered by any field are left as zero.
+    fn raw_bytes(&self) -> Option<Vec<u8>> {
+        let mut frame: Vec<u8> = Vec::new();
+        let mut covered = false;
+        for metadata in self.iter().flat_map(|layer| layer.iter()) {
+            let (Some(position), Some(size)) = (metadata.position(), metadata.size()) else {
+                continue;
+            };
+            let raw = metadata.raw_value();
+            if size == 0 || raw.len() != size as usize * 2 {
+                continue;
+            }
+            let Ok(bytes) = hex::decode(raw) else {
+                continue;
+            };
+            let start = position as usize;
+            if frame.len() < start + bytes.len() {
+                frame.resize(start + bytes.len(), 0);
+            }
+            frame[start..start + bytes.len()].copy_from_slice(&bytes);
+            covered = true;
+        }
+        covered.then_some(frame)
+    }
+}
+
+impl MetadataSource for HashMap<String, String> {
+    fn get(&self, key: &str) -> Option<&str> {
+        HashMap::get(self, key).map(String::as_str)
+    }
+}
+
+impl PacketSource for HashMap<String, HashMap<String, String>> {
+    type Layer = HashMap<String, String>;
+
+    fn layer(&self, name: &str) -> Option<&Self::Layer> {
+        HashMap::get(self, name)
+    }
+}
@@ -0,0 +1,332 @@
+use polars::prelude::*;
+use polars_utils::plpath::PlPath;
+use std::path::Path;
+
+use crate::config::Preview;
+use crate::metadata::read_parquet_metadata;
+use crate::Result;
+
+/// Print summary statistics for a converted dataset
+pub fn print_statistics(df: &DataFrame) -> Result<()> {
+    print_lazy_statistics(df.clone().lazy(), None)
+}
+
+/// [`print_statistics`] for rows kept by `--sample-rate`, noting the sampling factor
+pub(crate) fn print_sampled_statistics(df: &DataFrame, sample_rate: Option<usize>) -> Result<()> {
+    // Part of the conversion log, so `--quiet` drops it along with the info messages
+    if !log::log_enabled!(log::Level::Info) {
+        return Ok(());
+    }
+    print_lazy_statistics(df.clone().lazy(), sample_rate)
+}
+
+/// Print the `--preview` rows of the final DataFrame; silent under `--quiet` like the statistics
+pub(crate) fn print_preview(df: &DataFrame, preview: Preview) {
+    if log::log_enabled!(log::Level::Info) {
+        println!("{}", preview_rows(df, preview));
+    }
+}
+
+fn preview_rows(df: &DataFrame, preview: Preview) -> DataFrame {
+    match preview {
+        Preview::Head(rows) => df.head(Some(rows)),
+        Preview::Tail(rows) => df.tail(Some(rows)),
+    }
+}
+
+/// Print the same statistics for an existing Parquet file (`stats` subcommand).
+///
+/// The file is scanned lazily; sections whose columns the file predates are skipped.
+pub fn print_parquet_statistics(path: &Path) -> Result<()> {
+    let path = path.to_str().ok_or("Parquet path is not valid UTF-8")?;
+    let lazy_df = LazyFrame::scan_parquet(PlPath::new(path), ScanArgsParquet::default())?;
+    let sample_rate = read_parquet_metadata(Path::new(path))?
+        .into_iter()
+        .find(|(key, _)| key == "sample_rate")
+        .and_then(|(_, value)| value.parse().ok());
+    println!("Statistics for {}", path);
+    print_lazy_statistics(lazy_df, sample_rate)
+}
+
+fn print_lazy_statistics(mut lazy_df: LazyFrame, sample_rate: Option<usize>) -> Result<()> {
+    let schema = lazy_df.collect_schema()?;
+    let has = |columns: &[&str]| columns.iter().all(|c| schema.contains(c));
+    let total_records = lazy_df.clone().select([len()]).collect()?.column("len")?.get(0)?.extract::<usize>().unwrap_or(0);
+
+    println!("\n=== Statistics ===");
+    println!("Total records: {}", total_records);
+    if let Some(n) = sample_rate.filter(|&n| n > 1) {
+        println!("Sampled 1 in {} packets (--sample-rate): counts cover roughly 1/{} of the capture", n, n);
+    }
+    println!("Columns: {:?}", schema.iter_names().map(|n| n.as_str()).collect::<Vec<_>>());
+
+    // Basic counts using group_by
+    if has(&["direction"]) {
+        let direction_stats = lazy_df
+            .clone()
+            .group_by([col("direction")])
+            .agg([len().alias("count")])
+            .sort(["count"], SortMultipleOptions::default().with_order_descending(true))
+            .collect()?;
+
+        println!("\nDirection distribution:");
+        println!("{}", direction_stats);
+    }
+
+    if has(&["device_address"]) {
+        let device_stats = lazy_df
+            .clone()
+            .group_by([col("device_address")])
+            .agg([len().alias("count")])
+            .sort(["count"], SortMultipleOptions::default().with_order_descending(true))
+            .collect()?;
+
+        println!("\nDevice address distribution:");
+        println!("{}", device_stats);
+    }
+
+    // Data length statistics
+    if has(&["data_length"]) {
+        let length_stats = lazy_df
+            .clone()
+            .select([
+                col("data_length").mean().alias("avg_length"),
+                col("data_length").min().alias("min_length"),
+                col("data_length").max().alias("max_length"),
+            ])
+            .collect()?;
+
+        println!("\nPayload length statistics:");
+        println!("{}", length_stats);
+    }
+
+    // Time range statistics
+    if has(&["timestamp"]) {
+        let time_stats = lazy_df
+            .clone()
+            .select([
+                col("timestamp").min().alias("start_time"),
+                col("timestamp").max().alias("end_time"),
+                (col("timestamp").max() - col("timestamp").min()).alias("duration"),
+            ])
+            .collect()?;
+
+        println!("\nTime range:");
+        println!("{}", time_stats);
+    }
+
+    // Endpoint and transfer type breakdowns
+    if has(&["endpoint_number", "data_length"]) {
+        let endpoint_stats = lazy_df
+            .clone()
+            .group_by([col("endpoint_number")])
+            .agg([
+                len().alias("count"),
+                col("data_length").sum().alias("total_bytes"),
+            ])
+            .sort(["count"], SortMultipleOptions::default().with_order_descending(true))
+            .collect()?;
+
+        println!("\nEndpoint distribution:");
+        println!("{}", endpoint_stats);
+    }
+
+    if has(&["transfer_type"]) {
+        let transfer_type_stats = lazy_df
+            .clone()
+            .group_by([col("transfer_type")])
+            .agg([len().alias("count")])
+            .sort(["count"], SortMultipleOptions::default().with_order_descending(true))
+            .collect()?;
+
+        println!("\nTransfer type distribution:");
+        println!("{}", transfer_type_stats);
+    }
+
+    if let Some(adc_stats) = adc_stats(lazy_df.clone(), &schema)? {
+        println!("\nADC measurements:");
+        println!("{}", adc_stats);
+    }
+
+    if has(&["endpoint_address", "direction", "timestamp", "data_length"]) {
+        println!("\nEndpoint throughput:");
+        println!("{}", throughput_stats(lazy_df)?);
+    }
+
+    Ok(())
+}
+
+/// Decoded ADC columns summarized by [`adc_stats`]
+const ADC_STAT_COLUMNS: [&str; 3] = ["adc_vbus_v", "adc_ibus_a", "adc_power_w"];
+
+/// Min/mean/max and p50/p95 of each decoded ADC column present in the schema.
+///
+/// Nulls (rows that are not ADC samples) are ignored; `None` when no row was decoded.
+fn adc_stats(lazy_df: LazyFrame, schema: &Schema) -> Result<Option<DataFrame>> {
+    let per_column: Vec<LazyFrame> = ADC_STAT_COLUMNS
+        .iter()
+        .filter(|name| schema.contains(name))
+        .map(|&name| {
+            let value = col(name).cast(DataType::Float64);
+            lazy_df.clone().select([
+                lit(name).alias("column"),
+                value.clone().count().cast(DataType::UInt64).alias("samples"),
+                value.clone().min().alias("min"),
+                value.clone().mean().alias("mean"),
+                value.clone().max().alias("max"),
+                value.clone().quantile(lit(0.5), QuantileMethod::Linear).alias("p50"),
+                value.quantile(lit(0.95), QuantileMethod::Linear).alias("p95"),
+            ])
+        })
+        .collect();
+    if per_column.is_empty() {
+        return Ok(None);
+    }
+    let stats = concat(per_column, UnionArgs::default())?
+        .filter(col("samples").gt(lit(0u64)))
+        .collect()?;
+    Ok(Some(stats).filter(|s| s.height() > 0))
+}
+
+/// Per endpoint and direction: total bytes, active duration, average and peak 1-second throughput.
+///
+/// The peak is the largest byte count inside any rolling 1 s window ending at a packet.
+pub fn throughput_stats(lazy_df: LazyFrame) -> Result<DataFrame> {
+    let keys = [col("endpoint_address"), col("direction")];
+    let lazy_df = lazy_df
+        .with_column((col("timestamp") * lit(1e6)).cast(DataType::Int64).alias("timestamp_us"))
+        .sort(["endpoint_address", "direction", "timestamp_us"], SortMultipleOptions::default());
+
+    let totals = lazy_df
+        .clone()
+        .group_by(keys.clone())
+        .agg([
+            col("data_length").cast(DataType::UInt64).sum().alias("total_bytes"),
+            (col("timestamp").max() - col("timestamp").min()).alias("duration_s"),
+        ])
+        .with_column((col("total_bytes").cast(DataType::Float64) / col("duration_s")).alias("avg_bytes_per_s"));
+
+    let peaks = lazy_df
+        .rolling(
+            col("timestamp_us"),
+            keys.clone(),
+            RollingGroupOptions {
+                period: Duration::new(1_000_000),
+                offset: Duration::new(-1_000_000),
+                closed_window: ClosedWindow::Right,
+                ..Default::default()
+            },
+        )
+        .agg([col("data_length").cast(DataType::UInt64).sum().alias("window_bytes")])
+        .group_by(keys.clone())
+        .agg([col("window_bytes").max().alias("peak_bytes_per_s")]);
+
+    let stats = totals
+        .join(peaks, keys.clone(), keys, JoinArgs::new(JoinType::Left))
+        .sort(["total_bytes"], SortMultipleOptions::default().with_order_descending(true))
+        .collect()?;
+    Ok(stats)
+}
+
+/// Write [`throughput_stats`] rows as a JSON array of objects (`--throughput-json`)
+pub(crate) fn write_throughput_json(stats: &DataFrame, path: &Path) -> Result<()> {
+    let mut rows = vec![serde_json::Map::new(); stats.height()];
+    for column in stats.get_columns() {
+        for (row, value) in rows.iter_mut().zip(column.as_materialized_series().iter()) {
+            let json = match value {
+                AnyValue::Null => serde_json::Value::Null,
+                AnyValue::String(s) => s.into(),
+                AnyValue::UInt64(v) => v.into(),
+                AnyValue::Float64(v) => serde_json::Number::from_f64(v).map_or(serde_json::Value::Null, Into::into),
+                other => other.to_string().into(),
+            };
+            row.insert(column.name().to_string(), json);
+        }
+    }
+    std::fs::write(path, serde_json::to_string_pretty(&rows)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_average_and_peak_throughput() {
+        let df = df![
+            "endpoint_address" => ["0x81", "0x81", "0x81", "0x81", "0x01"],
+            "direction" => ["D->H", "D->H", "D->H", "D->H", "H->D"],
+            "timestamp" => [0.0, 0.5, 0.9, 4.0, 1.0],
+            "data_length" => [100u32, 200, 300, 400, 8],
+        ]
+        .unwrap();
+
+        let stats = throughput_stats(df.lazy()).unwrap();
+        let bulk_in = stats.head(Some(1));
+        assert_eq!(bulk_in.column("endpoint_address").unwrap().str().unwrap().get(0), Some("0x81"));
+        assert_eq!(bulk_in.column("total_bytes").unwrap().u64().unwrap().get(0), Some(1000));
+        assert_eq!(bulk_in.column("avg_bytes_per_s").unwrap().f64().unwrap().get(0), Some(250.0));
+        assert_eq!(bulk_in.column("peak_bytes_per_s").unwrap().u64().unwrap().get(0), Some(600));
+        // The printed statistics need polars' `fmt` feature to show rows rather than just a shape
+        let rendered = stats.to_string();
+        assert!(rendered.contains("0x81") && rendered.contains("600"), "{}", rendered);
+
+        let path = std::env::temp_dir().join(format!("throughput_{}.json", std::process::id()));
+        write_throughput_json(&stats, &path).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json[0]["peak_bytes_per_s"], 600);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn summarizes_decoded_adc_columns_only() {
+        let df = df![
+            "adc_vbus_v" => [None, Some(5.0), Some(9.0), Some(20.0)],
+            "adc_ibus_a" => [None::<f64>, None, None, None],
+        ]
+        .unwrap();
+        let schema = df.schema().clone();
+        let stats = adc_stats(df.lazy(), &schema).unwrap().unwrap();
+        assert_eq!(stats.height(), 1);
+        let value = |name: &str| stats.column(name).unwrap().f64().unwrap().get(0).unwrap();
+        assert_eq!((value("min"), value("max"), value("p50")), (5.0, 20.0, 9.0));
+        assert_eq!(stats.column("samples").unwrap().u64().unwrap().get(0), Some(3));
+
+        let no_adc = df!["adc_power_w" => [None::<f64>]].unwrap();
+        let schema = no_adc.schema().clone();
+        assert!(adc_stats(no_adc.lazy(), &schema).unwrap().is_none());
+    }
+
+    #[test]
+    fn previews_either_end_of_the_table() {
+        let df = df!["frame_number" => [1u32, 2, 3, 4]].unwrap();
+        let frames = |preview: &str| {
+            let rows = preview_rows(&df, preview.parse().unwrap());
+            rows.column("frame_number").unwrap().u32().unwrap().into_no_null_iter().collect::<Vec<_>>()
+        };
+        assert_eq!(frames("head:2"), [1, 2]);
+        assert_eq!(frames("tail:3"), [2, 3, 4]);
+        assert_eq!(frames("tail:10"), [1, 2, 3, 4]);
+        // What --preview prints: the rows themselves, not just the shape
+        let sessions = df!["session_id" => ["first_run", "second_run"]].unwrap();
+        let rendered = preview_rows(&sessions, "tail:1".parse().unwrap()).to_string();
+        assert!(rendered.contains("second_run") && !rendered.contains("first_run"), "{}", rendered);
+        for bad in ["head", "middle:2", "tail:x"] {
+            assert!(bad.parse::<Preview>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn parquet_statistics_tolerate_older_schemas() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("old.parquet");
+        let mut df = df![
+            "direction" => ["H->D", "D->H"],
+            "timestamp" => [0.0, 0.5],
+            "data_length" => [4u32, 52],
+        ]
+        .unwrap();
+        ParquetWriter::new(std::fs::File::create(&path).unwrap()).finish(&mut df).unwrap();
+        print_parquet_statistics(&path).unwrap();
+    }
+}
@@ -0,0 +1,44 @@
+//! Packet and record builders shared by the unit tests.
+
+use std::collections::HashMap;
+
+use crate::record::{process_packet, UsbPacketRecord};
+
+/// A tshark packet as `layer -> field -> value`
+pub(crate) type FakePacket = HashMap<String, HashMap<String, String>>;
+
+pub(crate) fn layer(fields: &[(&str, &str)]) -> HashMap<String, String> {
+    fields.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+/// Packet with these `frame` and `usb` layer fields
+pub(crate) fn packet_with(frame_fields: &[(&str, &str)], usb_fields: &[(&str, &str)]) -> FakePacket {
+    FakePacket::from([("frame".to_string(), layer(frame_fields)), ("usb".to_string(), layer(usb_fields))])
+}
+
+/// Packet for frame 42 (1.5 s, 96 bytes) with these `usb` fields
+pub(crate) fn packet(usb_fields: &[(&str, &str)]) -> FakePacket {
+    packet_with(&[("frame.number", "42"), ("frame.time_relative", "1.5"), ("frame.len", "96")], usb_fields)
+}
+
+/// Record of session `s` built from these `frame` and `usb` layer fields
+pub(crate) fn record(frame_fields: &[(&str, &str)], usb_fields: &[(&str, &str)]) -> UsbPacketRecord {
+    process_packet(&packet_with(frame_fields, usb_fields), "s", false).unwrap()
+}
+
+/// Bulk transfer of device 16 in `frame`, captured `frame` ms in; `direction` is "0" (out) or "1" (in)
+pub(crate) fn bulk(frame: u32, direction: &str, capdata: &str) -> UsbPacketRecord {
+    let number = frame.to_string();
+    let time = format!("{:.3}", frame as f64 * 0.001);
+    let usb = [("usb.device_address", "16"), ("usb.transfer_type", "0x03"), ("usb.endpoint_address.direction", direction), ("usb.capdata", capdata)];
+    record(&[("frame.number", &number), ("frame.time_relative", &time)], &usb)
+}
+
+/// Colon-separated PutData answering transaction `id` with one 44-byte ADC packet
+pub(crate) fn adc_put_data(id: u8, vbus_uv: i32, ibus_ua: i32) -> String {
+    let mut bytes = vec![0x41, id, 0x82, 0x02, 0x01, 0x00, 0x00, 0x0b];
+    bytes.extend_from_slice(&vbus_uv.to_le_bytes());
+    bytes.extend_from_slice(&ibus_ua.to_le_bytes());
+    bytes.extend_from_slice(&[0; 36]);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(":")
+}
@@ -0,0 +1,88 @@
+use polars::prelude::*;
+use std::collections::HashMap;
+
+use crate::Result;
+
+/// Capture time of each row in microseconds since the Unix epoch: `timestamp_utc`, or the
+/// usbmon URB time for rows (or files) without it
+fn epoch_micros(df: &DataFrame) -> Result<Vec<Option<i64>>> {
+    let utc = match df.column("timestamp_utc") {
+        Ok(column) => Some(column.cast(&DataType::Int64)?),
+        Err(_) => None,
+    };
+    let utc = utc.as_ref().map(|c| c.i64()).transpose()?;
+    let urb = match (df.column("urb_ts_sec"), df.column("urb_ts_usec")) {
+        (Ok(sec), Ok(usec)) => Some((sec.cast(&DataType::Int64)?, usec.cast(&DataType::Int64)?)),
+        _ => None,
+    };
+    let urb = urb.as_ref().map(|(sec, usec)| Ok::<_, PolarsError>((sec.i64()?, usec.i64()?))).transpose()?;
+    Ok((0..df.height())
+        .map(|row| {
+            utc.and_then(|utc| utc.get(row))
+                .or_else(|| urb.and_then(|(sec, usec)| Some(sec.get(row)? * 1_000_000 + usec.get(row)?)))
+        })
+        .collect())
+}
+
+/// Lay the sessions end to end on one timeline (`--continuous-time`).
+///
+/// Sessions are ordered by their earliest epoch time; each one's `timestamp` is shifted
+/// by the end of the sessions before it, and its own capture-relative time is kept in
+/// `timestamp_session`. Rows from an earlier run already carry `timestamp_session`, so
+/// re-applying this to an appended file is stable.
+pub(crate) fn continuous_timeline(df: DataFrame) -> Result<DataFrame> {
+    let original = match df.get_column_index("timestamp_session") {
+        Some(_) => col("timestamp_session").fill_null(col("timestamp")),
+        None => col("timestamp"),
+    };
+    let mut df = df.lazy().with_column(original.alias("timestamp_session")).collect()?;
+
+    let epoch = epoch_micros(&df)?;
+    let sessions = df.column("session_id")?.str()?;
+    let local = df.column("timestamp_session")?.f64()?;
+    // Per session: earliest epoch time and latest capture-relative time
+    let mut spans: HashMap<Option<&str>, (Option<i64>, f64)> = HashMap::new();
+    for (row, epoch) in epoch.into_iter().enumerate() {
+        let span = spans.entry(sessions.get(row)).or_insert((None, 0.0));
+        span.0 = match (span.0, epoch) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        span.1 = span.1.max(local.get(row).unwrap_or(0.0));
+    }
+    // Sessions without any epoch time go last
+    let mut order: Vec<_> = spans.into_iter().collect();
+    order.sort_by_key(|(session, (start, _))| (start.is_none(), *start, *session));
+
+    let mut offsets: HashMap<Option<&str>, f64> = HashMap::new();
+    let mut end = 0.0;
+    for (session, (_, latest)) in order {
+        offsets.insert(session, end);
+        end += latest;
+    }
+    let shifted: Vec<Option<f64>> = (0..df.height()).map(|row| Some(local.get(row)? + offsets[&sessions.get(row)])).collect();
+    df.with_column(Column::new("timestamp".into(), shifted))?;
+    Ok(df)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chains_sessions_in_epoch_order_and_is_stable() {
+        let df = df![
+            "session_id" => ["late", "late", "early", "early"],
+            "timestamp" => [0.0, 5.0, 0.0, 3.0],
+            "urb_ts_sec" => [2000u64, 2005, 1000, 1003],
+            "urb_ts_usec" => [0u32, 0, 0, 0],
+        ]
+        .unwrap();
+        let merged = continuous_timeline(df).unwrap();
+        let column = |df: &DataFrame, name| df.column(name).unwrap().f64().unwrap().into_no_null_iter().collect::<Vec<_>>();
+
+        assert_eq!(column(&merged, "timestamp"), [3.0, 8.0, 0.0, 3.0]);
+        assert_eq!(column(&merged, "timestamp_session"), [0.0, 5.0, 0.0, 3.0]);
+        assert_eq!(column(&continuous_timeline(merged.clone()).unwrap(), "timestamp"), [3.0, 8.0, 0.0, 3.0]);
+    }
+}
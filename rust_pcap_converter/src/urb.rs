@@ -0,0 +1,165 @@
+//! URB-level transformations over converted records.
+
+use std::collections::HashMap;
+
+use crate::record::UsbPacketRecord;
+
+/// Merge each submit URB with its completion into a single transaction row.
+///
+/// Pairing follows capture order: a completion belongs to the most recent pending
+/// submit with the same `urb_id` (the kernel reuses URB addresses, so ids are only
+/// unique while in flight). The merged row keeps the submit's direction and setup
+/// fields, takes the completion's status, and carries whichever side has the
+/// payload (the completion for IN transfers, the submit for OUT transfers).
+/// Rows without a partner pass through unchanged with `urb_coalesced = false`.
+pub fn coalesce_urbs(records: Vec<UsbPacketRecord>) -> Vec<UsbPacketRecord> {
+    let mut output: Vec<UsbPacketRecord> = Vec::with_capacity(records.len());
+    let mut pending: HashMap<String, usize> = HashMap::new();
+
+    for mut record in records {
+        match record.urb_type.as_str() {
+            "S" => {
+                record.submit_ts = Some(record.timestamp);
+                record.urb_coalesced = Some(false);
+                pending.insert(record.urb_id.clone(), output.len());
+                output.push(record);
+            }
+            "C" => match pending.remove(&record.urb_id) {
+                Some(index) => merge_completion(&mut output[index], record),
+                None => {
+                    record.complete_ts = Some(record.timestamp);
+                    record.urb_coalesced = Some(false);
+                    output.push(record);
+                }
+            },
+            _ => {
+                record.urb_coalesced = Some(false);
+                output.push(record);
+            }
+        }
+    }
+
+    output
+}
+
+fn merge_completion(submit: &mut UsbPacketRecord, complete: UsbPacketRecord) {
+    submit.complete_ts = Some(complete.timestamp);
+    submit.urb_status = complete.urb_status;
+    submit.urb_coalesced = Some(true);
+    if !complete.payload_hex.is_empty() {
+        submit.payload_hex = complete.payload_hex;
+        submit.data_length = complete.data_length;
+        submit.packet_hash = complete.packet_hash;
+    }
+}
+
+/// A run of half-seen URBs, uninterrupted by a matched submit/completion pair
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DropSpan {
+    pub first_frame: u32,
+    pub last_frame: u32,
+    /// URBs in the span whose submit or completion is missing
+    pub unmatched: usize,
+}
+
+/// Find regions where usbmon events appear to have been dropped (`--check-drops`).
+///
+/// tshark numbers frames sequentially as it reads, so `frame_number` gaps cannot reveal
+/// kernel buffer overruns; a lost event instead leaves its URB half-seen. A completion
+/// with no pending submit, or a submit whose id is reused before it completes, counts as
+/// unmatched. Completions before the first submit and submits still pending at the end
+/// were in flight when the capture started or stopped and are ignored.
+pub fn find_drop_spans(records: &[UsbPacketRecord]) -> Vec<DropSpan> {
+    let mut spans = Vec::new();
+    let mut current: Option<DropSpan> = None;
+    let mut pending: HashMap<&str, u32> = HashMap::new();
+    let mut seen_submit = false;
+
+    for record in records {
+        match record.urb_type.as_str() {
+            "S" => {
+                seen_submit = true;
+                if let Some(lost) = pending.insert(&record.urb_id, record.frame_number) {
+                    unmatched_at(&mut current, lost);
+                }
+            }
+            "C" => {
+                if pending.remove(record.urb_id.as_str()).is_some() {
+                    spans.extend(current.take());
+                } else if seen_submit {
+                    unmatched_at(&mut current, record.frame_number);
+                }
+            }
+            _ => {}
+        }
+    }
+    spans.extend(current);
+    spans
+}
+
+/// Widen the open span, or start one, to cover an unmatched URB at `frame`
+fn unmatched_at(current: &mut Option<DropSpan>, frame: u32) {
+    let span = current.get_or_insert(DropSpan { first_frame: frame, last_frame: frame, unmatched: 0 });
+    span.first_frame = span.first_frame.min(frame);
+    span.last_frame = span.last_frame.max(frame);
+    span.unmatched += 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(frame: &str, urb_type: &str, urb_id: &str, capdata: &str) -> UsbPacketRecord {
+        let usb = [("usb.urb_type", urb_type), ("usb.urb_id", urb_id), ("usb.capdata", capdata)];
+        crate::test_support::record(&[("frame.number", frame), ("frame.time_relative", frame)], &usb)
+    }
+
+    #[test]
+    fn pairs_submit_with_completion() {
+        let rows = coalesce_urbs(vec![
+            record("1", "S", "0xa", ""),
+            record("2", "S", "0xb", "0c"),
+            record("3", "C", "0xa", "4100"),
+            record("4", "C", "0xb", ""),
+        ]);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].frame_number, 1);
+        assert_eq!(rows[0].payload_hex, "4100");
+        assert_eq!((rows[0].submit_ts, rows[0].complete_ts), (Some(1.0), Some(3.0)));
+        assert_eq!(rows[1].payload_hex, "0c");
+        assert_eq!(rows[1].urb_coalesced, Some(true));
+    }
+
+    #[test]
+    fn reports_half_seen_urbs_as_drop_spans() {
+        let spans = find_drop_spans(&[
+            record("1", "C", "0xa", "01"),
+            record("2", "S", "0xa", ""),
+            record("3", "C", "0xa", "02"),
+            record("4", "S", "0xb", ""),
+            record("5", "S", "0xb", ""),
+            record("6", "C", "0xc", "03"),
+            record("7", "S", "0xd", ""),
+            record("8", "C", "0xd", "04"),
+            record("9", "S", "0xe", ""),
+        ]);
+        // Frame 1 predates the first submit and frame 9 is still in flight
+        assert_eq!(spans, [DropSpan { first_frame: 4, last_frame: 6, unmatched: 2 }]);
+    }
+
+    #[test]
+    fn reused_urb_ids_pair_in_order_and_orphans_pass_through() {
+        let rows = coalesce_urbs(vec![
+            record("1", "C", "0xa", "01"),
+            record("2", "S", "0xa", ""),
+            record("3", "C", "0xa", "02"),
+            record("4", "S", "0xa", ""),
+        ]);
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].urb_coalesced, Some(false));
+        assert_eq!(rows[1].payload_hex, "02");
+        assert_eq!(rows[1].urb_coalesced, Some(true));
+        assert_eq!(rows[2].urb_coalesced, Some(false));
+        assert_eq!(rows[2].complete_ts, None);
+    }
+}
@@ -0,0 +1,107 @@
+//! `validate` subcommand: how much of a capture's bulk traffic follows the KM003C grammar.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+use crate::config::ConverterConfig;
+use crate::converter::Converter;
+use crate::km003c;
+use crate::record::TRANSFER_TYPE_BULK;
+use crate::{ConverterError, Result};
+
+/// Result of [`validate_capture`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Validation {
+    /// Non-empty bulk payloads checked
+    pub total: usize,
+    /// Payloads accepted by [`km003c::is_well_formed`]
+    pub recognized: usize,
+    /// First byte of each rejected payload, with its count
+    pub unrecognized_leading_bytes: BTreeMap<u8, usize>,
+}
+
+impl Validation {
+    fn record(&mut self, payload: &[u8]) {
+        let Some(&leading) = payload.first() else {
+            return;
+        };
+        self.total += 1;
+        if km003c::is_well_formed(payload) {
+            self.recognized += 1;
+        } else {
+            *self.unrecognized_leading_bytes.entry(leading).or_insert(0) += 1;
+        }
+    }
+
+    /// Share of payloads recognized; a capture without bulk payloads counts as 100%
+    pub fn recognized_percent(&self) -> f64 {
+        if self.total == 0 {
+            return 100.0;
+        }
+        self.recognized as f64 * 100.0 / self.total as f64
+    }
+}
+
+/// Check every bulk payload of `capture` against the protocol grammar (`validate` subcommand).
+///
+/// Device address, tshark settings and other options come from `config`; its input is
+/// replaced by `capture`. Control transfers are not part of the KM003C protocol and are skipped.
+pub fn validate_capture(config: &ConverterConfig, capture: &Path) -> Result<Validation> {
+    let mut config = config.clone();
+    config.input = capture.to_path_buf();
+    let mut converter = Converter::new(config)?;
+    let mut validation = Validation::default();
+    for result in converter.records() {
+        let record = match result {
+            Ok(record) => record,
+            Err(e) if e.is_tshark_failure() => return Err(e),
+            Err(_) => continue,
+        };
+        if record.transfer_type != TRANSFER_TYPE_BULK {
+            continue;
+        }
+        let payload = hex::decode(&record.payload_hex)
+            .map_err(|source| ConverterError::HexDecode { payload: record.payload_hex.clone(), source })?;
+        validation.record(&payload);
+    }
+    Ok(validation)
+}
+
+impl fmt::Display for Validation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Recognized {} of {} bulk payloads ({:.2}%)",
+            self.recognized,
+            self.total,
+            self.recognized_percent()
+        )?;
+        if self.unrecognized_leading_bytes.is_empty() {
+            return Ok(());
+        }
+        write!(f, "\n\n{:>12}  {:>8}", "Leading byte", "Count")?;
+        for (byte, count) in &self.unrecognized_leading_bytes {
+            write!(f, "\n{:>12}  {:>8}", format!("0x{:02x}", byte), count)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_recognized_payloads_and_histograms_the_rest() {
+        let mut validation = Validation::default();
+        for payload in [&[0x0C, 0x05, 0x22, 0x00][..], &[0x41, 0x05, 0x00, 0x00], &[0x7F, 0x01, 0x00, 0x00], &[0x7F], &[0xC4, 0x00], &[]] {
+            validation.record(payload);
+        }
+        assert_eq!((validation.total, validation.recognized), (5, 2));
+        assert_eq!(validation.unrecognized_leading_bytes, BTreeMap::from([(0x7F, 2), (0xC4, 1)]));
+        assert!((validation.recognized_percent() - 40.0).abs() < 1e-9);
+        assert!(validation.to_string().starts_with("Recognized 2 of 5 bulk payloads (40.00%)"));
+        assert_eq!(Validation::default().recognized_percent(), 100.0);
+    }
+}
@@ -0,0 +1,161 @@
+//! `--wasm-decoder`: run a user-supplied WebAssembly decoder over every KM003C payload.
+//!
+//! The module must export its `memory` and two functions:
+//!
+//! - `alloc(len: i32) -> i32`: reserve `len` bytes and return their address
+//! - `decode(ptr: i32, len: i32) -> i64`: decode the payload at `ptr`, returning the
+//!   address (high 32 bits) and length (low 32 bits) of a UTF-8 JSON object; a zero
+//!   length means the payload is not one the decoder understands
+//!
+//! Each top-level key of the returned objects becomes a `wasm_<key>` column.
+
+use polars::prelude::*;
+use serde_json::{Map, Value};
+use std::collections::BTreeSet;
+use std::path::Path;
+use wasmtime::{Engine, Instance, Memory, Module, Store, TypedFunc};
+
+use crate::Result;
+
+/// A loaded decoder module with its own store
+pub(crate) struct WasmDecoder {
+    store: Store<()>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    decode: TypedFunc<(i32, i32), i64>,
+}
+
+impl WasmDecoder {
+    /// Compile and instantiate the module at `path` (`.wasm`, or `.wat` text)
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        let wasm_err = |e: wasmtime::Error| format!("--wasm-decoder {:?}: {}", path, e);
+        let engine = Engine::default();
+        let module = Module::from_file(&engine, path).map_err(wasm_err)?;
+        let mut store = Store::new(&engine, ());
+        let instance = Instance::new(&mut store, &module, &[]).map_err(wasm_err)?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| format!("--wasm-decoder {:?} does not export `memory`", path))?;
+        let alloc = instance.get_typed_func(&mut store, "alloc").map_err(wasm_err)?;
+        let decode = instance.get_typed_func(&mut store, "decode").map_err(wasm_err)?;
+        Ok(Self { store, memory, alloc, decode })
+    }
+
+    /// Run the module's `decode` on one payload
+    fn decode(&mut self, payload: &[u8]) -> Result<Option<Map<String, Value>>> {
+        let wasm_err = |e: wasmtime::Error| format!("--wasm-decoder trapped: {}", e);
+        let len = i32::try_from(payload.len()).map_err(|_| "Payload too large for the WASM decoder")?;
+        let ptr = self.alloc.call(&mut self.store, len).map_err(wasm_err)?;
+        self.memory
+            .write(&mut self.store, ptr as u32 as usize, payload)
+            .map_err(|e| format!("--wasm-decoder returned an out-of-bounds buffer: {}", e))?;
+        let packed = self.decode.call(&mut self.store, (ptr, len)).map_err(wasm_err)? as u64;
+        let (out_ptr, out_len) = ((packed >> 32) as usize, (packed & 0xFFFF_FFFF) as usize);
+        if out_len == 0 {
+            return Ok(None);
+        }
+        let mut json = vec![0; out_len];
+        self.memory
+            .read(&self.store, out_ptr, &mut json)
+            .map_err(|e| format!("--wasm-decoder returned an out-of-bounds result: {}", e))?;
+        match serde_json::from_slice(&json)? {
+            Value::Object(fields) => Ok(Some(fields)),
+            other => Err(format!("--wasm-decoder must return a JSON object, got {}", other).into()),
+        }
+    }
+}
+
+/// Decode every KM003C payload of `df` and append the results as `wasm_*` columns
+pub(crate) fn add_wasm_columns(df: DataFrame, decoder: &mut WasmDecoder) -> Result<DataFrame> {
+    let km_rows = df.column("km_packet_type")?.is_not_null();
+    let payloads = df.column("payload_hex")?.str()?;
+    let mut decoded = Vec::with_capacity(df.height());
+    for (is_km, payload) in km_rows.into_iter().zip(payloads) {
+        let bytes = match (is_km, payload) {
+            (Some(true), Some(hex_payload)) => hex::decode(hex_payload).ok(),
+            _ => None,
+        };
+        decoded.push(match bytes {
+            Some(bytes) => decoder.decode(&bytes)?,
+            None => None,
+        });
+    }
+    let mut df = df;
+    for column in json_columns(&decoded) {
+        df.with_column(column)?;
+    }
+    Ok(df)
+}
+
+/// One column per JSON key: Float64 when every value is a number, Boolean when every
+/// value is a bool, otherwise String (non-string values keep their JSON text)
+fn json_columns(rows: &[Option<Map<String, Value>>]) -> Vec<Column> {
+    let keys: BTreeSet<&String> = rows.iter().flatten().flat_map(|fields| fields.keys()).collect();
+    keys.into_iter()
+        .map(|key| {
+            let name = PlSmallStr::from(format!("wasm_{}", key));
+            let values: Vec<Option<&Value>> =
+                rows.iter().map(|row| row.as_ref().and_then(|fields| fields.get(key)).filter(|v| !v.is_null())).collect();
+            if values.iter().flatten().all(|v| v.is_number()) {
+                Column::new(name, values.iter().map(|v| v.and_then(Value::as_f64)).collect::<Vec<_>>())
+            } else if values.iter().flatten().all(|v| v.is_boolean()) {
+                Column::new(name, values.iter().map(|v| v.and_then(Value::as_bool)).collect::<Vec<_>>())
+            } else {
+                let text = |v: &Value| v.as_str().map_or_else(|| v.to_string(), str::to_string);
+                Column::new(name, values.iter().map(|v| v.map(text)).collect::<Vec<_>>())
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reports the last digit of the first byte and of the length for payloads of 4+ bytes
+    const DIGITS_DECODER: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (data (i32.const 0) "{\"first\":0,\"len\":0}")
+          (global $next (mut i32) (i32.const 1024))
+          (func (export "alloc") (param $len i32) (result i32)
+            (local $ptr i32)
+            (local.set $ptr (global.get $next))
+            (global.set $next (i32.add (global.get $next) (local.get $len)))
+            (local.get $ptr))
+          (func (export "decode") (param $ptr i32) (param $len i32) (result i64)
+            (if (i32.lt_u (local.get $len) (i32.const 4)) (then (return (i64.const 0))))
+            (i32.store8 (i32.const 9) (i32.add (i32.const 48) (i32.rem_u (i32.load8_u (local.get $ptr)) (i32.const 10))))
+            (i32.store8 (i32.const 17) (i32.add (i32.const 48) (i32.rem_u (local.get $len) (i32.const 10))))
+            (i64.const 19)))
+    "#;
+
+    #[test]
+    fn json_values_become_typed_columns() {
+        let row = |json: &str| serde_json::from_str::<Map<String, Value>>(json).ok();
+        let columns = json_columns(&[row(r#"{"mode":"fast","ok":true,"v":1.5}"#), None, row(r#"{"mode":2,"v":null}"#)]);
+        let names: Vec<&str> = columns.iter().map(|c| c.name().as_str()).collect();
+        assert_eq!(names, ["wasm_mode", "wasm_ok", "wasm_v"]);
+        assert_eq!(columns[0].str().unwrap().get(2), Some("2"));
+        assert_eq!(columns[1].bool().unwrap().get(0), Some(true));
+        assert_eq!(columns[2].f64().unwrap().into_iter().collect::<Vec<_>>(), [Some(1.5), None, None]);
+    }
+
+    #[test]
+    fn runs_the_module_on_km003c_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("digits.wat");
+        std::fs::write(&path, DIGITS_DECODER).unwrap();
+        let mut decoder = WasmDecoder::load(&path).unwrap();
+
+        let df = df![
+            "km_packet_type" => [Some(0x0Cu32), None, Some(0x41)],
+            "payload_hex" => ["0c0a0200", "0c0a0200", "41"],
+        ]
+        .unwrap();
+        let df = add_wasm_columns(df, &mut decoder).unwrap();
+        let first: Vec<Option<f64>> = df.column("wasm_first").unwrap().f64().unwrap().into_iter().collect();
+        assert_eq!(first, [Some(2.0), None, None]);
+        assert_eq!(df.column("wasm_len").unwrap().f64().unwrap().get(0), Some(4.0));
+    }
+}
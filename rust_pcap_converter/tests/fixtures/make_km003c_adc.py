@@ -0,0 +1,55 @@
+"""Write km003c_adc.pcapng: a minimal usbmon capture of one KM003C ADC GetData exchange.
+
+The fixture is synthetic so it stays tiny and free of host details. Re-run with
+`python3 make_km003c_adc.py` from this directory if the packet layout needs to change.
+"""
+
+import struct
+
+DEVICE, BUS = 5, 1
+LINKTYPE_USB_LINUX_MMAPPED = 220
+
+
+def block(block_type: int, body: bytes) -> bytes:
+    body += b"\0" * (-len(body) % 4)
+    length = len(body) + 12
+    return struct.pack("<II", block_type, length) + body + struct.pack("<I", length)
+
+
+def usbmon(urb_id, urb_type, endpoint, ts_usec, data=b"", urb_len=None, status=0):
+    flag_data = 0 if data else ord("<" if endpoint & 0x80 else ">")
+    header = struct.pack(
+        "<QBBBBHbbqiiII8siiII",
+        urb_id, ord(urb_type), 3, endpoint, DEVICE, BUS, ord("-"), flag_data,
+        1_700_000_000, ts_usec, status, len(data) if urb_len is None else urb_len, len(data),
+        b"\0" * 8, 0, 0, 0, 0,
+    )
+    return header + data
+
+
+def packet(frame: bytes, ts_usec: int, comment: str | None = None) -> bytes:
+    ts = 1_700_000_000 * 1_000_000 + ts_usec
+    body = struct.pack("<IIIII", 0, ts >> 32, ts & 0xFFFFFFFF, len(frame), len(frame)) + frame
+    body += b"\0" * (-len(body) % 4)
+    if comment:
+        text = comment.encode()
+        body += struct.pack("<HH", 1, len(text)) + text + b"\0" * (-len(text) % 4)
+        body += struct.pack("<HH", 0, 0)
+    return block(6, body)
+
+
+get_adc = bytes([0x0C, 0x01, 0x02, 0x00])
+adc_body = struct.pack("<ii", 5_000_000, 1_250_000) + b"\0" * 36
+put_data = struct.pack("<I", 0x41 | 0x01 << 8 | 12 << 22) + struct.pack("<I", 0x0001 | 44 << 22) + adc_body
+
+capture = b"".join([
+    block(0x0A0D0D0A, struct.pack("<IHHq", 0x1A2B3C4D, 1, 0, -1)),
+    block(1, struct.pack("<HHI", LINKTYPE_USB_LINUX_MMAPPED, 0, 0)),
+    packet(usbmon(0x1000, "S", 0x01, 0, get_adc), 0, "GetData ADC"),
+    packet(usbmon(0x1000, "C", 0x01, 120, urb_len=4), 120),
+    packet(usbmon(0x2000, "S", 0x81, 150, urb_len=64), 150),
+    packet(usbmon(0x2000, "C", 0x81, 980, put_data), 980),
+])
+
+with open("km003c_adc.pcapng", "wb") as f:
+    f.write(capture)
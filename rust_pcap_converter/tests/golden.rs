@@ -0,0 +1,119 @@
+//! Golden-file round trip: the committed fixture capture must always convert to the
+//! committed Parquet, column by column (`added_datetime` is ignored).
+//!
+//! The golden file is produced with the native parser, so the check needs no `tshark`.
+//! After an intentional schema or decoding change, regenerate it with
+//! `UPDATE_GOLDEN=1 cargo test --test golden` and commit it.
+
+use pcap_to_parquet::config::ParserKind;
+use pcap_to_parquet::{convert, convert_bytes, ConverterConfig};
+use polars::prelude::*;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+}
+
+fn tshark_available() -> bool {
+    Command::new("tshark").arg("--version").output().is_ok_and(|o| o.status.success())
+}
+
+/// Read a converted file with volatile columns dropped and Categoricals as plain strings
+fn read_comparable(path: &Path) -> DataFrame {
+    let df = ParquetReader::new(File::open(path).unwrap()).finish().unwrap();
+    let mut df = df.drop("added_datetime").unwrap();
+    for column in df.get_columns().to_vec() {
+        if matches!(column.dtype(), DataType::Categorical(_, _)) {
+            df.with_column(column.cast(&DataType::String).unwrap()).unwrap();
+        }
+    }
+    df
+}
+
+#[test]
+fn fixture_converts_to_golden_parquet() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("km003c_adc.parquet");
+    // Relative to the package root (cargo's working directory for tests), so the committed
+    // file's source_file footer holds no machine-specific path
+    let input = Path::new("tests/fixtures/km003c_adc.pcapng");
+    let config = ConverterConfig::new(input).output(&output).device_address(5).parser(ParserKind::Native);
+    convert(&config).unwrap();
+
+    let golden = fixture("km003c_adc.expected.parquet");
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::copy(&output, &golden).unwrap();
+        eprintln!("Wrote {:?}; commit it if the change is intended", golden);
+        return;
+    }
+    assert!(golden.exists(), "{:?} is missing; generate it with UPDATE_GOLDEN=1 and commit it", golden);
+
+    let actual = read_comparable(&output);
+    let expected = read_comparable(&golden);
+    assert_eq!(actual.get_column_names(), expected.get_column_names(), "column set or order changed");
+    assert_eq!(actual.height(), expected.height(), "row count changed");
+    for column in expected.get_columns() {
+        let name = column.name();
+        assert_eq!(actual.column(name).unwrap().dtype(), column.dtype(), "dtype of {} changed", name);
+        assert!(
+            actual.column(name).unwrap().as_materialized_series().equals_missing(column.as_materialized_series()),
+            "values of {} changed:\nactual:   {:?}\nexpected: {:?}",
+            name,
+            actual.column(name).unwrap(),
+            column
+        );
+    }
+}
+
+#[test]
+fn convert_bytes_matches_file_conversion() {
+    if !tshark_available() {
+        eprintln!("tshark not found; skipping in-memory conversion test");
+        return;
+    }
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("km003c_adc.parquet");
+    let config = ConverterConfig::new(fixture("km003c_adc.pcapng")).output(&output).device_address(5).session_id("km003c_adc");
+    convert(&config).unwrap();
+
+    let in_memory = convert_bytes(&std::fs::read(fixture("km003c_adc.pcapng")).unwrap(), &config).unwrap();
+    let written = read_comparable(&output);
+    assert_eq!(in_memory.height(), written.height());
+    assert!(in_memory.column("frame_number").unwrap().as_materialized_series().equals(written.column("frame_number").unwrap().as_materialized_series()));
+}
+
+/// Columns both parsers must agree on; class and descriptor fields are tshark-only
+const CORE_COLUMNS: [&str; 10] = [
+    "frame_number", "timestamp", "direction", "device_address", "endpoint_address", "transfer_type", "urb_type", "data_length", "payload_hex", "adc_vbus_v",
+];
+
+#[test]
+fn native_parser_converts_fixture_without_tshark() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("native.parquet");
+    // No device address: the native parser also scans for the busiest device
+    convert(&ConverterConfig::new(fixture("km003c_adc.pcapng")).output(&output).parser(ParserKind::Native)).unwrap();
+    let native = read_comparable(&output);
+
+    assert_eq!(native.height(), 4);
+    assert_eq!(native.column("frame_comment").unwrap().str().unwrap().get(0), Some("GetData ADC"));
+    assert_eq!(native.column("adc_vbus_v").unwrap().f64().unwrap().get(3), Some(5.0));
+    assert_eq!(native.column("timestamp").unwrap().f64().unwrap().get(3), Some(0.00098));
+
+    if !tshark_available() {
+        eprintln!("tshark not found; skipping comparison with the tshark parser");
+        return;
+    }
+    let tshark_output = dir.path().join("tshark.parquet");
+    convert(&ConverterConfig::new(fixture("km003c_adc.pcapng")).output(&tshark_output).device_address(5)).unwrap();
+    let tshark = read_comparable(&tshark_output);
+    for name in CORE_COLUMNS {
+        assert!(
+            native.column(name).unwrap().as_materialized_series().equals_missing(tshark.column(name).unwrap().as_materialized_series()),
+            "{} differs between parsers",
+            name
+        );
+    }
+}